@@ -0,0 +1,203 @@
+//! ITM/DWT trace packets (ARMv7-M ARM, Appendix D4)
+use core::fmt::Display;
+
+use crate::Timestamp;
+
+/// Packet decoded from the SWO stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TracePacket {
+    pub ts: Option<Timestamp>,
+    pub packet: Packet,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Packet {
+    Sync,
+    Overflow,
+    /// Delta since the previous local timestamp, in trace clock cycles
+    LocalTimestamp {
+        /// Relation to the associated source packet (`TC` field)
+        tc: u8,
+        delta: u32,
+    },
+    /// Bits `[25:0]` of the global timestamp
+    GlobalTimestamp1 {
+        value: u32,
+        wrap: bool,
+        clock_change: bool,
+    },
+    /// Bits `[63:26]` of the global timestamp
+    GlobalTimestamp2 {
+        value: u64,
+    },
+    Extension {
+        /// `SH` bit, false means stimulus port page
+        hardware: bool,
+        value: u32,
+    },
+    /// Software source packet, written by the firmware to ITM stimulus port
+    Instrumentation {
+        port: u8,
+        payload: Payload,
+    },
+    EventCounter {
+        /// `CPI`, `Exc`, `Sleep`, `LSU`, `Fold`, `Cyc` counter wrap flags
+        flags: u8,
+    },
+    ExceptionTrace {
+        number: u16,
+        function: ExceptionFunction,
+    },
+    /// `None` if the core was sleeping
+    PcSample {
+        pc: Option<u32>,
+    },
+    DataTracePc {
+        comparator: u8,
+        pc: u32,
+    },
+    DataTraceAddress {
+        comparator: u8,
+        offset: u16,
+    },
+    DataTraceValue {
+        comparator: u8,
+        write: bool,
+        payload: Payload,
+    },
+    /// Hardware source packet with an unassigned discriminator
+    Hardware {
+        discriminator: u8,
+        payload: Payload,
+    },
+    /// Header that does not match any known packet
+    Reserved(u8),
+}
+
+/// Source packet payload, 1, 2 or 4 bytes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Payload {
+    Byte(u8),
+    Halfword(u16),
+    Word(u32),
+}
+
+impl Payload {
+    pub fn as_(&self) -> u32 {
+        match *self {
+            Payload::Byte(v) => v as _,
+            Payload::Halfword(v) => v as _,
+            Payload::Word(v) => v,
+        }
+    }
+}
+
+impl Display for Payload {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Payload::Byte(v) => write!(f, "{v:#04x}"),
+            Payload::Halfword(v) => write!(f, "{v:#06x}"),
+            Payload::Word(v) => write!(f, "{v:#010x}"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExceptionFunction {
+    Entered,
+    Exited,
+    Returned,
+    Reserved,
+}
+
+impl Display for Packet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Packet::Sync => write!(f, "SYNC"),
+            Packet::Overflow => write!(f, "OVERFLOW"),
+            Packet::LocalTimestamp { tc, delta } => write!(f, "LTS +{delta} (TC={tc})"),
+            Packet::GlobalTimestamp1 {
+                value,
+                wrap,
+                clock_change,
+            } => {
+                write!(f, "GTS1 {value:#x}")?;
+                if *wrap {
+                    write!(f, " wrap")?;
+                }
+                if *clock_change {
+                    write!(f, " clkch")?;
+                }
+                Ok(())
+            }
+            Packet::GlobalTimestamp2 { value } => write!(f, "GTS2 {value:#x}"),
+            Packet::Extension {
+                hardware: false,
+                value,
+            } => write!(f, "EXT page={value}"),
+            Packet::Extension {
+                hardware: true,
+                value,
+            } => write!(f, "EXT hw={value:#x}"),
+            Packet::Instrumentation { port, payload } => {
+                write!(f, "STIM[{port}] {payload}")?;
+                let bytes = payload.as_().to_le_bytes();
+                let len = match payload {
+                    Payload::Byte(_) => 1,
+                    Payload::Halfword(_) => 2,
+                    Payload::Word(_) => 4,
+                };
+                let text = &bytes[..len];
+                if text
+                    .iter()
+                    .all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+                {
+                    write!(f, " {:?}", String::from_utf8_lossy(text))?;
+                }
+                Ok(())
+            }
+            Packet::EventCounter { flags } => {
+                write!(f, "DWT counter wrap:")?;
+                for (bit, name) in ["CPI", "Exc", "Sleep", "LSU", "Fold", "Cyc"]
+                    .iter()
+                    .enumerate()
+                {
+                    if flags & (1 << bit) != 0 {
+                        write!(f, " {name}")?;
+                    }
+                }
+                Ok(())
+            }
+            Packet::ExceptionTrace { number, function } => {
+                let function = match function {
+                    ExceptionFunction::Entered => "enter",
+                    ExceptionFunction::Exited => "exit",
+                    ExceptionFunction::Returned => "return",
+                    ExceptionFunction::Reserved => "???",
+                };
+                write!(f, "DWT exception {function} {number}")
+            }
+            Packet::PcSample { pc: Some(pc) } => write!(f, "DWT PC sample {pc:#010x}"),
+            Packet::PcSample { pc: None } => write!(f, "DWT PC sample (sleeping)"),
+            Packet::DataTracePc { comparator, pc } => {
+                write!(f, "DWT comp[{comparator}] PC {pc:#010x}")
+            }
+            Packet::DataTraceAddress { comparator, offset } => {
+                write!(f, "DWT comp[{comparator}] address offset {offset:#06x}")
+            }
+            Packet::DataTraceValue {
+                comparator,
+                write,
+                payload,
+            } => {
+                let rw = if *write { "W" } else { "R" };
+                write!(f, "DWT comp[{comparator}] {rw} {payload}")
+            }
+            Packet::Hardware {
+                discriminator,
+                payload,
+            } => write!(f, "DWT[{discriminator}] {payload}"),
+            Packet::Reserved(header) => write!(f, "reserved header {header:#04x}"),
+        }
+    }
+}
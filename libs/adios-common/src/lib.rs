@@ -1,5 +1,8 @@
 use bilge::prelude::*;
 
+pub mod itm;
+pub mod swd;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Timestamp {
     pub start: u64,
@@ -23,6 +26,28 @@ impl From<Command> for Input {
     }
 }
 
+/// Transfer the target did not complete, answered with something else than OK
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// From the first attempt to the final answer
+    pub ts: Option<Timestamp>,
+    pub apndp: bool,
+    pub rnw: bool,
+    /// 2nd and 3rd bit
+    /// Real `a` would be `Self::a << 2`
+    pub a: u2,
+    /// Final answer, WAIT if retrying was given up on
+    pub ack: swd::Ack,
+    /// Attempts after the first one, if known
+    pub retries: Option<u32>,
+}
+
+impl From<Failure> for Input {
+    fn from(value: Failure) -> Self {
+        Self::Failure(value)
+    }
+}
+
 /// API boundary between input parsers and the ADI VM
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Input {
@@ -30,10 +55,89 @@ pub enum Input {
     Landmark(String),
     /// Actual command pushing the VM forward
     Command(Command),
+    /// ITM/DWT packet captured on SWO, passed through the VM as-is
+    Trace(itm::TracePacket),
+    /// Failed transfer, does not change the VM state
+    Failure(Failure),
+    /// Wire-level SWD traffic, resolved into commands by the VM
+    Swd(swd::Event),
+}
+
+impl From<itm::TracePacket> for Input {
+    fn from(value: itm::TracePacket) -> Self {
+        Self::Trace(value)
+    }
 }
 
 impl Input {
     pub fn landmark(s: impl Into<String>) -> Self {
         Self::Landmark(s.into())
     }
+
+    pub fn ts(&self) -> Option<Timestamp> {
+        match self {
+            Input::Landmark(_) => None,
+            Input::Command(command) => command.ts,
+            Input::Trace(packet) => packet.ts,
+            Input::Failure(failure) => failure.ts,
+            Input::Swd(event) => event.ts(),
+        }
+    }
+}
+
+/// Merge two input streams captured on the same sample-number timeline
+///
+/// Order within each stream is preserved. Inputs without a timestamp stay
+/// glued to whatever preceded them in their own stream.
+pub fn interleave(primary: Vec<Input>, secondary: Vec<Input>) -> Vec<Input> {
+    let mut merged = Vec::with_capacity(primary.len() + secondary.len());
+    let mut secondary = secondary.into_iter().peekable();
+    for input in primary {
+        if let Some(ts) = input.ts() {
+            while let Some(next) = secondary.next_if(|v| v.ts().is_none_or(|v| v.start <= ts.start))
+            {
+                merged.push(next);
+            }
+        }
+        merged.push(input);
+    }
+    merged.extend(secondary);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(start: u64) -> Input {
+        itm::TracePacket {
+            ts: Some(Timestamp { start, end: start }),
+            packet: itm::Packet::Sync,
+        }
+        .into()
+    }
+
+    fn command(start: u64) -> Input {
+        Command {
+            ts: Some(Timestamp { start, end: start }),
+            apndp: false,
+            rnw: true,
+            a: u2::new(0),
+            data: 0,
+        }
+        .into()
+    }
+
+    #[test]
+    fn interleave_by_sample_number() {
+        let merged = interleave(
+            vec![command(10), Input::landmark("x"), command(30)],
+            vec![trace(5), trace(20), trace(40)],
+        );
+        let starts: Vec<_> = merged.iter().map(|v| v.ts().map(|v| v.start)).collect();
+        assert_eq!(
+            starts,
+            [Some(5), Some(10), None, Some(20), Some(30), Some(40)]
+        );
+    }
 }
@@ -0,0 +1,378 @@
+//! Wire-level SWD transfers and their resolution into [`Command`]s
+//!
+//! Importers seeing the raw SWD traffic (e.g. a logic analyzer) emit [`Event`]s as they
+//! are, [`Resolver`] takes care of the protocol details so that every such source
+//! resolves them the same way:
+//! - AP reads are posted, the result arrives with the next AP read or RDBUFF, B4.2.5, IHI0031G.
+//!   Whatever comes in the meantime is held back, so that the read is still seen in the
+//!   context (e.g. SELECT) it was requested in
+//! - WAIT is answered by retrying the very same request, giving up shows as a [`Failure`]
+//! - FAULT means the transfer did not happen at all, it shows as a [`Failure`]
+//! - RESEND repeats the data of the last AP read or RDBUFF
+use super::*;
+
+/// Acknowledgement of an SWD packet request
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ack {
+    Ok,
+    Wait,
+    Fault,
+    /// Nobody drove the line, e.g. the target is not there or lost the protocol
+    NoAck,
+}
+
+impl core::fmt::Display for Ack {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Ack::Ok => "OK",
+            Ack::Wait => "WAIT",
+            Ack::Fault => "FAULT",
+            Ack::NoAck => "NO ACK",
+        })
+    }
+}
+
+/// A single SWD packet as seen on the wire
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub ts: Option<Timestamp>,
+    pub apndp: bool,
+    pub rnw: bool,
+    /// 2nd and 3rd bit
+    /// Real `a` would be `Self::a << 2`
+    pub a: u2,
+    pub ack: Ack,
+    /// Data phase, only present when acknowledged with OK
+    pub data: Option<u32>,
+}
+
+impl Packet {
+    fn same_request(&self, other: &Self) -> bool {
+        (self.apndp, self.rnw, self.a) == (other.apndp, other.rnw, other.a)
+    }
+
+    fn is_ap_read(&self) -> bool {
+        self.apndp && self.rnw
+    }
+
+    fn is_dp_read(&self, a: u8) -> bool {
+        !self.apndp && self.rnw && self.a.value() << 2 == a
+    }
+
+    fn failure(&self, retries: u32) -> Failure {
+        Failure {
+            ts: self.ts,
+            apndp: self.apndp,
+            rnw: self.rnw,
+            a: self.a,
+            ack: self.ack,
+            retries: Some(retries),
+        }
+    }
+
+    fn command(&self, ts: Option<Timestamp>, data: u32) -> Command {
+        Command {
+            ts,
+            apndp: self.apndp,
+            rnw: self.rnw,
+            a: self.a,
+            data,
+        }
+    }
+}
+
+impl core::fmt::Display for Packet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let rw = if self.rnw { "R" } else { "W" };
+        let port = if self.apndp { "AP" } else { "DP" };
+        write!(f, "{rw} {port} {:#x}", self.a.value() << 2)
+    }
+}
+
+/// Wire-level input of [`Resolver`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Packet(Packet),
+    /// Line reset sequence, abandons whatever was being retried
+    LineReset {
+        ts: Option<Timestamp>,
+    },
+}
+
+impl Event {
+    pub fn ts(&self) -> Option<Timestamp> {
+        match self {
+            Event::Packet(packet) => packet.ts,
+            Event::LineReset { ts } => *ts,
+        }
+    }
+}
+
+impl From<Packet> for Event {
+    fn from(value: Packet) -> Self {
+        Self::Packet(value)
+    }
+}
+
+impl From<Event> for Input {
+    fn from(value: Event) -> Self {
+        Self::Swd(value)
+    }
+}
+
+/// Turns [`Event`]s into [`Input`]s, one event at a time
+#[derive(Clone, Debug, Default)]
+pub struct Resolver {
+    /// AP read waiting for its result
+    posted: Option<Packet>,
+    /// Request answered with WAIT so far, timestamped from its first attempt, and its retries
+    waiting: Option<(Packet, u32)>,
+    /// Data of the last AP read or RDBUFF, what RESEND returns
+    last_read: Option<u32>,
+    /// Inputs following the posted AP read, released once it is resolved
+    held: Vec<Input>,
+}
+
+impl Resolver {
+    pub fn push(&mut self, event: Event) -> Vec<Input> {
+        let posted = self.posted;
+        let mut resolved = None;
+        let inputs = self.resolve(event, &mut resolved);
+        let mut released = Vec::new();
+        if let Some(resolved) = resolved {
+            released.push(resolved);
+            released.append(&mut self.held);
+        }
+        match (posted, self.posted) {
+            // Inputs preceding the request of a newly posted read
+            (_, Some(current)) if posted != Some(current) => released.extend(inputs),
+            (_, Some(_)) => self.held.extend(inputs),
+            (_, None) => {
+                released.append(&mut self.held);
+                released.extend(inputs);
+            }
+        }
+        released
+    }
+
+    /// Inputs `event` results in, the command of a posted read it delivers goes to `resolved`
+    fn resolve(&mut self, event: Event, resolved: &mut Option<Input>) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        let mut packet = match event {
+            Event::Packet(packet) => packet,
+            Event::LineReset { .. } => {
+                inputs.extend(self.abandon_wait());
+                if let Some(posted) = self.posted.take() {
+                    log::warn!("Line reset while {posted} result was pending, result is lost");
+                }
+                return inputs;
+            }
+        };
+
+        let mut retries = 0;
+        if let Some((first, waits)) = self.waiting.take() {
+            if first.same_request(&packet) {
+                packet.ts = match (first.ts, packet.ts) {
+                    (Some(first), Some(retry)) => Some(Timestamp {
+                        start: first.start,
+                        end: retry.end,
+                    }),
+                    (first, retry) => retry.or(first),
+                };
+                retries = waits + 1;
+            } else {
+                self.waiting = Some((first, waits));
+                inputs.extend(self.abandon_wait());
+            }
+        }
+
+        let data = match (packet.ack, packet.data) {
+            (Ack::Wait, _) => {
+                self.waiting = Some((packet, retries));
+                return inputs;
+            }
+            (Ack::Fault | Ack::NoAck, _) => {
+                inputs.push(packet.failure(retries).into());
+                return inputs;
+            }
+            (Ack::Ok, None) => {
+                log::error!("{packet} acknowledged with OK but without data, ignoring");
+                return inputs;
+            }
+            (Ack::Ok, Some(data)) => data,
+        };
+
+        if packet.is_ap_read() || packet.is_dp_read(0xc) {
+            let posted = if packet.is_ap_read() {
+                self.posted.replace(packet)
+            } else {
+                self.posted.take()
+            };
+            match posted {
+                Some(posted) => {
+                    let ts = match (posted.ts, packet.ts) {
+                        (Some(posted), Some(packet)) => Some(Timestamp {
+                            start: posted.start,
+                            end: packet.end,
+                        }),
+                        (posted, packet) => packet.or(posted),
+                    };
+                    self.last_read = Some(data);
+                    *resolved = Some(posted.command(ts, data).into());
+                }
+                // RDBUFF is relevant only as a result of an AP read
+                None if !packet.is_ap_read() => {
+                    log::debug!("Unsolicited RDBUFF: {data:#x}");
+                }
+                None => {}
+            }
+        } else if packet.is_dp_read(0x8) {
+            match self.last_read {
+                Some(last_read) if last_read != data => inputs.push(Input::Landmark(format!(
+                    "RESEND returned {data:#x}, last read result was {last_read:#x}"
+                ))),
+                None => log::debug!("RESEND with no previous read: {data:#x}"),
+                _ => {}
+            }
+            inputs.push(packet.command(packet.ts, data).into());
+        } else {
+            inputs.push(packet.command(packet.ts, data).into());
+        }
+        inputs
+    }
+
+    /// Flush whatever is still pending at the end of the input
+    pub fn finish(&mut self) -> Vec<Input> {
+        if let Some(posted) = self.posted.take() {
+            log::warn!("{posted} result was never read out");
+        }
+        let mut inputs = std::mem::take(&mut self.held);
+        inputs.extend(self.abandon_wait());
+        inputs
+    }
+
+    fn abandon_wait(&mut self) -> Option<Input> {
+        self.waiting
+            .take()
+            .map(|(packet, retries)| packet.failure(retries).into())
+    }
+}
+
+/// Resolve a whole wire-level capture at once
+pub fn resolve(events: impl IntoIterator<Item = Event>) -> Vec<Input> {
+    let mut resolver = Resolver::default();
+    let mut inputs: Vec<_> = events
+        .into_iter()
+        .flat_map(|event| resolver.push(event))
+        .collect();
+    inputs.extend(resolver.finish());
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(start: u64, apndp: bool, rnw: bool, a: u8, ack: Ack, data: Option<u32>) -> Event {
+        Packet {
+            ts: Some(Timestamp { start, end: start }),
+            apndp,
+            rnw,
+            a: u2::new(a),
+            ack,
+            data,
+        }
+        .into()
+    }
+
+    #[test]
+    fn posted_read_across_dp_accesses() {
+        let inputs = resolve([
+            packet(1, true, true, 3, Ack::Ok, Some(0xffff_ffff)),
+            packet(2, false, true, 1, Ack::Ok, Some(0xf000_0000)),
+            packet(3, false, true, 3, Ack::Ok, Some(0x1234)),
+            packet(4, false, true, 2, Ack::Ok, Some(0x4321)),
+        ]);
+        assert_eq!(inputs.len(), 4);
+        // The DP read is held back until the AP read it follows is resolved
+        assert_eq!(
+            inputs[0],
+            Input::Command(Command {
+                ts: Some(Timestamp { start: 1, end: 3 }),
+                apndp: true,
+                rnw: true,
+                a: u2::new(3),
+                data: 0x1234,
+            })
+        );
+        assert!(matches!(inputs[1], Input::Command(Command { a, .. }) if a.value() == 1));
+        // RESEND disagreeing with the read it repeats
+        assert!(matches!(inputs[2], Input::Landmark(_)));
+        assert!(matches!(
+            inputs[3],
+            Input::Command(Command { data: 0x4321, .. })
+        ));
+    }
+
+    #[test]
+    fn select_between_read_and_rdbuff() {
+        let inputs = resolve([
+            // CSW of AP[0]
+            packet(1, true, true, 0, Ack::Ok, Some(0xffff_ffff)),
+            // SELECT, APSEL=1
+            packet(2, false, false, 2, Ack::Ok, Some(0x0100_0000)),
+            packet(3, false, true, 3, Ack::Ok, Some(0x2300_0012)),
+        ]);
+        assert_eq!(inputs.len(), 2);
+        assert!(matches!(
+            inputs[0],
+            Input::Command(Command {
+                apndp: true,
+                data: 0x2300_0012,
+                ..
+            })
+        ));
+        assert!(matches!(
+            inputs[1],
+            Input::Command(Command {
+                apndp: false,
+                data: 0x0100_0000,
+                ..
+            })
+        ));
+
+        // Never read out, held inputs still come out at the end
+        let inputs = resolve([
+            packet(1, true, true, 0, Ack::Ok, Some(0xffff_ffff)),
+            packet(2, false, false, 2, Ack::Ok, Some(0x0100_0000)),
+        ]);
+        assert!(matches!(
+            inputs[..],
+            [Input::Command(Command { apndp: false, .. })]
+        ));
+    }
+
+    #[test]
+    fn wait_then_fault() {
+        let inputs = resolve([
+            packet(1, true, false, 1, Ack::Wait, None),
+            packet(2, true, false, 1, Ack::Wait, None),
+            packet(3, true, false, 1, Ack::Fault, None),
+            packet(4, true, false, 1, Ack::Wait, None),
+        ]);
+        let failure = |start, end, ack, retries| {
+            Input::Failure(Failure {
+                ts: Some(Timestamp { start, end }),
+                apndp: true,
+                rnw: false,
+                a: u2::new(1),
+                ack,
+                retries: Some(retries),
+            })
+        };
+        assert_eq!(
+            inputs,
+            [failure(1, 3, Ack::Fault, 2), failure(4, 4, Ack::Wait, 0)]
+        );
+    }
+}
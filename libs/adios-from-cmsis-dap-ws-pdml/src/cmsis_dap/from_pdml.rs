@@ -313,8 +313,8 @@ impl request::DapTransfer {
                     if field.name == "cmsis_dap.transfer.request" {
                         log::trace!("cmsis_dap.transfer.request: {}", field.show);
                         let value =
-                            u8::from_str_radix(&field.show.trim_start_matches("0x"), 16).unwrap();
-                        let request = request::DapTransferRequest::try_from(value).unwrap();
+                            u8::from_str_radix(field.show.trim_start_matches("0x"), 16).unwrap();
+                        let request = request::DapTransferRequest::from(value);
                         transfers.push(request::DapSingleTransfer {
                             request,
                             data: None,
@@ -381,8 +381,8 @@ impl request::DapTransferBlock {
             }
             if field.name == "cmsis_dap.transfer.request" {
                 log::trace!("cmsis_dap.transfer.request: {}", field.show);
-                let value = u8::from_str_radix(&field.show.trim_start_matches("0x"), 16).unwrap();
-                let value = request::DapTransferBlockRequest::try_from(value).unwrap();
+                let value = u8::from_str_radix(field.show.trim_start_matches("0x"), 16).unwrap();
+                let value = request::DapTransferBlockRequest::from(value);
                 assert!(
                     request.replace(value).is_none(),
                     "Field shows up more than once"
@@ -499,7 +499,7 @@ impl request::DapSwjSequence {
         let bit_data = bit_data
             .split(":")
             .map(|v| u8::from_str_radix(v, 16).unwrap())
-            .take((bit_count + 7) / 8)
+            .take(bit_count.div_ceil(8))
             .collect();
         Self {
             bit_count,
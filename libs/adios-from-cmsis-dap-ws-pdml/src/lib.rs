@@ -3,7 +3,7 @@ pub(crate) mod pdml;
 
 use std::{fmt::Display, io::BufRead};
 
-use adios_common::{Command, Input};
+use adios_common::{swd::Ack, Command, Failure, Input};
 use bilge::prelude::*;
 use cmsis_dap::{
     response::{DapResponseStatus, DapTransferResponseAck},
@@ -21,6 +21,8 @@ pub fn generate_vm_input(r: impl BufRead) -> Vec<Input> {
     }
 
     let mut adi_commands = Vec::new();
+    // Probe gives up on WAIT after this many retries, if DAP_TransferConfigure was captured
+    let mut wait_retry = None;
     let mut request_waiting: Option<AwaitingRequest> = None;
     for packet in pdml.packet.into_iter() {
         let Some(frame) = Frame::from_pdml_packet(&packet) else {
@@ -60,28 +62,30 @@ pub fn generate_vm_input(r: impl BufRead) -> Vec<Input> {
                     (Request::DapTransfer(req), Response::DapTransfer(res)) => {
                         log::info!("Request ({}): {:#0X?}", request.number, req);
                         log::info!("Response ({}): {:#0X?}", frame.number, res);
-                        if matches!(
-                            res.response.ack(),
-                            DapTransferResponseAck::Fault | DapTransferResponseAck::NoAck
-                        ) || res.response.protocol_error()
-                            || res.response.value_mismatch()
-                        {
+                        if res.response.protocol_error() || res.response.value_mismatch() {
                             log::warn!("Response ({}) is faulty, skipping", frame.number);
                             request_waiting = None;
                             continue;
                         }
-                        // If Ack.Ok then `req.transfer_count == res.transfer_count`
-                        // If Ack.Wait, then last valid transfer is `res.transfer_count - 1`
-                        let valid_transfers = match res.response.ack() {
-                            DapTransferResponseAck::Ok => res.transfer_count,
-                            DapTransferResponseAck::Wait => res.transfer_count.saturating_sub(1),
-                            _ => unreachable!(),
-                        };
+                        // Transfer Count is the number of transfers that went through, the
+                        // failed one (if any) follows them. AP reads are posted and count once
+                        // issued, their data comes with the next transfer or the RDBUFF read
+                        // at the end, which can be what failed (DAP_Transfer, CMSIS-DAP).
+                        let ack = failed_ack(res.response.ack());
+                        let completed = (res.transfer_count as usize).min(req.transfers.len());
                         let mut read_data_iter = res.data.iter();
-                        for index in 0..valid_transfers as usize {
-                            let transfer = &req.transfers[index];
+                        let mut read_lost = false;
+                        for transfer in &req.transfers[..completed] {
                             let data = if transfer.request.rnw() {
-                                *read_data_iter.next().unwrap()
+                                let Some(&data) = read_data_iter.next() else {
+                                    log::warn!(
+                                        "Response ({}) lacks the data of a posted read",
+                                        frame.number
+                                    );
+                                    read_lost = true;
+                                    continue;
+                                };
+                                data
                             } else {
                                 transfer.data.unwrap()
                             };
@@ -100,26 +104,41 @@ pub fn generate_vm_input(r: impl BufRead) -> Vec<Input> {
                                 .into(),
                             );
                         }
+                        if let Some(ack) = ack {
+                            let failed = req.transfers.get(completed).map(|v| &v.request);
+                            adi_commands.push(match failed {
+                                // An AP read fetches the posted data and posts its own at once
+                                Some(request)
+                                    if !read_lost || (request.apndp() && request.rnw()) =>
+                                {
+                                    failure(
+                                        request.apndp(),
+                                        request.rnw(),
+                                        request.a2(),
+                                        request.a3(),
+                                        ack,
+                                        wait_retry,
+                                    )
+                                }
+                                // RDBUFF reading the posted data or checking the last write
+                                _ => failure(false, true, true, true, ack, wait_retry),
+                            });
+                        }
                     }
                     (Request::DapTransferBlock(req), Response::DapTransferBlock(res)) => {
                         log::info!("Request ({}): {:#0X?}", request.number, req);
                         log::info!("Response ({}): {:#0X?}", frame.number, res);
-                        if matches!(
-                            res.response.ack(),
-                            DapTransferResponseAck::Fault | DapTransferResponseAck::NoAck
-                        ) || res.response.protocol_error()
-                        {
+                        if res.response.protocol_error() {
                             log::warn!("Response ({}) is faulty, skipping", frame.number);
                             request_waiting = None;
                             continue;
                         }
-                        // If Ack.Ok then `req.transfer_count == res.transfer_count`
-                        // If Ack.Wait, then last valid transfer is `res.transfer_count - 1`
-                        let valid_transfers = match res.response.ack() {
-                            DapTransferResponseAck::Ok => res.transfer_count,
-                            DapTransferResponseAck::Wait => res.transfer_count.saturating_sub(1),
-                            _ => unreachable!(),
-                        } as usize;
+                        // Transfer Count is the number of transfers that went through, the
+                        // failed one (if any) follows them. The last AP read and the check of
+                        // the writes are RDBUFF reads (DAP_TransferBlock, CMSIS-DAP).
+                        let ack = failed_ack(res.response.ack());
+                        let requested = req.transfer_count.get() as usize;
+                        let completed = (res.transfer_count as usize).min(requested);
                         let data_source = if req.request.rnw() {
                             res.data.iter().copied()
                         } else {
@@ -127,7 +146,7 @@ pub fn generate_vm_input(r: impl BufRead) -> Vec<Input> {
                         };
 
                         let a = u2::new(((req.request.a3() as u8) << 1) | (req.request.a2() as u8));
-                        for data in data_source.take(valid_transfers) {
+                        for data in data_source.take(completed) {
                             adi_commands.push(
                                 Command {
                                     ts: None,
@@ -139,6 +158,30 @@ pub fn generate_vm_input(r: impl BufRead) -> Vec<Input> {
                                 .into(),
                             );
                         }
+                        if let Some(ack) = ack {
+                            let rdbuff = completed == requested
+                                || (req.request.apndp()
+                                    && req.request.rnw()
+                                    && completed + 1 == requested);
+                            adi_commands.push(match rdbuff {
+                                true => failure(false, true, true, true, ack, wait_retry),
+                                false => failure(
+                                    req.request.apndp(),
+                                    req.request.rnw(),
+                                    req.request.a2(),
+                                    req.request.a3(),
+                                    ack,
+                                    wait_retry,
+                                ),
+                            });
+                        }
+                    }
+                    (Request::DapTransferConfigure(req), Response::DapTransferConfigure(res)) => {
+                        log::info!("Request ({}): {:#0X?}", request.number, req);
+                        log::info!("Response ({}): {:#0X?}", frame.number, res);
+                        if let DapResponseStatus::Ok = res.status {
+                            wait_retry = Some(req.wait_retry);
+                        }
                     }
                     (Request::DapWriteAbort(req), Response::DapWriteAbort(res)) => {
                         log::info!("Request ({}): {:#0X?}", request.number, req);
@@ -205,6 +248,32 @@ pub fn generate_vm_input(r: impl BufRead) -> Vec<Input> {
     adi_commands
 }
 
+/// `None` when the transfers went through
+fn failed_ack(ack: DapTransferResponseAck) -> Option<Ack> {
+    match ack {
+        DapTransferResponseAck::Ok => None,
+        DapTransferResponseAck::Wait => Some(Ack::Wait),
+        DapTransferResponseAck::Fault => Some(Ack::Fault),
+        DapTransferResponseAck::NoAck => Some(Ack::NoAck),
+    }
+}
+
+fn failure(apndp: bool, rnw: bool, a2: bool, a3: bool, ack: Ack, wait_retry: Option<u16>) -> Input {
+    Failure {
+        ts: None,
+        apndp,
+        rnw,
+        a: u2::new(((a3 as u8) << 1) | (a2 as u8)),
+        ack,
+        // The probe only retries on WAIT
+        retries: match ack {
+            Ack::Wait => wait_retry.map(u32::from),
+            _ => None,
+        },
+    }
+    .into()
+}
+
 pub struct UnknownCommandLandmark {
     pub header_byte: u8,
     pub request_data: Vec<u8>,
@@ -222,3 +291,229 @@ impl Display for UnknownCommandLandmark {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `<field>`s of `(name, show)` pairs
+    fn fields(fields: &[(&str, String)]) -> String {
+        fields
+            .iter()
+            .map(|(name, show)| format!(r#"<field name="{name}" show="{show}" size="1"/>"#))
+            .collect()
+    }
+
+    /// PDML packet of frame `number`, `fields` of the `usbdap` protocol
+    fn packet(number: usize, fields: &str) -> String {
+        format!(
+            r#"<packet>
+  <proto name="frame">
+    <field name="frame.number" show="{number}" size="0"/>
+    <field name="frame.time_epoch" show="0" size="0"/>
+  </proto>
+  <proto name="usbdap">{fields}</proto>
+</packet>"#
+        )
+    }
+
+    /// DAP_Transfer of `(request, write data)` transfers, answered with `ack` after `count`
+    fn transfer(
+        number: usize,
+        transfers: &[(u8, Option<u32>)],
+        count: u8,
+        ack: u8,
+        read: &[u32],
+    ) -> String {
+        let mut nested = Vec::new();
+        for &(request, data) in transfers {
+            nested.push(("cmsis_dap.transfer.request", format!("{request:#04x}")));
+            if let Some(data) = data {
+                nested.push(("cmsis_dap.transfer.write.data", data.to_string()));
+            }
+        }
+        let request = packet(
+            number,
+            &(fields(&[
+                ("cmsis_dap.response", (number + 1).to_string()),
+                ("cmsis_dap.command", "0x05".into()),
+                ("cmsis_dap.dap_index", "0".into()),
+                ("cmsis_dap.transfer.count", transfers.len().to_string()),
+            ]) + &format!(
+                r#"<field name="cmsis_dap.transfer" show="" size="1">{}</field>"#,
+                fields(&nested)
+            )),
+        );
+        let mut response = vec![
+            ("cmsis_dap.request", number.to_string()),
+            ("cmsis_dap.command", "0x05".into()),
+            ("cmsis_dap.transfer.count", count.to_string()),
+            ("cmsis_dap.transfer.response", format!("{ack:#04x}")),
+        ];
+        response.extend(
+            read.iter()
+                .map(|v| ("cmsis_dap.transfer.read.data", v.to_string())),
+        );
+        request + &packet(number + 1, &fields(&response))
+    }
+
+    fn transfer_configure(number: usize, wait_retry: u16) -> String {
+        packet(
+            number,
+            &fields(&[
+                ("cmsis_dap.response", (number + 1).to_string()),
+                ("cmsis_dap.command", "0x04".into()),
+                ("cmsis_dap.transfer_config.idle_cycles", "0".into()),
+                (
+                    "cmsis_dap.transfer_config.wait_retry",
+                    wait_retry.to_string(),
+                ),
+                ("cmsis_dap.transfer_config.match_retry", "0".into()),
+            ]),
+        ) + &packet(
+            number + 1,
+            &fields(&[
+                ("cmsis_dap.request", number.to_string()),
+                ("cmsis_dap.command", "0x04".into()),
+                ("cmsis_dap.status", "0x00".into()),
+            ]),
+        )
+    }
+
+    fn input(packets: &[String]) -> Vec<Input> {
+        let pdml = format!("<pdml>{}</pdml>", packets.concat());
+        generate_vm_input(pdml.as_bytes())
+    }
+
+    fn command(apndp: bool, rnw: bool, a: u8, data: u32) -> Input {
+        Command {
+            ts: None,
+            apndp,
+            rnw,
+            a: u2::new(a),
+            data,
+        }
+        .into()
+    }
+
+    fn failed(apndp: bool, rnw: bool, a: u8, ack: Ack, retries: Option<u32>) -> Input {
+        Failure {
+            ts: None,
+            apndp,
+            rnw,
+            a: u2::new(a),
+            ack,
+            retries,
+        }
+        .into()
+    }
+
+    // Transfer requests: APnDP, RnW, A[3:2]
+    const W_AP_TAR: u8 = 0x05;
+    const W_AP_DRW: u8 = 0x0D;
+    const R_AP_DRW: u8 = 0x0F;
+
+    #[test]
+    fn wait_partway() {
+        // The posted DRW result is read out before the write retries run out
+        let inputs = input(&[
+            transfer_configure(1, 80),
+            transfer(
+                3,
+                &[
+                    (W_AP_TAR, Some(0x2000_0000)),
+                    (R_AP_DRW, None),
+                    (W_AP_DRW, Some(2)),
+                ],
+                2,
+                0x02,
+                &[0x1234],
+            ),
+        ]);
+        assert_eq!(
+            inputs,
+            [
+                command(true, false, 1, 0x2000_0000),
+                command(true, true, 3, 0x1234),
+                failed(true, false, 3, Ack::Wait, Some(80)),
+            ]
+        );
+    }
+
+    #[test]
+    fn fault_partway() {
+        // Reading the posted DRW result before the write faults
+        let inputs = input(&[transfer(
+            1,
+            &[
+                (W_AP_TAR, Some(0x2000_0000)),
+                (R_AP_DRW, None),
+                (W_AP_DRW, Some(7)),
+            ],
+            2,
+            0x04,
+            &[],
+        )]);
+        assert_eq!(
+            inputs,
+            [
+                command(true, false, 1, 0x2000_0000),
+                failed(false, true, 3, Ack::Fault, None),
+            ]
+        );
+
+        // Second of three DRW reads faults, the posted data of the first one is lost with it
+        let inputs = input(&[transfer(
+            1,
+            &[
+                (W_AP_TAR, Some(0x2000_0000)),
+                (R_AP_DRW, None),
+                (R_AP_DRW, None),
+                (R_AP_DRW, None),
+            ],
+            2,
+            0x04,
+            &[],
+        )]);
+        assert_eq!(
+            inputs,
+            [
+                command(true, false, 1, 0x2000_0000),
+                failed(true, true, 3, Ack::Fault, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_fault_partway() {
+        let request = packet(
+            1,
+            &fields(&[
+                ("cmsis_dap.response", "2".into()),
+                ("cmsis_dap.command", "0x06".into()),
+                ("cmsis_dap.dap_index", "0".into()),
+                ("cmsis_dap.transfer_block.count", "3".into()),
+                ("cmsis_dap.transfer.request", format!("{W_AP_DRW:#04x}")),
+                ("cmsis_dap.transfer.write.data", "1".into()),
+                ("cmsis_dap.transfer.write.data", "2".into()),
+                ("cmsis_dap.transfer.write.data", "3".into()),
+            ]),
+        );
+        let response = packet(
+            2,
+            &fields(&[
+                ("cmsis_dap.request", "1".into()),
+                ("cmsis_dap.command", "0x06".into()),
+                ("cmsis_dap.transfer_block.count", "1".into()),
+                ("cmsis_dap.transfer.response", "0x04".into()),
+            ]),
+        );
+        assert_eq!(
+            input(&[request, response]),
+            [
+                command(true, false, 3, 1),
+                failed(true, false, 3, Ack::Fault, None),
+            ]
+        );
+    }
+}
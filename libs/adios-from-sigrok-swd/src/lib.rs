@@ -1,397 +1,109 @@
-use adios_common::{Command, Input, Timestamp};
+use adios_common::{
+    swd::{self, Ack, Event, Packet},
+    Input, Timestamp,
+};
 use bilge::prelude::*;
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{digit1, hex_digit1, line_ending},
-    combinator::{all_consuming, eof, fail, map_res},
-    multi::{many0, many0_count, many1},
-    sequence::pair,
+    combinator::{all_consuming, eof, map_res},
+    multi::many1,
     IResult, Parser,
 };
 
-#[derive(Debug, Copy, Clone)]
-enum AccessId {
-    Dp(Dp),
-    Ap(Ap),
+/// Request part of an SWD packet, `(apndp, rnw, a)`
+fn request(input: &str) -> IResult<&str, (bool, bool, u2)> {
+    let (input, (apndp, rnw, a)) = alt((
+        tag("IDCODE").map(|_| (false, true, 0x0)),
+        tag("R CTRL/STAT").map(|_| (false, true, 0x4)),
+        tag("RESEND").map(|_| (false, true, 0x8)),
+        tag("RDBUFF").map(|_| (false, true, 0xc)),
+        tag("W ABORT").map(|_| (false, false, 0x0)),
+        tag("W CTRL/STAT").map(|_| (false, false, 0x4)),
+        tag("W SELECT").map(|_| (false, false, 0x8)),
+        ap("R AP", true),
+        ap("W AP", false),
+    ))(input)?;
+    Ok((input, (apndp, rnw, u2::new(a >> 2))))
 }
 
-impl AccessId {
-    fn tag(&self) -> impl FnMut(&str) -> IResult<&str, &str> + '_ {
-        move |input| match self {
-            AccessId::Dp(v) => v.tag()(input),
-            AccessId::Ap(v) => v.tag()(input),
-        }
-    }
-
-    fn simple(input: &str) -> IResult<&str, Self> {
-        if Self::complex(input).is_ok() {
-            fail(input)
-        } else {
-            alt((Dp::parse, Ap::w))(input)
-        }
-    }
-
-    fn complex(input: &str) -> IResult<&str, Self> {
-        Ap::r(input)
-    }
-
-    fn command(&self, data: u32, ts: Timestamp) -> Command {
-        let apndp;
-        let rnw;
-        let a = match self {
-            AccessId::Dp(dp) => {
-                apndp = false;
-                match dp {
-                    Dp::R(r) => {
-                        rnw = true;
-                        (*r as u8) >> 2
-                    }
-                    Dp::W(w) => {
-                        rnw = false;
-                        (*w as u8) >> 2
-                    }
-                }
-            }
-            AccessId::Ap(ap) => {
-                apndp = true;
-                rnw = matches!(ap, Ap::R(_));
-                let (Ap::R(v) | Ap::W(v)) = ap;
-                (*v as u8) >> 2
-            }
-        };
-        let a = u2::new(a);
-        Command {
-            apndp,
-            rnw,
-            a,
-            data,
-            ts: Some(ts),
-        }
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-enum Dp {
-    R(ReadDp),
-    W(WriteDp),
-}
-
-impl Dp {
-    fn tag(&self) -> impl FnMut(&str) -> IResult<&str, &str> + '_ {
-        move |input| match self {
-            Dp::R(ReadDp::IdCode) => tag("IDCODE")(input),
-            Dp::R(ReadDp::CtrlStat) => tag("R CTRL/STAT")(input),
-            Dp::R(ReadDp::Resend) => tag("RESEND")(input),
-            Dp::R(ReadDp::Rdbuff) => tag("RDBUFF")(input),
-            Dp::W(WriteDp::Abort) => tag("W ABORT")(input),
-            Dp::W(WriteDp::CtrlStat) => tag("W CTRL/STAT")(input),
-            Dp::W(WriteDp::Select) => tag("W SELECT")(input),
-        }
-    }
-    fn rdbuff(input: &str) -> IResult<&str, AccessId> {
-        tag("RDBUFF")
-            .map(|_| AccessId::Dp(Self::R(ReadDp::Rdbuff)))
-            .parse(input)
-    }
-    fn parse(input: &str) -> IResult<&str, AccessId> {
-        alt((
-            tag("IDCODE").map(|_| AccessId::Dp(Self::R(ReadDp::IdCode))),
-            tag("R CTRL/STAT").map(|_| AccessId::Dp(Self::R(ReadDp::CtrlStat))),
-            tag("RESEND").map(|_| AccessId::Dp(Self::R(ReadDp::Resend))),
-            // TODO: This probably can be removed and then simple_command can be simplified
-            // not to ignore rdbuff??
-            Self::rdbuff,
-            tag("W ABORT").map(|_| AccessId::Dp(Self::W(WriteDp::Abort))),
-            tag("W CTRL/STAT").map(|_| AccessId::Dp(Self::W(WriteDp::CtrlStat))),
-            tag("W SELECT").map(|_| AccessId::Dp(Self::W(WriteDp::Select))),
-        ))(input)
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-enum ReadDp {
-    IdCode = 0x0,
-    CtrlStat = 0x4,
-    Resend = 0x8,
-    Rdbuff = 0xC,
-}
-
-#[derive(Debug, Copy, Clone)]
-enum WriteDp {
-    Abort = 0x0,
-    CtrlStat = 0x4,
-    Select = 0x8,
-}
-
-#[derive(Debug, Copy, Clone)]
-enum Ap {
-    R(InnerAp),
-    W(InnerAp),
-}
-
-impl Ap {
-    fn tag(&self) -> impl FnMut(&str) -> IResult<&str, &str> + '_ {
-        move |input| match self {
-            Ap::R(InnerAp::One) => tag("R AP0")(input),
-            Ap::R(InnerAp::Two) => tag("R AP4")(input),
-            Ap::R(InnerAp::Three) => tag("R AP8")(input),
-            Ap::R(InnerAp::Four) => tag("R APc")(input),
-            Ap::W(InnerAp::One) => tag("W AP0")(input),
-            Ap::W(InnerAp::Two) => tag("W AP4")(input),
-            Ap::W(InnerAp::Three) => tag("W AP8")(input),
-            Ap::W(InnerAp::Four) => tag("W APc")(input),
-        }
-    }
-    fn r(input: &str) -> IResult<&str, AccessId> {
-        let (input, _) = tag("R AP")(input)?;
-        let (input, hex) = InnerAp::parse(input)?;
-        Ok((input, AccessId::Ap(Self::R(hex))))
-    }
-
-    fn w(input: &str) -> IResult<&str, AccessId> {
-        let (input, _) = tag("W AP")(input)?;
-        let (input, hex) = InnerAp::parse(input)?;
-        Ok((input, AccessId::Ap(Self::W(hex))))
-    }
-}
-
-#[derive(Debug, Copy, Clone)]
-enum InnerAp {
-    One = 0x0,
-    Two = 0x4,
-    Three = 0x8,
-    Four = 0xc,
-}
-
-impl InnerAp {
-    fn parse(input: &str) -> IResult<&str, Self> {
-        alt((
-            tag("0").map(|_| Self::One),
-            tag("4").map(|_| Self::Two),
-            tag("8").map(|_| Self::Three),
-            tag("c").map(|_| Self::Four),
-        ))(input)
+fn ap(prefix: &'static str, rnw: bool) -> impl FnMut(&str) -> IResult<&str, (bool, bool, u8)> {
+    move |input| {
+        let (input, _) = tag(prefix)(input)?;
+        let (input, a) = alt((
+            tag("0").map(|_| 0x0),
+            tag("4").map(|_| 0x4),
+            tag("8").map(|_| 0x8),
+            tag("c").map(|_| 0xc),
+        ))(input)?;
+        Ok((input, (true, rnw, a)))
     }
 }
 
+/// Resolved commands, posted AP reads, WAIT retries and FAULTs are handled by [`swd::resolve`]
 pub fn generate_vm_commands(input: &str) -> Result<Vec<Input>, nom::Err<nom::error::Error<&str>>> {
-    let (_, commands) = all_consuming(many1(command))(input)?;
-    Ok(commands.into_iter().flat_map(|v| v).collect())
+    Ok(swd::resolve(generate_wire_events(input)?))
 }
 
-fn command(input: &str) -> IResult<&str, Vec<Input>> {
-    alt((simple_command, complex_command, ll::ignored_commands))(input)
+/// Raw SWD packets with their ACKs, as they appeared on the wire
+pub fn generate_wire_events(input: &str) -> Result<Vec<Event>, nom::Err<nom::error::Error<&str>>> {
+    let (_, events) = all_consuming(many1(event))(input)?;
+    Ok(events.into_iter().flatten().collect())
 }
 
-fn simple_command(input: &str) -> IResult<&str, Vec<Input>> {
-    let (input, ll_command) = ll::command(AccessId::simple, true)(input)?;
-    // Do not propagate certain accesses.
-    // - RDBUFF is relevant only if following AP read operation (complex command) and then it should be merged with it
-    let command = match ll_command {
-        ll::MaybeCommand::Ok(command) => match &command.access_id {
-            // TODO: This is probably over-complicated, consider removing rdbuff handling from Dp::parse
-            AccessId::Dp(Dp::R(ReadDp::Rdbuff)) => None,
-            _ => Some(Input::Command(command.into())),
-        },
-        v => Some(v.into()),
-    };
-    Ok((input, command.into_iter().collect()))
+fn event(input: &str) -> IResult<&str, Option<Event>> {
+    alt((
+        packet.map(|v| Some(v.into())),
+        ll::line(tag("LINERESET")).map(|(ts, _)| Some(Event::LineReset { ts: Some(ts) })),
+        ll::line(tag("JTAG->SWD")).map(|_| None),
+    ))(input)
 }
 
-fn complex_command(input: &str) -> IResult<&str, Vec<Input>> {
-    let (input, ll_commands) = many1(ll::command(AccessId::complex, false))(input)?;
-    if ll_commands.iter().any(|v| v.is_not_ok()) {
-        // TODO: No real-life example of this, hard to determine how to handle it
-        log::error!("R APx FAULTs? Parsing might be incomplete");
-        // Cleanup? All of this is theoretical
-        let (input, _) = many0(ll::command(Dp::rdbuff, true))(input)?;
-        return Ok((
-            input,
-            core::iter::once(Input::landmark("FAULT occurred amoung R APx, unclear how to generate a command, result is presumably incomplete.")).collect()));
-    }
-
-    let ll_commands: Vec<_> = ll_commands.into_iter().map(|v| v.unwrap()).collect();
-
-    let (input, rdbuff_ll_command) = ll::command(Dp::rdbuff, true)(input)?;
-    let rdbuff_ll_command = match rdbuff_ll_command {
-        ll::MaybeCommand::Ok(cmd) => cmd,
-        // TODO: Instead of returing a FAULT as "RDBUFF's FAULT", picking up the
-        // actual command (commands) that was (were) requested would give a better
-        // insight.
-        v => return Ok((input, core::iter::once(v.into()).collect())),
+fn packet(input: &str) -> IResult<&str, Packet> {
+    let (input, (start_ts, (apndp, rnw, a))) = ll::line(request)(input)?;
+    let (input, (ack_ts, ack)) = ll::line(alt((
+        tag("OK").map(|_| Ack::Ok),
+        tag("WAIT").map(|_| Ack::Wait),
+        tag("FAULT").map(|_| Ack::Fault),
+    )))(input)?;
+    let (input, end_ts, data) = if let Ack::Ok = ack {
+        let (input, (end_ts, data)) = ll::line(ll::value)(input)?;
+        (input, end_ts, Some(data))
+    } else {
+        (input, ack_ts, None)
     };
-
-    let commands = ll_commands
-        .iter()
-        .zip(
-            ll_commands
-                .iter()
-                .skip(1) // Offset by one
-                .chain(core::iter::once(&rdbuff_ll_command)),
-        )
-        .map(|(l, r)| {
-            // Offset by one
-            Input::Command(
-                ll::Command {
-                    ts: Timestamp {
-                        start: l.ts.start,
-                        end: r.ts.end,
-                    },
-                    access_id: l.access_id,
-                    value: r.value,
-                }
-                .into(),
-            )
-        })
-        .collect();
-
-    Ok((input, commands))
+    Ok((
+        input,
+        Packet {
+            ts: Some(Timestamp {
+                start: start_ts.start,
+                end: end_ts.end,
+            }),
+            apndp,
+            rnw,
+            a,
+            ack,
+            data,
+        },
+    ))
 }
 
 mod ll {
     use super::*;
-    pub(super) enum MaybeCommand {
-        GotBoredOfWaits(AccessId),
-        Fault(AccessId),
-        Ok(Command),
-    }
-
-    impl MaybeCommand {
-        pub(super) fn is_not_ok(&self) -> bool {
-            if let Self::Ok(_) = self {
-                false
-            } else {
-                true
-            }
-        }
-
-        pub(super) fn unwrap(self) -> Command {
-            match self {
-                MaybeCommand::GotBoredOfWaits(access_id) => {
-                    panic!("unwrapped when GotBoredOfWaits({access_id:?})")
-                }
-                MaybeCommand::Fault(access_id) => panic!("unwrapped when Fault({access_id:?}"),
-                MaybeCommand::Ok(v) => v,
-            }
-        }
-    }
-
-    impl From<MaybeCommand> for Input {
-        fn from(value: MaybeCommand) -> Self {
-            match value {
-                MaybeCommand::GotBoredOfWaits(access_id) => Input::Landmark(format!(
-                    "WAIT spam with no resolution occurred when trying to {access_id:?}"
-                )),
-                MaybeCommand::Fault(access_id) => Input::Landmark(format!(
-                    "FAULT occurred when trying to {access_id:?}"
-                )),
-                MaybeCommand::Ok(command) => Input::Command(command.into()),
-            }
-        }
-    }
-
-    pub(super) struct Command {
-        pub(super) ts: Timestamp,
-        pub(super) access_id: AccessId,
-        pub(super) value: u32,
-    }
-
-    impl From<Command> for super::Command {
-        fn from(cmd: Command) -> Self {
-            let Command {
-                ts,
-                access_id,
-                value,
-            } = cmd;
-            access_id.command(value, ts)
-        }
-    }
-
-    pub fn command(
-        command: impl FnMut(&str) -> IResult<&str, AccessId> + Copy,
-        eof_ok: bool,
-    ) -> impl FnMut(&str) -> IResult<&str, MaybeCommand> {
-        move |input| {
-            enum Ack {
-                Ok,
-                Wait,
-                Fault,
-            }
-            let (input, (start_ts, access_id)) = line(command, false)(input)?;
-            let (input, (.., ack)) = alt((
-                // TODO: Make `ll::line` work with all Parser::map
-                // For now, this is a workaround
-                line(alt((tag("OK").map(|_| Ack::Ok), fail)), false),
-                line(alt((tag("WAIT").map(|_| Ack::Wait), fail)), false),
-                line(alt((tag("FAULT").map(|_| Ack::Fault), fail)), true),
-            ))(input)?;
-            let (input, ack) = if let Ack::Wait = ack {
-                let (input, _) =
-                    many0(pair(line(access_id.tag(), false), line(tag("WAIT"), true)))(input)?;
-                let (input, count) = many0_count(line(access_id.tag(), false))(input)?;
-                if count == 0 {
-                    return Ok((input, MaybeCommand::GotBoredOfWaits(access_id)));
-                }
-                // Very improbable case in real life. Usually when WAIT occurs, SWD is screwed up.
-                let (input, (.., ack)) = line(
-                    alt((tag("OK").map(|_| Ack::Ok), tag("FAULT").map(|_| Ack::Fault))),
-                    false,
-                )(input)?;
-                (input, ack)
-            } else {
-                (input, ack)
-            };
-            match ack {
-                Ack::Ok => {
-                    let (input, (end_ts, value)) = line(value, eof_ok)(input)?;
-                    Ok((
-                        input,
-                        MaybeCommand::Ok(Command {
-                            ts: Timestamp {
-                                start: start_ts.start,
-                                end: end_ts.end,
-                            },
-                            value,
-                            access_id,
-                        }),
-                    ))
-                }
-                Ack::Wait => {
-                    unreachable!("Another WAIT? Come on.");
-                }
-                Ack::Fault => Ok((input, MaybeCommand::Fault(access_id))),
-            }
-        }
-    }
 
-    fn line<'a: 'b, 'b, T>(
-        mut command: impl FnMut(&'a str) -> IResult<&'b str, T>,
-        eof_ok: bool,
-    ) -> impl FnMut(&'a str) -> IResult<&'b str, (Timestamp, T)> {
+    pub(super) fn line<'a, T>(
+        mut command: impl FnMut(&'a str) -> IResult<&'a str, T>,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, (Timestamp, T)> {
         move |input| {
             let (input, ts) = timestamps(input)?;
-            let (input, access_id) = command(input)?;
-            let input = if eof_ok {
-                let (input, _) = alt((line_ending, eof))(input)?;
-                input
-            } else {
-                let (input, _) = line_ending(input)?;
-                input
-            };
-            Ok((input, (ts, access_id)))
+            let (input, v) = command(input)?;
+            let (input, _) = alt((line_ending, eof))(input)?;
+            Ok((input, (ts, v)))
         }
     }
 
-    // TODO: Maybe VM should be reset when LINERESET encountered.
-    // OTOH, CMSIS_DAP based VM does nothing on DAP_Connect
-    pub fn ignored_commands(input: &str) -> IResult<&str, Vec<super::Input>> {
-        let (input, _) =
-            alt((line(tag("LINERESET"), false), line(tag("JTAG->SWD"), false)))(input)?;
-        Ok((input, Vec::new()))
-    }
-
-    fn value(input: &str) -> IResult<&str, u32> {
+    pub(super) fn value(input: &str) -> IResult<&str, u32> {
         let (input, _) = tag("0x")(input)?;
         let (input, hex) = map_res(hex_digit1, hex_u32)(input)?;
         Ok((input, hex))
@@ -417,6 +129,7 @@ mod ll {
 #[cfg(test)]
 mod test {
     use super::*;
+    use adios_common::{Command, Failure};
 
     #[test]
     fn simple_command_with_ok() {
@@ -489,11 +202,19 @@ mod test {
             a: u2::new(0),
             data: 0x5ba02477,
         })];
-        // First is the landmark, second is the correct command
+        // First is the abandoned request, second is the correct command
         assert_eq!(&commands[1..], expected_commands);
-        assert!(matches!(commands[0], Input::Landmark(_)));
+        assert!(matches!(
+            commands[0],
+            Input::Failure(Failure {
+                ack: Ack::Wait,
+                retries: Some(3),
+                ..
+            })
+        ));
     }
 
+    #[test]
     fn simple_command_with_wait_and_switch() {
         let text_sample = "1337-1337 swd-1: IDCODE
 1337-1337 swd-1: WAIT
@@ -514,14 +235,31 @@ mod test {
 1337-71 swd-1: 0xdeadbeef
 ";
         let commands = generate_vm_commands(text_sample).unwrap();
-        let expected_commands = [Input::Command(Command {
-            ts: Some(Timestamp { start: 17, end: 71 }),
-            apndp: false,
-            rnw: false,
-            a: u2::new(0),
-            data: 0xdeadbeef,
-        })];
-        // TODO: Fix when landmark contains the access_id
+        let wait = |rnw, a, retries| {
+            Input::Failure(Failure {
+                ts: Some(Timestamp {
+                    start: 1337,
+                    end: 1337,
+                }),
+                apndp: false,
+                rnw,
+                a: u2::new(a),
+                ack: Ack::Wait,
+                retries: Some(retries),
+            })
+        };
+        let expected_commands = [
+            wait(true, 0, 2),
+            wait(false, 2, 1),
+            wait(true, 0, 1),
+            Input::Command(Command {
+                ts: Some(Timestamp { start: 17, end: 71 }),
+                apndp: false,
+                rnw: false,
+                a: u2::new(0),
+                data: 0xdeadbeef,
+            }),
+        ];
         assert_eq!(commands, expected_commands);
     }
 
@@ -564,9 +302,22 @@ mod test {
             a: u2::new(0),
             data: 0x1,
         })];
-        // First is the command, second is the landmark with the fault
+        // First is the command, second is the fault
         assert_eq!(&commands[..1], expected_commands);
-        assert!(matches!(commands[1], Input::Landmark(_)));
+        assert_eq!(
+            commands[1],
+            Input::Failure(Failure {
+                ts: Some(Timestamp {
+                    start: 1337,
+                    end: 1337,
+                }),
+                apndp: true,
+                rnw: true,
+                a: u2::new(3),
+                ack: Ack::Fault,
+                retries: Some(0),
+            })
+        );
     }
 
     #[test]
@@ -0,0 +1,251 @@
+use adios_common::{
+    itm::{ExceptionFunction, Packet, Payload, TracePacket},
+    Timestamp,
+};
+
+/// Turn the SWO byte stream into ITM/DWT packets
+///
+/// A packet truncated by the end of the capture is dropped.
+pub(crate) fn decode(bytes: &[(Option<Timestamp>, u8)]) -> Vec<TracePacket> {
+    let mut packets = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (start_ts, header) = bytes[i];
+        let Some((len, packet)) = packet(header, &bytes[i..]) else {
+            log::warn!("ITM packet with header {header:#04x} truncated by the end of the capture");
+            break;
+        };
+        i += len;
+        let Some(packet) = packet else {
+            continue;
+        };
+        let ts = match (start_ts, bytes[i - 1].0) {
+            (Some(start), Some(end)) => Some(Timestamp {
+                start: start.start,
+                end: end.end,
+            }),
+            _ => None,
+        };
+        packets.push(TracePacket { ts, packet });
+    }
+    packets
+}
+
+/// Returns the number of consumed bytes and the packet (if it was not just idling)
+fn packet(header: u8, bytes: &[(Option<Timestamp>, u8)]) -> Option<(usize, Option<Packet>)> {
+    let data = || bytes.iter().skip(1).map(|&(_, v)| v);
+    match header {
+        0x00 => {
+            let zeros = data().take_while(|&v| v == 0).count() + 1;
+            match bytes.get(zeros) {
+                Some(&(_, 0x80)) => Some((zeros + 1, Some(Packet::Sync))),
+                // Idle line, or the stream ends in the middle of a sync
+                _ => Some((zeros, None)),
+            }
+        }
+        0x70 => Some((1, Some(Packet::Overflow))),
+        0x94 => {
+            let payload = continuation(data(), 4)?;
+            let mut value = 0;
+            let mut wrap = false;
+            let mut clock_change = false;
+            for (i, &byte) in payload.iter().enumerate() {
+                if i == 3 {
+                    value |= (byte as u32 & 0x1F) << 21;
+                    clock_change = byte & 0x20 != 0;
+                    wrap = byte & 0x40 != 0;
+                } else {
+                    value |= (byte as u32 & 0x7F) << (7 * i);
+                }
+            }
+            let packet = Packet::GlobalTimestamp1 {
+                value,
+                wrap,
+                clock_change,
+            };
+            Some((1 + payload.len(), Some(packet)))
+        }
+        0xB4 => {
+            let payload = continuation(data(), 6)?;
+            let value = payload
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, &byte)| acc | (byte as u64 & 0x7F) << (7 * i));
+            Some((1 + payload.len(), Some(Packet::GlobalTimestamp2 { value })))
+        }
+        // Local timestamp, format 2
+        0x10..=0x60 if header & 0x0F == 0 => Some((
+            1,
+            Some(Packet::LocalTimestamp {
+                tc: 0,
+                delta: (header >> 4) as u32,
+            }),
+        )),
+        // Local timestamp, format 1
+        0xC0..=0xF0 if header & 0x0F == 0 => {
+            let payload = continuation(data(), 4)?;
+            let delta = payload
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, &byte)| acc | (byte as u32 & 0x7F) << (7 * i));
+            let packet = Packet::LocalTimestamp {
+                tc: (header >> 4) & 0b11,
+                delta,
+            };
+            Some((1 + payload.len(), Some(packet)))
+        }
+        _ if header & 0x0B == 0x08 => {
+            let mut value = (header as u32 >> 4) & 0b111;
+            let mut len = 1;
+            if header & 0x80 != 0 {
+                let payload = continuation(data(), 4)?;
+                for (i, &byte) in payload.iter().enumerate() {
+                    let mask = if i == 3 { 0xFF } else { 0x7F };
+                    value |= (byte as u32 & mask) << (3 + 7 * i);
+                }
+                len += payload.len();
+            }
+            let packet = Packet::Extension {
+                hardware: header & 0x04 != 0,
+                value,
+            };
+            Some((len, Some(packet)))
+        }
+        _ if header & 0x03 != 0 => {
+            let size = match header & 0x03 {
+                0b01 => 1,
+                0b10 => 2,
+                _ => 4,
+            };
+            let payload: Vec<u8> = data().take(size).collect();
+            if payload.len() < size {
+                return None;
+            }
+            let payload = match *payload {
+                [b] => Payload::Byte(b),
+                [b0, b1] => Payload::Halfword(u16::from_le_bytes([b0, b1])),
+                [b0, b1, b2, b3] => Payload::Word(u32::from_le_bytes([b0, b1, b2, b3])),
+                _ => unreachable!(),
+            };
+            let id = header >> 3;
+            let packet = if header & 0x04 == 0 {
+                Packet::Instrumentation { port: id, payload }
+            } else {
+                hardware_source(id, payload)
+            };
+            Some((1 + size, Some(packet)))
+        }
+        _ => Some((1, Some(Packet::Reserved(header)))),
+    }
+}
+
+fn hardware_source(discriminator: u8, payload: Payload) -> Packet {
+    match (discriminator, payload) {
+        (0, Payload::Byte(flags)) => Packet::EventCounter { flags },
+        (1, Payload::Halfword(value)) => Packet::ExceptionTrace {
+            number: value & 0x1FF,
+            function: match (value >> 12) & 0b11 {
+                1 => ExceptionFunction::Entered,
+                2 => ExceptionFunction::Exited,
+                3 => ExceptionFunction::Returned,
+                _ => ExceptionFunction::Reserved,
+            },
+        },
+        (2, Payload::Word(pc)) => Packet::PcSample { pc: Some(pc) },
+        (2, Payload::Byte(_)) => Packet::PcSample { pc: None },
+        (8..=15, Payload::Word(pc)) if discriminator & 1 == 0 => Packet::DataTracePc {
+            comparator: (discriminator >> 1) & 0b11,
+            pc,
+        },
+        (8..=15, Payload::Halfword(offset)) if discriminator & 1 == 1 => Packet::DataTraceAddress {
+            comparator: (discriminator >> 1) & 0b11,
+            offset,
+        },
+        (16..=23, payload) => Packet::DataTraceValue {
+            comparator: (discriminator >> 1) & 0b11,
+            write: discriminator & 1 == 1,
+            payload,
+        },
+        (discriminator, payload) => Packet::Hardware {
+            discriminator,
+            payload,
+        },
+    }
+}
+
+/// Payload bytes with the continuation bit (bit 7), the last one has it cleared
+fn continuation(mut data: impl Iterator<Item = u8>, max: usize) -> Option<Vec<u8>> {
+    let mut payload = Vec::new();
+    while payload.len() < max {
+        let byte = data.next()?;
+        payload.push(byte);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_bytes(bytes: &[u8]) -> Vec<Packet> {
+        let bytes: Vec<_> = bytes
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                let ts = Timestamp {
+                    start: 10 * i as u64,
+                    end: 10 * i as u64 + 9,
+                };
+                (Some(ts), v)
+            })
+            .collect();
+        decode(&bytes).into_iter().map(|v| v.packet).collect()
+    }
+
+    #[test]
+    fn packets() {
+        let packets = decode_bytes(&[
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x80, // sync
+            0x01, b'H', // STIM[0], byte
+            0x0B, 0x78, 0x56, 0x34, 0x12, // STIM[1], word
+            0x0E, 0x10, 0x10, // exception 16 entered
+            0x17, 0x00, 0x01, 0x00, 0x08, // PC sample
+            0xC0, 0x81, 0x01, // LTS format 1: 129
+            0x30, // LTS format 2: 3
+            0x70, // overflow
+            0x08, // stimulus port page 0
+            0x03, 0x00, // truncated
+        ]);
+        assert_eq!(
+            packets,
+            [
+                Packet::Sync,
+                Packet::Instrumentation {
+                    port: 0,
+                    payload: Payload::Byte(b'H')
+                },
+                Packet::Instrumentation {
+                    port: 1,
+                    payload: Payload::Word(0x1234_5678)
+                },
+                Packet::ExceptionTrace {
+                    number: 16,
+                    function: ExceptionFunction::Entered
+                },
+                Packet::PcSample {
+                    pc: Some(0x0800_0100)
+                },
+                Packet::LocalTimestamp { tc: 0, delta: 129 },
+                Packet::LocalTimestamp { tc: 0, delta: 3 },
+                Packet::Overflow,
+                Packet::Extension {
+                    hardware: false,
+                    value: 0
+                },
+            ]
+        );
+    }
+}
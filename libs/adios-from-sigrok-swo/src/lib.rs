@@ -0,0 +1,113 @@
+//! ITM/DWT trace captured on SWO next to the SWD lines
+//!
+//! Timestamps are sample numbers of the same capture the SWD commands come from,
+//! so [`adios_common::interleave`] can merge both streams.
+use adios_common::{Input, Timestamp};
+use nom::{
+    bytes::complete::{tag, take_till, take_while1},
+    character::complete::{digit1, line_ending, not_line_ending},
+    combinator::{all_consuming, eof, map_res, opt},
+    multi::many0,
+    sequence::terminated,
+    IResult,
+};
+
+mod itm;
+mod manchester;
+
+/// NRZ (UART) encoded SWO, decoded by sigrok's UART decoder
+///
+/// Only `rx-data` annotations with hex formatted bytes are taken into account.
+pub fn generate_vm_input_from_uart(
+    input: &str,
+) -> Result<Vec<Input>, nom::Err<nom::error::Error<&str>>> {
+    let (_, bytes) = all_consuming(many0(terminated(line, alt_line_ending)))(input)?;
+    let bytes: Vec<_> = bytes.into_iter().flatten().collect();
+    Ok(itm::decode(&bytes).into_iter().map(Input::from).collect())
+}
+
+/// Manchester encoded SWO, `samples` being a raw (`sigrok-cli -O binary`) dump
+///
+/// `bit` is the position of the SWO channel within a sample.
+pub fn generate_vm_input_from_manchester(samples: &[u8], bit: u8) -> Vec<Input> {
+    let bytes = manchester::decode(samples, bit);
+    itm::decode(&bytes).into_iter().map(Input::from).collect()
+}
+
+fn alt_line_ending(input: &str) -> IResult<&str, &str> {
+    if input.is_empty() {
+        eof(input)
+    } else {
+        line_ending(input)
+    }
+}
+
+/// `<start>-<end> <decoder>: <annotation>`
+fn line(input: &str) -> IResult<&str, Option<(Option<Timestamp>, u8)>> {
+    let (input, ts) = timestamps(input)?;
+    let (input, _) = take_till(|c| c == ':')(input)?;
+    let (input, _) = tag(": ")(input)?;
+    let (input, annotation) = not_line_ending(input)?;
+    Ok((input, byte(annotation).ok().map(|(_, v)| (Some(ts), v))))
+}
+
+fn byte(input: &str) -> IResult<&str, u8> {
+    let (input, _) = opt(tag("0x"))(input)?;
+    all_consuming(map_res(
+        take_while1(|c: char| c.is_ascii_hexdigit()),
+        |v: &str| {
+            if v.len() > 2 {
+                return Err(());
+            }
+            u8::from_str_radix(v, 16).map_err(|_| ())
+        },
+    ))(input)
+}
+
+fn timestamps(input: &str) -> IResult<&str, Timestamp> {
+    let (input, start) = map_res(digit1, str::parse)(input)?;
+    let (input, _) = tag("-")(input)?;
+    let (input, end) = map_res(digit1, str::parse)(input)?;
+    let (input, _) = tag(" ")(input)?;
+    Ok((input, Timestamp { start, end }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adios_common::itm::{Packet, Payload, TracePacket};
+
+    #[test]
+    fn uart_annotations() {
+        let input = "\
+100-110 uart-1: Start bit
+100-190 uart-1: 01
+190-200 uart-1: Stop bit
+200-290 uart-1: 48
+300-390 uart-1: 70
+";
+        let inputs = generate_vm_input_from_uart(input).unwrap();
+        assert_eq!(
+            inputs,
+            [
+                Input::Trace(TracePacket {
+                    ts: Some(Timestamp {
+                        start: 100,
+                        end: 290
+                    }),
+                    packet: Packet::Instrumentation {
+                        port: 0,
+                        payload: Payload::Byte(b'H')
+                    }
+                }),
+                Input::Trace(TracePacket {
+                    ts: Some(Timestamp {
+                        start: 300,
+                        end: 390
+                    }),
+                    packet: Packet::Overflow
+                }),
+            ]
+        );
+    }
+}
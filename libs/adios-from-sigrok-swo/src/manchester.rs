@@ -0,0 +1,110 @@
+use adios_common::Timestamp;
+
+/// Decode Manchester encoded SWO from raw logic samples
+///
+/// The line idles low. Every frame starts with a `1` start bit, data bits
+/// follow LSB first. `1` is sent as high then low, `0` as low then high, so
+/// there is always an edge in the middle of a bit. The start bit's high half
+/// gives away the bit period, afterwards the decoder resynchronizes on every
+/// mid-bit edge. A missing mid-bit edge terminates the frame.
+///
+/// `samples` is a `sigrok-cli -O binary` dump, `bit` selects the SWO channel
+/// within a sample. Returned timestamps are sample numbers.
+pub(crate) fn decode(samples: &[u8], bit: u8) -> Vec<(Option<Timestamp>, u8)> {
+    let level = |i: usize| samples[i] >> bit & 1 == 1;
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < samples.len() {
+        // Rising edge of the start bit
+        let Some(start) = (i..samples.len()).find(|&i| level(i)) else {
+            break;
+        };
+        let Some(mid) = (start..samples.len()).find(|&i| !level(i)) else {
+            break;
+        };
+        let half_period = mid - start;
+        let period = 2 * half_period;
+        // Edges on bit boundaries (same consecutive bits) fall outside of this window
+        let window = (half_period / 2).max(1);
+
+        let mut edge = mid;
+        let mut bits = Vec::new();
+        let mut byte_start = None;
+        loop {
+            let from = edge + period - window;
+            let to = (edge + period + window).min(samples.len());
+            if from >= to {
+                break;
+            }
+            let before = level(from);
+            let Some(next_edge) = (from..to).find(|&i| level(i) != before) else {
+                break;
+            };
+            let bit_start = next_edge.saturating_sub(half_period) as u64;
+            byte_start.get_or_insert(bit_start);
+            // Falling edge in the middle means `1`
+            bits.push(before);
+            edge = next_edge;
+            if bits.len() == 8 {
+                let byte = bits
+                    .drain(..)
+                    .enumerate()
+                    .fold(0u8, |acc, (i, v)| acc | (v as u8) << i);
+                let ts = Timestamp {
+                    start: byte_start.take().unwrap(),
+                    end: (edge + half_period) as u64,
+                };
+                bytes.push((Some(ts), byte));
+            }
+        }
+        if !bits.is_empty() {
+            log::warn!(
+                "Manchester SWO frame at sample {start} ends with {} stray bits",
+                bits.len()
+            );
+        }
+        // Skip the second half of the last bit
+        i = edge + half_period;
+        while i < samples.len() && level(i) {
+            i += 1;
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(bytes: &[u8], half_period: usize) -> Vec<u8> {
+        let mut samples = vec![0; 7];
+        let mut push = |first: u8, second: u8| {
+            samples.extend(core::iter::repeat_n(first, half_period));
+            samples.extend(core::iter::repeat_n(second, half_period));
+        };
+        push(1, 0);
+        for byte in bytes {
+            for i in 0..8 {
+                match byte >> i & 1 {
+                    1 => push(1, 0),
+                    _ => push(0, 1),
+                }
+            }
+        }
+        samples.extend([0; 10]);
+        // SWO on the third channel
+        samples.iter().map(|v| v << 2 | 0b01).collect()
+    }
+
+    #[test]
+    fn frames() {
+        let mut samples = encode(&[0x01, 0x48], 4);
+        samples.extend(encode(&[0xFF, 0x00], 5));
+        let bytes = decode(&samples, 2);
+        let values: Vec<_> = bytes.iter().map(|v| v.1).collect();
+        assert_eq!(values, [0x01, 0x48, 0xFF, 0x00]);
+        // Right after the start bit
+        assert_eq!(bytes[0].0.unwrap().start, 7 + 8);
+        assert_eq!(bytes[0].0.unwrap().end, 7 + 8 + 8 * 8);
+    }
+}
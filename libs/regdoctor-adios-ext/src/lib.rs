@@ -1,4 +1,5 @@
 use regdoctor::RegisterInfo;
+use svd_parser::svd;
 
 pub struct Database {
     inner: regdoctor::Database,
@@ -7,9 +8,16 @@ pub struct Database {
 impl Database {
     const CSW_GENERIC: u64 = 0xFFFFFFFF00000000;
     const CSW_AMBA_AHB3: u64 = Self::CSW_GENERIC + 0x20;
+    const CSW_AMBA_AHB5: u64 = Self::CSW_GENERIC + 0x40;
+    const CSW_AMBA_AHB5_ENHANCED_HPROT: u64 = Self::CSW_GENERIC + 0x60;
+    const CSW_AMBA_AXI3_OR_AXI4: u64 = Self::CSW_GENERIC + 0x80;
+    const CSW_AMBA_AXI5: u64 = Self::CSW_GENERIC + 0xA0;
+    const CSW_AMBA_APB2_OR_APB3: u64 = Self::CSW_GENERIC + 0xC0;
+    const CSW_AMBA_APB4_OR_APB5: u64 = Self::CSW_GENERIC + 0xE0;
+    const CSW_JTAG: u64 = Self::CSW_GENERIC + 0x100;
     pub fn new() -> Self {
         let device = svd_parser::parse(include_str!("adi.svd")).unwrap();
-        let inner = regdoctor::Database::from_svd(device);
+        let inner = regdoctor::Database::from_svd(device).unwrap();
         Self { inner }
     }
 
@@ -17,15 +25,99 @@ impl Database {
         let addr = match type_ {
             CswType::Generic => Self::CSW_GENERIC,
             CswType::AmbaAhb3 => Self::CSW_AMBA_AHB3,
+            CswType::AmbaAhb5 => Self::CSW_AMBA_AHB5,
+            CswType::AmbaAhb5WithEnhancedHprot => Self::CSW_AMBA_AHB5_ENHANCED_HPROT,
+            CswType::AmbaAxi3OrAxi4 => Self::CSW_AMBA_AXI3_OR_AXI4,
+            CswType::AmbaAxi5 => Self::CSW_AMBA_AXI5,
+            CswType::AmbaApb2OrApb3 => Self::CSW_AMBA_APB2_OR_APB3,
+            CswType::AmbaApb4OrApb5 => Self::CSW_AMBA_APB4_OR_APB5,
+            CswType::Jtag => Self::CSW_JTAG,
         };
         self.inner.get_register(addr).unwrap().clone()
     }
+
+    /// AP register by the name used in `adios`, CSW is picked by the bus behind the AP
+    pub fn ap(&self, name: &str, csw_type: CswType) -> Option<RegisterInfo> {
+        if name == "CSW" {
+            return Some(self.ap_csw(csw_type));
+        }
+        let lookup = self.inner.find(&format!("ADIv5.AP.{name}"))?;
+        Some(lookup.info.clone())
+    }
+
+    /// DP register by the name used in `adios` (e.g. `CTRL` for CTRL/STAT)
+    pub fn dp(&self, name: &str) -> Option<RegisterInfo> {
+        let name = match name {
+            "CTRL" => "CTRL_STAT",
+            name => name,
+        };
+        let lookup = self.inner.find(&format!("ADIv5.DP.{name}"))?;
+        Some(lookup.info.clone())
+    }
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Register layouts of CoreSight components, relative to the component base
+pub struct CoreSight {
+    device: svd::Device,
+}
+
+impl CoreSight {
+    pub fn new() -> Self {
+        let device = svd_parser::parse(include_str!("coresight.svd")).unwrap();
+        Self { device }
+    }
+
+    /// Registers of a component spanning from `base` to the end of its `id_block`
+    ///
+    /// `layout` is one of `ROM`, `SCS`, `DWT`, `ITM`, `FPB` or `TPIU`, anything else
+    /// gets just the management and identification registers.
+    pub fn component(&self, layout: &str, base: u64, id_block: u64) -> regdoctor::Database {
+        let peripheral = |name: &str| {
+            self.device
+                .peripherals
+                .iter()
+                .find(|v| v.name == name)
+                .map(|v| (**v).clone())
+        };
+        let mut peripherals = Vec::new();
+        if let Some(mut info) = peripheral("Component") {
+            info.name = layout.to_owned();
+            info.base_address = id_block;
+            peripherals.push(svd::Peripheral::Single(info));
+        }
+        if let Some(mut info) = peripheral(layout).filter(|_| layout != "Component") {
+            info.base_address = base;
+            peripherals.push(svd::Peripheral::Single(info));
+        }
+        let mut device = self.device.clone();
+        device.peripherals = peripherals;
+        regdoctor::Database::from_svd(device).unwrap()
+    }
+}
+
+impl Default for CoreSight {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum CswType {
     Generic,
     AmbaAhb3,
+    AmbaAhb5,
+    AmbaAhb5WithEnhancedHprot,
+    AmbaAxi3OrAxi4,
+    AmbaAxi5,
+    AmbaApb2OrApb3,
+    AmbaApb4OrApb5,
+    Jtag,
 }
 
 #[cfg(test)]
@@ -48,4 +140,99 @@ mod tests {
             .unwrap()
             .unwrap();
     }
+
+    #[test]
+    fn ap_registers() {
+        let db = Database::new();
+        for name in ["IDR", "CFG", "BASE", "CFG1"] {
+            assert!(db.ap(name, CswType::Generic).is_some(), "{name}");
+        }
+        assert!(db.ap("TAR", CswType::Generic).is_none());
+
+        let field_names = |type_| {
+            let csw = db.ap("CSW", type_).unwrap().decode_value(0);
+            csw.fields()
+                .iter()
+                .map(|v| v.info.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(field_names(CswType::AmbaAhb5).contains(&"HNONSEC".to_string()));
+        assert!(field_names(CswType::AmbaAxi3OrAxi4).contains(&"Domain".to_string()));
+        assert!(field_names(CswType::AmbaApb4OrApb5).contains(&"PPROT[1]-NonSecure".to_string()));
+        assert!(field_names(CswType::Jtag).contains(&"PORTCONNECTED".to_string()));
+
+        let idr = db.ap("IDR", CswType::Generic).unwrap();
+        let idr = idr.decode_value(0x24770011);
+        let idr = idr.diff_from_nothing().to_string();
+        assert!(idr.contains("AmbaAhb3Bus"), "{idr}");
+        assert!(idr.contains("MemoryAccessPort"), "{idr}");
+        let cfg = db.ap("CFG", CswType::Generic).unwrap();
+        let cfg = cfg.decode_value(0x2);
+        let cfg = cfg.diff_from_nothing().to_string();
+        assert!(cfg.contains("Bits64"), "{cfg}");
+    }
+
+    #[test]
+    fn dp_registers() {
+        let db = Database::new();
+        for name in [
+            "DPIDR",
+            "ABORT",
+            "CTRL",
+            "DLCR",
+            "TARGETID",
+            "DLPIDR",
+            "EVENTSTAT",
+            "SELECT",
+            "RDBUFF",
+            "TARGETSEL",
+        ] {
+            assert!(db.dp(name).is_some(), "{name}");
+        }
+        assert!(db.dp("RESEND").is_none());
+
+        let ctrl_stat = db.dp("CTRL").unwrap();
+        assert_eq!(ctrl_stat.identifier(), "ADIv5.DP.CTRL_STAT");
+        let old = ctrl_stat.decode_value(0x5000_0000);
+        let new = ctrl_stat.decode_value(0xF000_0020);
+        let diff = regdoctor::Register::diff(&old, &new).unwrap().unwrap();
+        let diff = diff.to_string();
+        assert!(
+            diff.contains("STICKYERR : 0x0 → 0x1 / Clear → Set"),
+            "{diff}"
+        );
+        assert!(
+            diff.contains("CDBGPWRUPACK : 0x0 → 0x1 / NotAcknowledged → Acknowledged"),
+            "{diff}"
+        );
+        assert!(!diff.contains("CDBGPWRUPREQ"), "{diff}");
+    }
+
+    #[test]
+    fn coresight_components() {
+        let coresight = CoreSight::new();
+        let dwt = coresight.component("DWT", 0xE000_1000, 0xE000_1000);
+        let ctrl = dwt.get_register(0xE000_1000).unwrap();
+        assert_eq!(ctrl.identifier(), "CoreSight.DWT.CTRL");
+        let cidr1 = dwt.get_register(0xE000_1FF4).unwrap();
+        assert_eq!(cidr1.identifier(), "CoreSight.DWT.CIDR1");
+        let class = cidr1.decode_value(0xB0).diff_from_nothing().to_string();
+        assert!(class.contains("PeripheralTestBlock"), "{class}");
+        assert_eq!(
+            dwt.get_register(0xE000_1048).unwrap().identifier(),
+            "CoreSight.DWT.FUNCTION2"
+        );
+
+        let rom = coresight.component("ROM", 0xE00F_F000, 0xE00F_F000);
+        let entry = rom.get_register(0xE00F_F004).unwrap();
+        assert_eq!(entry.identifier(), "CoreSight.ROM.ROMENTRY1");
+
+        // Unknown layouts only get the identification registers, in the last block
+        let etm = coresight.component("ETM", 0xE004_0000, 0xE004_1000);
+        assert!(etm.get_register(0xE004_0000).is_none());
+        assert_eq!(
+            etm.get_register(0xE004_1FE0).unwrap().identifier(),
+            "CoreSight.ETM.PIDR0"
+        );
+    }
 }
@@ -9,8 +9,8 @@ fn main() {
     let mimxrt1189_svd = svd_parser::parse(&mimxrt1189_svd).unwrap();
     let cortex_m_svd = svd_parser::parse(&cortex_m_svd).unwrap();
     let mut db = Database::new();
-    db.extend_with_svd(mimxrt1189_svd);
-    db.extend_with_svd(cortex_m_svd);
+    db.extend_with_svd(mimxrt1189_svd).unwrap();
+    db.extend_with_svd(cortex_m_svd).unwrap();
     println!("DB len: {}", db.regs.len());
     let register_info = db.get_register(0x524C0000).unwrap();
     let register = register_info.decode_value(0x2);
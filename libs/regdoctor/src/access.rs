@@ -0,0 +1,305 @@
+//! `access`, `modifiedWriteValues` and `readAction` semantics
+use std::fmt::Display;
+use std::ops::Deref;
+
+use svd_rs::{Access, FieldInfo, ModifiedWriteValues, ReadAction};
+
+use crate::{field_mask, RegisterInfo, RegisterSlice};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// Access the register (or one of its fields) is not meant for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Anomaly {
+    WriteToReadOnly,
+    /// Write attempting to change a read-only field
+    WriteToReadOnlyField {
+        field: String,
+    },
+    ReadOfWriteOnly,
+    /// Read affecting the register state (e.g. clear-on-read)
+    ReadSideEffect {
+        /// `None` if the whole register has the read action
+        field: Option<String>,
+        action: ReadAction,
+    },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anomaly::WriteToReadOnly => write!(f, "write to read-only register"),
+            Anomaly::WriteToReadOnlyField { field } => {
+                write!(f, "write changing read-only field {field}")
+            }
+            Anomaly::ReadOfWriteOnly => write!(f, "read of write-only register"),
+            Anomaly::ReadSideEffect { field, action } => {
+                let action = match action {
+                    ReadAction::Clear => "clears",
+                    ReadAction::Set => "sets",
+                    ReadAction::Modify => "modifies",
+                    ReadAction::ModifyExternal => "has an external side effect on",
+                };
+                match field {
+                    Some(field) => write!(f, "read {action} field {field}"),
+                    None => write!(f, "read {action} the register"),
+                }
+            }
+        }
+    }
+}
+
+impl RegisterInfo {
+    pub fn access(&self) -> Option<Access> {
+        self.properties.access
+    }
+
+    fn field_access(&self, field: &FieldInfo) -> Option<Access> {
+        field.access.or(self.access())
+    }
+
+    /// Register state after `written` lands on top of `old`
+    ///
+    /// Read-only parts keep their old value, `modifiedWriteValues` (e.g. `oneToClear`) are
+    /// honoured on the register and field level.
+    pub fn apply_write(&self, old: u64, written: u64) -> u64 {
+        self.apply_write_masked(old, written, u64::MAX)
+    }
+
+    fn apply_write_masked(&self, old: u64, written: u64, mask: u64) -> u64 {
+        let new = modified(
+            self.inner.modified_write_values,
+            self.access(),
+            old,
+            written,
+        );
+        let new = self
+            .inner
+            .fields()
+            .map(Deref::deref)
+            .filter(|field| field_mask(field) & !mask == 0)
+            .fold(new, |new, field| {
+                let mask = field_mask(field);
+                let field_new = modified(
+                    field
+                        .modified_write_values
+                        .or(self.inner.modified_write_values),
+                    self.field_access(field),
+                    old,
+                    written,
+                );
+                (new & !mask) | (field_new & mask)
+            });
+        (old & !mask) | (new & mask)
+    }
+
+    fn anomalies_masked(
+        &self,
+        kind: AccessKind,
+        old: Option<u64>,
+        value: u64,
+        mask: u64,
+    ) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        let fields = || {
+            self.inner
+                .fields()
+                .map(Deref::deref)
+                .filter(move |field| field_mask(field) & !mask == 0)
+        };
+        match kind {
+            AccessKind::Write => {
+                if self.access() == Some(Access::ReadOnly) {
+                    anomalies.push(Anomaly::WriteToReadOnly);
+                    return anomalies;
+                }
+                for field in fields() {
+                    if field.access != Some(Access::ReadOnly) {
+                        continue;
+                    }
+                    let field_mask = field_mask(field);
+                    // Writing back what was read is fine, otherwise only zeroes are expected
+                    let changed = match old {
+                        Some(old) => (old ^ value) & field_mask != 0,
+                        None => value & field_mask != 0,
+                    };
+                    if changed {
+                        anomalies.push(Anomaly::WriteToReadOnlyField {
+                            field: field.name.clone(),
+                        });
+                    }
+                }
+            }
+            AccessKind::Read => {
+                if self.access() == Some(Access::WriteOnly) {
+                    anomalies.push(Anomaly::ReadOfWriteOnly);
+                }
+                if let Some(action) = self.inner.read_action {
+                    anomalies.push(Anomaly::ReadSideEffect {
+                        field: None,
+                        action,
+                    });
+                }
+                for field in fields() {
+                    if let Some(action) = field.read_action {
+                        anomalies.push(Anomaly::ReadSideEffect {
+                            field: Some(field.name.clone()),
+                            action,
+                        });
+                    }
+                }
+            }
+        }
+        anomalies
+    }
+}
+
+impl RegisterSlice<'_> {
+    /// Anomalies of this access, `old` being the value (as seen by the access) before a write
+    pub fn anomalies(&self, kind: AccessKind, old: Option<u64>, value: u64) -> Vec<Anomaly> {
+        let old = old.map(|v| self.to_register(v));
+        self.info
+            .anomalies_masked(kind, old, self.to_register(value), self.mask())
+    }
+
+    /// [`RegisterInfo::apply_write`] with values as seen by the access
+    pub fn apply_write(&self, old: u64, written: u64) -> u64 {
+        let new = self.info.apply_write_masked(
+            self.to_register(old),
+            self.to_register(written),
+            self.mask(),
+        );
+        let new = (new >> (self.register_offset * 8)) << (self.access_offset * 8);
+        let access_mask = crate::lanes_mask(self.access_offset, self.len);
+        (written & !access_mask) | (new & access_mask)
+    }
+}
+
+fn modified(
+    modified_write_values: Option<ModifiedWriteValues>,
+    access: Option<Access>,
+    old: u64,
+    written: u64,
+) -> u64 {
+    if access == Some(Access::ReadOnly) {
+        return old;
+    }
+    match modified_write_values.unwrap_or_default() {
+        ModifiedWriteValues::OneToClear => old & !written,
+        ModifiedWriteValues::OneToSet => old | written,
+        ModifiedWriteValues::OneToToggle => old ^ written,
+        ModifiedWriteValues::ZeroToClear => old & written,
+        ModifiedWriteValues::ZeroToSet => old | !written,
+        ModifiedWriteValues::ZeroToToggle => old ^ !written,
+        ModifiedWriteValues::Clear => 0,
+        ModifiedWriteValues::Set => u64::MAX,
+        ModifiedWriteValues::Modify => written,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+
+    fn database() -> Database {
+        let device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>UART</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register>
+          <name>STATUS</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field>
+              <name>RXNE</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth>
+              <modifiedWriteValues>oneToClear</modifiedWriteValues>
+            </field>
+            <field>
+              <name>BUSY</name><bitOffset>8</bitOffset><bitWidth>1</bitWidth>
+              <access>read-only</access>
+            </field>
+            <field><name>MODE</name><bitOffset>16</bitOffset><bitWidth>2</bitWidth></field>
+          </fields>
+        </register>
+        <register>
+          <name>DATA</name>
+          <addressOffset>0x4</addressOffset>
+          <readAction>clear</readAction>
+        </register>
+        <register>
+          <name>CMD</name>
+          <addressOffset>0x8</addressOffset>
+          <access>write-only</access>
+        </register>
+        <register>
+          <name>ID</name>
+          <addressOffset>0xC</addressOffset>
+          <access>read-only</access>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        Database::from_svd(device).unwrap()
+    }
+
+    #[test]
+    fn write_semantics() {
+        let db = database();
+        let status = db.get_register(0x40000000).unwrap();
+        // RXNE cleared by writing 1, BUSY stays, MODE is written
+        assert_eq!(status.apply_write(0x0000_0101, 0x0002_0001), 0x0002_0100);
+        // Byte write to the MODE lane leaves RXNE alone
+        let slice = db.get_registers(0x40000002, 1).next().unwrap();
+        assert_eq!(slice.apply_write(0x00, 0x03), 0x03);
+        let id = db.get_register(0x4000000C).unwrap();
+        assert_eq!(id.apply_write(0x1234, 0), 0x1234);
+    }
+
+    #[test]
+    fn anomalies() {
+        let db = database();
+        let anomalies = |address, kind, old, value| {
+            let slice = db.get_registers(address, 4).next().unwrap();
+            slice.anomalies(kind, old, value)
+        };
+        assert_eq!(
+            anomalies(0x40000000, AccessKind::Write, Some(0x000), 0x100),
+            [Anomaly::WriteToReadOnlyField {
+                field: "BUSY".into()
+            }]
+        );
+        assert_eq!(
+            anomalies(0x40000000, AccessKind::Write, Some(0x100), 0x101),
+            []
+        );
+        assert_eq!(
+            anomalies(0x4000000C, AccessKind::Write, None, 0),
+            [Anomaly::WriteToReadOnly]
+        );
+        assert_eq!(
+            anomalies(0x40000008, AccessKind::Read, None, 0),
+            [Anomaly::ReadOfWriteOnly]
+        );
+        assert_eq!(
+            anomalies(0x40000004, AccessKind::Read, None, 0),
+            [Anomaly::ReadSideEffect {
+                field: None,
+                action: ReadAction::Clear
+            }]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! On-disk cache of expanded databases
+//!
+//! Parsing and expanding large SVD files takes a while. Registers produced out of an SVD file
+//! are stored as CBOR in `<cache dir>/<hash>.cbor`, the hash covering the SVD contents (or the
+//! parsed device), applied patches, the regdoctor version and [`FORMAT_VERSION`]. Any trouble
+//! with the cache only costs a regular parse.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::{Database, Error, Patch, RegisterInfo};
+
+/// Bump whenever the registers produced out of the same SVD change (expansion, decoding or
+/// the serialized layout), so that stale entries are not served
+const FORMAT_VERSION: u32 = 1;
+
+impl Database {
+    /// Cache parsed SVD files in `cache_dir`, both [`Database::extend_with_svd`] and
+    /// [`Database::extend_with_svd_str`] use it
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Parse `svd` (XML), apply `patches` and extend the database with it
+    ///
+    /// With a cache directory set, the parsing is skipped if the same SVD was seen before.
+    pub fn extend_with_svd_str(&mut self, svd: &str, patches: &[Patch]) -> Result<(), Error> {
+        let Some(cache_dir) = self.cache_dir.clone() else {
+            return self.extend_with_svd(parse(svd, patches)?);
+        };
+        let inputs = [svd.as_bytes()]
+            .into_iter()
+            .chain(patches.iter().map(|v| v.source().as_bytes()));
+        let registers = cached(&cache_dir, key(b"svd", inputs), || {
+            Database::from_svd(parse(svd, patches)?)
+        })?;
+        self.extend_with_registers(registers);
+        Ok(())
+    }
+
+    /// [`Database::extend_with_svd`] of an already parsed device, served from the cache
+    /// if the same device was expanded before
+    pub(crate) fn extend_with_cached_svd(
+        &mut self,
+        cache_dir: &Path,
+        device: svd_rs::Device,
+    ) -> Result<(), Error> {
+        let mut serialized = Vec::new();
+        if let Err(error) = ciborium::into_writer(&device, &mut serialized) {
+            log::warn!("Not caching SVD {}: {error}", device.name);
+            return self.extend_with_device(device);
+        }
+        let registers = cached(cache_dir, key(b"device", [&serialized[..]]), || {
+            let mut db = Database::new();
+            db.extend_with_device(device)?;
+            Ok(db)
+        })?;
+        self.extend_with_registers(registers);
+        Ok(())
+    }
+
+    fn extend_with_registers(&mut self, registers: Vec<(u64, RegisterInfo)>) {
+        for (address, register) in registers {
+            self.insert(address, register);
+        }
+    }
+}
+
+/// Registers stored under `key`, `compute`d (and stored) if there are none
+fn cached(
+    cache_dir: &Path,
+    key: u64,
+    compute: impl FnOnce() -> Result<Database, Error>,
+) -> Result<Vec<(u64, RegisterInfo)>, Error> {
+    let path = cache_dir.join(format!("{key:016x}.cbor"));
+    match load(&path) {
+        Ok(registers) => Ok(registers),
+        Err(error) => {
+            if path.exists() {
+                log::warn!("Ignoring SVD cache {}: {error}", path.display());
+            }
+            let registers: Vec<_> = compute()?.regs.into_iter().collect();
+            if let Err(error) = store(cache_dir, &path, &registers) {
+                log::warn!("Could not write SVD cache {}: {error}", path.display());
+            }
+            Ok(registers)
+        }
+    }
+}
+
+fn parse(svd: &str, patches: &[Patch]) -> Result<svd_rs::Device, Error> {
+    let mut device = svd_parser::parse(svd).map_err(|e| Error::Parse(format!("{e:#}")))?;
+    for patch in patches {
+        patch.apply(&mut device)?;
+    }
+    Ok(device)
+}
+
+fn load(path: &Path) -> Result<Vec<(u64, RegisterInfo)>, Box<dyn std::error::Error>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(ciborium::from_reader(file)?)
+}
+
+fn store(
+    cache_dir: &Path,
+    path: &Path,
+    registers: &[(u64, RegisterInfo)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(cache_dir)?;
+    // Concurrent runs must not see a half-written file
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    ciborium::into_writer(registers, BufWriter::new(File::create(&tmp)?))?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// FNV-1a, stable across runs and toolchains unlike `DefaultHasher`
+fn key<'a>(kind: &[u8], inputs: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    let inputs = inputs
+        .into_iter()
+        .flat_map(|v| v.iter().copied().chain([0]));
+    FORMAT_VERSION
+        .to_le_bytes()
+        .into_iter()
+        .chain(env!("CARGO_PKG_VERSION").bytes())
+        .chain(kind.iter().copied())
+        .chain([0])
+        .chain(inputs)
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVD: &str = r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>UART</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register>
+          <name>CR</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field><name>EN</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth></field>
+          </fields>
+        </register>
+        <register>
+          <name>DR</name>
+          <addressOffset>0x4</addressOffset>
+          <size>8</size>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+    "#;
+
+    #[test]
+    fn round_trip() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("regdoctor-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+
+        let mut db = Database::new().with_cache_dir(&cache_dir);
+        db.extend_with_svd_str(SVD, &[]).unwrap();
+        let path = cache_dir.join(format!("{:016x}.cbor", key(b"svd", [SVD.as_bytes()])));
+        assert!(path.exists());
+
+        // Served from the cache
+        let mut cached = Database::new().with_cache_dir(&cache_dir);
+        let registers = load(&path).unwrap();
+        assert_eq!(registers.len(), 2);
+        cached.extend_with_svd_str(SVD, &[]).unwrap();
+        let identifiers = |db: &Database| {
+            db.regs
+                .values()
+                .map(|v| (v.identifier(), v.size()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(identifiers(&cached), identifiers(&db));
+        let cr = cached.get_register(0x40000000).unwrap();
+        assert_eq!(cr.decode_value(1).fields[0].info.name, "EN");
+
+        // Broken cache falls back to parsing
+        std::fs::write(&path, b"garbage").unwrap();
+        let mut db = Database::new().with_cache_dir(&cache_dir);
+        db.extend_with_svd_str(SVD, &[]).unwrap();
+        assert_eq!(db.regs.len(), 2);
+        assert!(load(&path).is_ok());
+
+        // Patched SVD gets its own entry
+        let patch = Patch::from_yaml("UART: {_delete: [DR]}").unwrap();
+        let mut db = Database::new().with_cache_dir(&cache_dir);
+        db.extend_with_svd_str(SVD, &[patch.clone()]).unwrap();
+        assert_eq!(db.regs.len(), 1);
+        let inputs = [SVD.as_bytes(), patch.source().as_bytes()];
+        assert!(cache_dir
+            .join(format!("{:016x}.cbor", key(b"svd", inputs)))
+            .exists());
+
+        // Parsed devices are cached too
+        let device = parse(SVD, &[]).unwrap();
+        let mut db = Database::new().with_cache_dir(&cache_dir);
+        db.extend_with_svd(device.clone()).unwrap();
+        let mut serialized = Vec::new();
+        ciborium::into_writer(&device, &mut serialized).unwrap();
+        let path = cache_dir.join(format!("{:016x}.cbor", key(b"device", [&serialized[..]])));
+        assert_eq!(load(&path).unwrap().len(), 2);
+        let mut cached = Database::new().with_cache_dir(&cache_dir);
+        cached.extend_with_svd(device).unwrap();
+        assert_eq!(identifiers(&cached), identifiers(&db));
+
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+//! `writeConstraint` and `enumeratedValues` `usage` validation
+use std::fmt::Display;
+
+use svd_rs::{Usage, WriteConstraint, WriteConstraintRange};
+
+use crate::{AccessKind, Field, Register};
+
+/// Field value the SVD does not allow for the access
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// Written value outside of the `writeConstraint` range
+    OutOfRange {
+        field: String,
+        value: u64,
+        min: u64,
+        max: u64,
+    },
+    /// None of the enumerated values applicable to the access matches
+    NoEnumeratedValue {
+        field: String,
+        value: u64,
+        kind: AccessKind,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::OutOfRange {
+                field,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{field} = {value:#x} is outside of the allowed range {min:#x}..={max:#x}"
+            ),
+            Violation::NoEnumeratedValue { field, value, kind } => {
+                let usage = match kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                };
+                write!(
+                    f,
+                    "{field} = {value:#x} has no enumerated value for {usage}"
+                )
+            }
+        }
+    }
+}
+
+impl Register {
+    /// Decoded fields holding values the SVD does not allow for this kind of access
+    ///
+    /// Enumerated values are looked up only in the sets whose `usage` covers the access.
+    /// A write additionally has to satisfy the field's `writeConstraint`.
+    pub fn violations(&self, kind: AccessKind) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for field in self.fields.iter() {
+            let write_constraint = match kind {
+                AccessKind::Write => field.info.write_constraint,
+                AccessKind::Read => None,
+            };
+            if let Some(WriteConstraint::Range(WriteConstraintRange { min, max })) =
+                write_constraint
+            {
+                if !(min..=max).contains(&field.value) {
+                    violations.push(Violation::OutOfRange {
+                        field: field.info.name.clone(),
+                        value: field.value,
+                        min,
+                        max,
+                    });
+                }
+            }
+            let enumerated_values_required =
+                write_constraint == Some(WriteConstraint::UseEnumeratedValues(true));
+            match matches_enumerated_value(field, kind) {
+                Some(true) => {}
+                // No enumerated values for this usage, fine unless the constraint demands them
+                None if !enumerated_values_required => {}
+                Some(false) | None => violations.push(Violation::NoEnumeratedValue {
+                    field: field.info.name.clone(),
+                    value: field.value,
+                    kind,
+                }),
+            }
+        }
+        violations
+    }
+}
+
+/// `None` if the field has no enumerated values applicable to the access
+fn matches_enumerated_value(field: &Field, kind: AccessKind) -> Option<bool> {
+    let mut applicable = field
+        .info
+        .enumerated_values
+        .iter()
+        .filter(|v| match (v.usage.unwrap_or_default(), kind) {
+            (Usage::ReadWrite, _) => true,
+            (Usage::Read, AccessKind::Read) => true,
+            (Usage::Write, AccessKind::Write) => true,
+            _ => false,
+        })
+        .flat_map(|v| v.values.iter())
+        .peekable();
+    applicable.peek()?;
+    Some(applicable.any(|variant| match variant.value {
+        Some(value) => value == field.value,
+        None => variant.is_default(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+
+    #[test]
+    fn violations() {
+        let device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>TIM</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register>
+          <name>CR</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field>
+              <name>PSC</name><bitOffset>0</bitOffset><bitWidth>4</bitWidth>
+              <writeConstraint><range><minimum>1</minimum><maximum>9</maximum></range></writeConstraint>
+            </field>
+            <field>
+              <name>MODE</name><bitOffset>4</bitOffset><bitWidth>2</bitWidth>
+              <writeConstraint><useEnumeratedValues>true</useEnumeratedValues></writeConstraint>
+              <enumeratedValues>
+                <usage>write</usage>
+                <enumeratedValue><name>UP</name><value>0</value></enumeratedValue>
+                <enumeratedValue><name>DOWN</name><value>1</value></enumeratedValue>
+              </enumeratedValues>
+              <enumeratedValues>
+                <usage>read</usage>
+                <enumeratedValue><name>UP</name><value>0</value></enumeratedValue>
+                <enumeratedValue><name>DOWN</name><value>1</value></enumeratedValue>
+                <enumeratedValue><name>STOPPED</name><value>3</value></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field>
+              <name>CH</name><bitOffset>8</bitOffset><bitWidth>2</bitWidth>
+              <writeConstraint><useEnumeratedValues>true</useEnumeratedValues></writeConstraint>
+            </field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        let db = Database::from_svd(device).unwrap();
+        let cr = db.get_register(0x40000000).unwrap();
+        let violations = |kind, value| cr.decode_value(value).violations(kind);
+
+        // STOPPED can be read, CH has no enumerated values to read
+        assert_eq!(violations(AccessKind::Read, 0x31), []);
+        assert_eq!(
+            violations(AccessKind::Write, 0x30),
+            [
+                Violation::OutOfRange {
+                    field: "PSC".into(),
+                    value: 0,
+                    min: 1,
+                    max: 9
+                },
+                Violation::NoEnumeratedValue {
+                    field: "MODE".into(),
+                    value: 3,
+                    kind: AccessKind::Write
+                },
+                Violation::NoEnumeratedValue {
+                    field: "CH".into(),
+                    value: 0,
+                    kind: AccessKind::Write
+                },
+            ]
+        );
+        assert_eq!(
+            violations(AccessKind::Read, 0x20),
+            [Violation::NoEnumeratedValue {
+                field: "MODE".into(),
+                value: 2,
+                kind: AccessKind::Read
+            }]
+        );
+    }
+}
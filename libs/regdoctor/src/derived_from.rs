@@ -0,0 +1,516 @@
+//! `derivedFrom` resolution
+//!
+//! https://arm-software.github.io/CMSIS_5/SVD/html/elem_registers.html#elem_derivedFrom
+//!
+//! Derived element keeps whatever it specifies itself, the gaps are filled from the base.
+//! References are either relative to the scope of the derived element (e.g. a sibling register)
+//! or dotted paths starting at the peripheral (`PERIPHERAL.CLUSTER.REGISTER.FIELD`).
+//!
+//! Resolution runs in passes until nothing is left. An element is only derived once its base
+//! is completely resolved, so chains of any length work. A pass without any progress means
+//! that the remaining elements derive from each other in a cycle.
+use std::ops::{Deref, DerefMut};
+
+use svd_rs::{
+    ClusterInfo, EnumeratedValues, FieldInfo, PeripheralInfo, PeripheralInfoBuilder,
+    RegisterCluster, RegisterInfo,
+};
+
+use crate::Error;
+
+pub(crate) fn expand(mut device: svd_rs::Device) -> Result<svd_rs::Device, Error> {
+    loop {
+        let snapshot = device.clone();
+        let mut resolver = Resolver {
+            snapshot: &snapshot,
+            progress: 0,
+            pending: Vec::new(),
+            merged: false,
+        };
+        resolver.peripherals(&mut device.peripherals);
+        if resolver.pending.is_empty() && !resolver.merged {
+            return Ok(device);
+        }
+        if resolver.progress == 0 {
+            return Err(Error::DerivedFromCycle(resolver.pending));
+        }
+    }
+}
+
+/// Anything in the device tree a path can point at
+#[derive(Copy, Clone)]
+enum Node<'a> {
+    Peripheral(&'a PeripheralInfo),
+    Cluster(&'a ClusterInfo),
+    Register(&'a RegisterInfo),
+    Field(&'a FieldInfo),
+    EnumeratedValues(&'a EnumeratedValues),
+}
+
+fn lookup<'a>(device: &'a svd_rs::Device, path: &[&str]) -> Option<Node<'a>> {
+    let (first, rest) = path.split_first()?;
+    let peripheral = device.peripherals.iter().find(|v| v.name == *first)?;
+    rest.iter()
+        .try_fold(Node::Peripheral(peripheral), |node, name| match node {
+            Node::Peripheral(PeripheralInfo {
+                registers: Some(children),
+                ..
+            })
+            | Node::Cluster(ClusterInfo { children, .. }) => {
+                children.iter().find_map(|child| match child {
+                    RegisterCluster::Register(v) if v.name == *name => Some(Node::Register(v)),
+                    RegisterCluster::Cluster(v) if v.name == *name => Some(Node::Cluster(v)),
+                    _ => None,
+                })
+            }
+            Node::Register(register) => register
+                .fields()
+                .find(|v| v.name == *name)
+                .map(|v| Node::Field(v)),
+            Node::Field(field) => field
+                .enumerated_values
+                .iter()
+                .find(|v| v.name.as_deref() == Some(*name))
+                .map(Node::EnumeratedValues),
+            _ => None,
+        })
+}
+
+trait Derivable: Clone {
+    fn derived_from(&self) -> Option<&String>;
+    /// Anything within still waiting for its base
+    fn is_unresolved(&self) -> bool {
+        self.derived_from().is_some()
+    }
+    fn clear_derived_from(&mut self);
+    fn from_node(node: Node<'_>) -> Option<&Self>;
+    /// `derived` with the gaps filled from `base`
+    fn merge(base: &Self, derived: Self) -> Self;
+}
+
+struct Resolver<'a> {
+    snapshot: &'a svd_rs::Device,
+    progress: usize,
+    pending: Vec<String>,
+    /// Children copied from a base are only visible in the snapshot of the next pass
+    merged: bool,
+}
+
+impl Resolver<'_> {
+    fn peripherals(&mut self, peripherals: &mut [svd_rs::Peripheral]) {
+        for peripheral in peripherals {
+            let scope = [peripheral.name.clone()];
+            if !self.derive(&[], &scope[0], peripheral.deref_mut()) {
+                continue;
+            }
+            if let Some(children) = &mut peripheral.registers {
+                self.children(&scope, children);
+            }
+        }
+    }
+
+    fn children(&mut self, scope: &[String], children: &mut [RegisterCluster]) {
+        for child in children {
+            match child {
+                RegisterCluster::Register(register) => {
+                    if !self.derive(scope, &register.name.clone(), register.deref_mut()) {
+                        continue;
+                    }
+                    let scope = [scope, &[register.name.clone()]].concat();
+                    for field in register.fields.iter_mut().flatten() {
+                        if !self.derive(&scope, &field.name.clone(), field.deref_mut()) {
+                            continue;
+                        }
+                        let scope = [&scope[..], &[field.name.clone()]].concat();
+                        for enumerated_values in field.enumerated_values.iter_mut() {
+                            let name = enumerated_values.name.clone().unwrap_or_default();
+                            self.derive(&scope, &name, enumerated_values);
+                        }
+                    }
+                }
+                RegisterCluster::Cluster(cluster) => {
+                    if !self.derive(scope, &cluster.name.clone(), cluster.deref_mut()) {
+                        continue;
+                    }
+                    let scope = [scope, &[cluster.name.clone()]].concat();
+                    self.children(&scope, &mut cluster.children);
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if `item` is settled and its children can be looked at in this pass
+    fn derive<T: Derivable>(&mut self, scope: &[String], name: &str, item: &mut T) -> bool {
+        let Some(derived_from) = item.derived_from() else {
+            return true;
+        };
+        let path = [scope, &[name.to_owned()]].concat().join(".");
+        let reference: Vec<&str> = derived_from.split('.').collect();
+        let scope: Vec<&str> = scope.iter().map(Deref::deref).collect();
+        let base = (0..=scope.len())
+            .rev()
+            .map(|depth| [&scope[..depth], &reference].concat())
+            .find_map(|path| lookup(self.snapshot, &path).and_then(T::from_node))
+            .or_else(|| self.anywhere_in_peripheral(&scope, derived_from));
+        match base {
+            None => {
+                log::warn!(
+                    "{path} derived from {derived_from} but the latter does not exist? Skipping"
+                );
+                item.clear_derived_from();
+                self.progress += 1;
+                true
+            }
+            // Copying has to wait for the base to be complete, references inside of it
+            // are relative to the base
+            Some(base) if base.is_unresolved() => {
+                self.pending.push(path);
+                false
+            }
+            Some(base) => {
+                let mut merged = T::merge(base, item.clone());
+                merged.clear_derived_from();
+                *item = merged;
+                self.progress += 1;
+                self.merged = true;
+                false
+            }
+        }
+    }
+
+    /// `enumeratedValues` are commonly referenced just by their name from any field of the peripheral
+    fn anywhere_in_peripheral<T: Derivable>(&self, scope: &[&str], name: &str) -> Option<&T> {
+        fn search<'a, T: Derivable>(children: &'a [RegisterCluster], name: &str) -> Option<&'a T> {
+            children.iter().find_map(|child| match child {
+                RegisterCluster::Register(register) => register
+                    .fields()
+                    .flat_map(|v| v.enumerated_values.iter())
+                    .filter(|v| v.name.as_deref() == Some(name))
+                    .find_map(|v| T::from_node(Node::EnumeratedValues(v))),
+                RegisterCluster::Cluster(cluster) => search(&cluster.children, name),
+            })
+        }
+        let peripheral = lookup(self.snapshot, scope.get(..1)?)?;
+        match peripheral {
+            Node::Peripheral(PeripheralInfo {
+                registers: Some(children),
+                ..
+            }) => search(children, name),
+            _ => None,
+        }
+    }
+}
+
+fn children_unresolved(children: &[RegisterCluster]) -> bool {
+    children.iter().any(|child| match child {
+        RegisterCluster::Register(v) => v.is_unresolved(),
+        RegisterCluster::Cluster(v) => v.is_unresolved(),
+    })
+}
+
+fn fill<T: Clone>(value: &mut Option<T>, base: &Option<T>) {
+    if value.is_none() {
+        value.clone_from(base);
+    }
+}
+
+impl Derivable for PeripheralInfo {
+    fn derived_from(&self) -> Option<&String> {
+        self.derived_from.as_ref()
+    }
+
+    fn is_unresolved(&self) -> bool {
+        self.derived_from.is_some() || self.registers.as_deref().is_some_and(children_unresolved)
+    }
+
+    fn clear_derived_from(&mut self) {
+        self.derived_from = None;
+    }
+
+    fn from_node(node: Node<'_>) -> Option<&Self> {
+        match node {
+            Node::Peripheral(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn merge(base: &Self, mut derived: Self) -> Self {
+        let derived_as_builder = PeripheralInfoBuilder::from(derived.clone()).derived_from(None);
+        let mut base = base.clone();
+        base.modify_from(derived_as_builder, Default::default())
+            .unwrap();
+        derived
+            .modify_from(base.into(), Default::default())
+            .unwrap();
+        derived
+    }
+}
+
+impl Derivable for ClusterInfo {
+    fn derived_from(&self) -> Option<&String> {
+        self.derived_from.as_ref()
+    }
+
+    fn is_unresolved(&self) -> bool {
+        self.derived_from.is_some() || children_unresolved(&self.children)
+    }
+
+    fn clear_derived_from(&mut self) {
+        self.derived_from = None;
+    }
+
+    fn from_node(node: Node<'_>) -> Option<&Self> {
+        match node {
+            Node::Cluster(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn merge(base: &Self, mut derived: Self) -> Self {
+        fill(&mut derived.description, &base.description);
+        fill(&mut derived.alternate_cluster, &base.alternate_cluster);
+        fill(&mut derived.header_struct_name, &base.header_struct_name);
+        derived.default_register_properties = crate::inherit(
+            &derived.default_register_properties,
+            &base.default_register_properties,
+        );
+        if derived.children.is_empty() {
+            derived.children.clone_from(&base.children);
+        }
+        derived
+    }
+}
+
+impl Derivable for RegisterInfo {
+    fn derived_from(&self) -> Option<&String> {
+        self.derived_from.as_ref()
+    }
+
+    fn is_unresolved(&self) -> bool {
+        self.derived_from.is_some() || self.fields().any(|v| v.is_unresolved())
+    }
+
+    fn clear_derived_from(&mut self) {
+        self.derived_from = None;
+    }
+
+    fn from_node(node: Node<'_>) -> Option<&Self> {
+        match node {
+            Node::Register(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn merge(base: &Self, mut derived: Self) -> Self {
+        fill(&mut derived.display_name, &base.display_name);
+        fill(&mut derived.description, &base.description);
+        fill(&mut derived.alternate_group, &base.alternate_group);
+        fill(&mut derived.alternate_register, &base.alternate_register);
+        derived.properties = crate::inherit(&derived.properties, &base.properties);
+        fill(&mut derived.datatype, &base.datatype);
+        fill(
+            &mut derived.modified_write_values,
+            &base.modified_write_values,
+        );
+        fill(&mut derived.write_constraint, &base.write_constraint);
+        fill(&mut derived.read_action, &base.read_action);
+        fill(&mut derived.fields, &base.fields);
+        derived
+    }
+}
+
+impl Derivable for FieldInfo {
+    fn derived_from(&self) -> Option<&String> {
+        self.derived_from.as_ref()
+    }
+
+    fn is_unresolved(&self) -> bool {
+        self.derived_from.is_some() || self.enumerated_values.iter().any(|v| v.is_unresolved())
+    }
+
+    fn clear_derived_from(&mut self) {
+        self.derived_from = None;
+    }
+
+    fn from_node(node: Node<'_>) -> Option<&Self> {
+        match node {
+            Node::Field(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn merge(base: &Self, mut derived: Self) -> Self {
+        fill(&mut derived.description, &base.description);
+        fill(&mut derived.access, &base.access);
+        fill(
+            &mut derived.modified_write_values,
+            &base.modified_write_values,
+        );
+        fill(&mut derived.write_constraint, &base.write_constraint);
+        fill(&mut derived.read_action, &base.read_action);
+        if derived.enumerated_values.is_empty() {
+            derived
+                .enumerated_values
+                .clone_from(&base.enumerated_values);
+        }
+        derived
+    }
+}
+
+impl Derivable for EnumeratedValues {
+    fn derived_from(&self) -> Option<&String> {
+        self.derived_from.as_ref()
+    }
+
+    fn clear_derived_from(&mut self) {
+        self.derived_from = None;
+    }
+
+    fn from_node(node: Node<'_>) -> Option<&Self> {
+        match node {
+            Node::EnumeratedValues(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn merge(base: &Self, mut derived: Self) -> Self {
+        fill(&mut derived.name, &base.name);
+        fill(&mut derived.usage, &base.usage);
+        if derived.values.is_empty() {
+            derived.values.clone_from(&base.values);
+        }
+        derived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(peripherals: &str) -> svd_rs::Device {
+        svd_parser::parse(&format!(
+            "<device><name>DEV</name><peripherals>{peripherals}</peripherals></device>"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn chained_and_nested() {
+        let device = parse(
+            r#"
+<peripheral derivedFrom="UART1">
+  <name>UART2</name>
+  <baseAddress>0x40002000</baseAddress>
+</peripheral>
+<peripheral derivedFrom="UART0">
+  <name>UART1</name>
+  <baseAddress>0x40001000</baseAddress>
+</peripheral>
+<peripheral>
+  <name>UART0</name>
+  <baseAddress>0x40000000</baseAddress>
+  <registers>
+    <register>
+      <name>CTRL</name>
+      <addressOffset>0x0</addressOffset>
+      <fields>
+        <field>
+          <name>EN</name>
+          <bitOffset>0</bitOffset>
+          <bitWidth>1</bitWidth>
+          <enumeratedValues>
+            <name>ENABLE</name>
+            <enumeratedValue><name>off</name><value>0</value></enumeratedValue>
+            <enumeratedValue><name>on</name><value>1</value></enumeratedValue>
+          </enumeratedValues>
+        </field>
+        <field derivedFrom="EN">
+          <name>TXEN</name>
+          <bitOffset>1</bitOffset>
+          <bitWidth>1</bitWidth>
+        </field>
+      </fields>
+    </register>
+    <register derivedFrom="CTRL">
+      <name>CTRL_SET</name>
+      <addressOffset>0x4</addressOffset>
+    </register>
+    <cluster>
+      <name>FIFO</name>
+      <addressOffset>0x10</addressOffset>
+      <register>
+        <name>STAT</name>
+        <addressOffset>0x0</addressOffset>
+        <fields>
+          <field>
+            <name>RXEN</name>
+            <bitOffset>0</bitOffset>
+            <bitWidth>1</bitWidth>
+            <enumeratedValues derivedFrom="ENABLE"></enumeratedValues>
+          </field>
+        </fields>
+      </register>
+    </cluster>
+    <cluster derivedFrom="FIFO">
+      <name>FIFO2</name>
+      <addressOffset>0x20</addressOffset>
+    </cluster>
+  </registers>
+</peripheral>
+<peripheral>
+  <name>OTHER</name>
+  <baseAddress>0x50000000</baseAddress>
+  <registers>
+    <register derivedFrom="UART0.FIFO.STAT">
+      <name>STAT</name>
+      <addressOffset>0x0</addressOffset>
+    </register>
+  </registers>
+</peripheral>
+"#,
+        );
+        let device = expand(device).unwrap();
+        let uart2 = device.get_peripheral("UART2").unwrap();
+        assert_eq!(uart2.base_address, 0x40002000);
+        assert!(uart2.get_register("CTRL_SET").is_some());
+        let ctrl = uart2.get_register("CTRL").unwrap();
+        let txen = ctrl.get_field("TXEN").unwrap();
+        assert_eq!(txen.bit_offset(), 1);
+        assert_eq!(txen.enumerated_values[0].values[1].name, "on");
+        let ctrl_set = uart2.get_register("CTRL_SET").unwrap();
+        assert_eq!(ctrl_set.address_offset, 4);
+        assert_eq!(ctrl_set.fields().count(), 2);
+        let fifo2 = uart2.get_cluster("FIFO2").unwrap();
+        let stat = fifo2.get_register("STAT").unwrap();
+        let rxen = stat.get_field("RXEN").unwrap();
+        assert_eq!(rxen.enumerated_values[0].values.len(), 2);
+        let other = device.get_peripheral("OTHER").unwrap();
+        let stat = other.get_register("STAT").unwrap();
+        assert_eq!(
+            stat.get_field("RXEN").unwrap().enumerated_values[0]
+                .values
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        let device = parse(
+            r#"
+<peripheral derivedFrom="B">
+  <name>A</name>
+  <baseAddress>0x40000000</baseAddress>
+</peripheral>
+<peripheral derivedFrom="A">
+  <name>B</name>
+  <baseAddress>0x40001000</baseAddress>
+</peripheral>
+"#,
+        );
+        assert_eq!(
+            expand(device),
+            Err(Error::DerivedFromCycle(vec!["A".into(), "B".into()]))
+        );
+    }
+}
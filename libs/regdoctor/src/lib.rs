@@ -1,136 +1,224 @@
 #![allow(warnings)]
 
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display};
-use std::ops::DerefMut;
+use std::path::PathBuf;
 use std::{collections::HashMap, ops::Deref};
 
+use serde::{Deserialize, Serialize};
 use svd_rs::PeripheralInfo;
 use svd_rs::RegisterInfo as InnerRegisterInfo;
-use svd_rs::{BitRange, EnumeratedValue};
-use svd_rs::{FieldInfo, PeripheralInfoBuilder, ValidateLevel};
+use svd_rs::{
+    BitRange, ClusterInfo, EnumeratedValue, MaybeArray, RegisterCluster, RegisterProperties,
+};
+use svd_rs::{FieldInfo, ValidateLevel};
+
+mod access;
+mod cache;
+mod constraint;
+mod derived_from;
+mod lookup;
+mod patch;
+mod ser;
+
+pub use access::{AccessKind, Anomaly};
+pub use constraint::Violation;
+pub use lookup::Lookup;
+pub use patch::Patch;
+pub use svd_rs::Access;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Paths of elements that could not be resolved
+    DerivedFromCycle(Vec<String>),
+    /// SVD file could not be parsed
+    Parse(String),
+    /// Malformed SVD patch
+    Patch(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::DerivedFromCycle(paths) => {
+                write!(f, "derivedFrom cycle among: {}", paths.join(", "))
+            }
+            Error::Parse(error) => write!(f, "SVD parsing failed: {error}"),
+            Error::Patch(error) => write!(f, "SVD patch: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Widest register that can be decoded, values are `u64`
+const MAX_REGISTER_BYTES: u64 = 8;
 
 pub struct Database {
-    pub regs: HashMap<u64, RegisterInfo>,
+    pub regs: BTreeMap<u64, RegisterInfo>,
+    cache_dir: Option<PathBuf>,
 }
 
 impl Database {
     pub fn new() -> Self {
         Self {
             regs: Default::default(),
+            cache_dir: None,
         }
     }
 
+    /// Register starting exactly at `address`
     pub fn get_register(&self, address: u64) -> Option<&RegisterInfo> {
         self.regs.get(&address)
     }
 
-    pub fn from_svd(device: svd_rs::Device) -> Self {
+    /// Registers overlapping an access of `len` bytes at `address`, in address order
+    ///
+    /// Catches sub-word accesses in the middle of a register (e.g. byte access to `base+1`)
+    /// as well as accesses to the upper part of registers wider than the access.
+    pub fn get_registers(&self, address: u64, len: u32) -> impl Iterator<Item = RegisterSlice<'_>> {
+        let end = address.saturating_add(len as u64);
+        self.regs
+            .range(address.saturating_sub(MAX_REGISTER_BYTES - 1)..end)
+            .filter_map(move |(&register_address, info)| {
+                let register_end = register_address + info.size_bytes() as u64;
+                let start = register_address.max(address);
+                let stop = register_end.min(end);
+                (start < stop).then(|| RegisterSlice {
+                    info,
+                    register_offset: (start - register_address) as u32,
+                    access_offset: (start - address) as u32,
+                    len: (stop - start) as u32,
+                })
+            })
+    }
+
+    pub fn from_svd(device: svd_rs::Device) -> Result<Self, Error> {
         let mut db = Self::new();
-        db.extend_with_svd(device);
-        db
-    }
-
-    pub fn extend_with_svd(&mut self, device: svd_rs::Device) {
-        let device = Self::expand_derived_from_attribute(device);
-        for peripheral in device.peripherals.into_iter() {
-            let peripheral = peripheral.deref();
-            let base_address = peripheral.base_address;
-            for register in peripheral.registers() {
-                let register = register.deref();
-                let address = base_address + register.address_offset as u64;
-                let register_desc = RegisterInfo {
-                    device_name: device.name.clone(),
-                    peripheral_name: peripheral.name.clone(),
-                    cluster_name: None,
-                    inner: register.clone(),
-                };
-                let identifier = register_desc.identifier();
-                match self.regs.insert(address, register_desc) {
-                    Some(previous) => {
-                        log::info!(
-                            "Address collision: [{}] overwrites [{}]",
-                            identifier,
-                            previous.identifier(),
-                        );
-                    }
-                    None => {}
-                }
+        db.extend_with_svd(device)?;
+        Ok(db)
+    }
+
+    /// Extend the database with the registers of `device`
+    ///
+    /// With a cache directory set, the expansion is skipped if the same device was seen before.
+    pub fn extend_with_svd(&mut self, device: svd_rs::Device) -> Result<(), Error> {
+        match self.cache_dir.clone() {
+            Some(cache_dir) => self.extend_with_cached_svd(&cache_dir, device),
+            None => self.extend_with_device(device),
+        }
+    }
+
+    fn extend_with_device(&mut self, device: svd_rs::Device) -> Result<(), Error> {
+        let device = Self::expand_derived_from_attribute(device)?;
+        for peripheral in device.peripherals.iter() {
+            let peripherals: Vec<PeripheralInfo> = match peripheral {
+                MaybeArray::Single(info) => vec![info.clone()],
+                MaybeArray::Array(info, dim) => svd_rs::peripheral::expand(info, dim).collect(),
+            };
+            for peripheral in peripherals {
+                let properties = inherit(
+                    &peripheral.default_register_properties,
+                    &device.default_register_properties,
+                );
+                let children = peripheral.registers.as_deref().unwrap_or_default();
+                self.extend_with_children(
+                    &device.name,
+                    &peripheral.name,
+                    None,
+                    peripheral.base_address,
+                    children,
+                    &properties,
+                );
             }
-            for cluster in peripheral.clusters() {
-                let cluster = cluster.deref();
-                let base_address = base_address + cluster.address_offset as u64;
-                for register in cluster.registers() {
-                    let register = register.deref();
-                    let address = base_address + register.address_offset as u64;
-                    let register_desc = RegisterInfo {
-                        device_name: device.name.clone(),
-                        peripheral_name: peripheral.name.clone(),
-                        cluster_name: Some(cluster.name.clone()),
-                        inner: register.clone(),
+        }
+        Ok(())
+    }
+
+    /// Registers and (possibly nested) clusters with `dim` arrays expanded
+    fn extend_with_children(
+        &mut self,
+        device_name: &str,
+        peripheral_name: &str,
+        cluster_name: Option<&str>,
+        base_address: u64,
+        children: &[RegisterCluster],
+        properties: &RegisterProperties,
+    ) {
+        for child in children {
+            match child {
+                RegisterCluster::Register(register) => {
+                    let registers: Vec<InnerRegisterInfo> = match register {
+                        MaybeArray::Single(info) => vec![info.clone()],
+                        MaybeArray::Array(info, dim) => {
+                            svd_rs::register::expand(info, dim).collect()
+                        }
                     };
-                    let identifier = register_desc.identifier();
-                    match self.regs.insert(address, register_desc) {
-                        Some(previous) => {
-                            log::info!(
-                                "Address collision: [{}] overwrites [{}]",
-                                identifier,
-                                previous.identifier(),
-                            );
+                    for register in registers {
+                        let address = base_address + register.address_offset as u64;
+                        let register_desc = RegisterInfo {
+                            device_name: device_name.to_owned(),
+                            peripheral_name: peripheral_name.to_owned(),
+                            cluster_name: cluster_name.map(ToOwned::to_owned),
+                            properties: inherit(&register.properties, properties),
+                            inner: register,
+                        };
+                        self.insert(address, register_desc);
+                    }
+                }
+                RegisterCluster::Cluster(cluster) => {
+                    let clusters: Vec<ClusterInfo> = match cluster {
+                        MaybeArray::Single(info) => vec![info.clone()],
+                        MaybeArray::Array(info, dim) => {
+                            svd_rs::cluster::expand(info, dim).collect()
                         }
-                        None => {}
+                    };
+                    for cluster in clusters {
+                        let name = match cluster_name {
+                            Some(parent) => format!("{parent}.{}", cluster.name),
+                            None => cluster.name.clone(),
+                        };
+                        self.extend_with_children(
+                            device_name,
+                            peripheral_name,
+                            Some(&name),
+                            base_address + cluster.address_offset as u64,
+                            &cluster.children,
+                            &inherit(&cluster.default_register_properties, properties),
+                        );
                     }
                 }
             }
         }
     }
 
-    fn expand_derived_from_attribute(mut device: svd_rs::Device) -> svd_rs::Device {
-        let device_copy = device.clone();
-        for peripheral in device
-            .peripherals
-            .iter_mut()
-            .filter(|v| v.derived_from.is_some())
-        {
-            let peripheral = peripheral.deref_mut();
-            let derived_from = peripheral.derived_from.as_ref().unwrap();
-            let Some(derived_from_peripheral) = device_copy.get_peripheral(derived_from) else {
-                log::warn!(
-                    "{} derived from {} but the latter does not exist? Skipping",
-                    &peripheral.name,
-                    derived_from
+    fn insert(&mut self, address: u64, register_desc: RegisterInfo) {
+        let identifier = register_desc.identifier();
+        match self.regs.insert(address, register_desc) {
+            Some(previous) => {
+                log::info!(
+                    "Address collision: [{}] overwrites [{}]",
+                    identifier,
+                    previous.identifier(),
                 );
-                continue;
-            };
-            let peripheral_as_builder =
-                PeripheralInfoBuilder::from(peripheral.clone()).derived_from(None);
-            let mut derived_from_peripheral = derived_from_peripheral.deref().clone();
-            derived_from_peripheral
-                .modify_from(peripheral_as_builder, Default::default())
-                .unwrap();
-            peripheral
-                .modify_from(derived_from_peripheral.into(), Default::default())
-                .unwrap();
-            peripheral.derived_from = None;
-        }
-        if device
-            .peripherals
-            .iter()
-            .filter(|v| v.derived_from.is_some())
-            .count()
-            > 0
-        {
-            log::warn!("Multi-pass derivedFrom peripherals expansion is not implemented, YMMV.");
+            }
+            None => {}
         }
-        device
+    }
+
+    fn expand_derived_from_attribute(device: svd_rs::Device) -> Result<svd_rs::Device, Error> {
+        derived_from::expand(device)
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RegisterInfo {
     device_name: String,
     peripheral_name: String,
-    /// Name of the cluster if it belongs to one
+    /// Name of the cluster if it belongs to one, dot-separated path for nested clusters
     cluster_name: Option<String>,
+    /// Register properties with cluster/peripheral/device defaults applied
+    properties: RegisterProperties,
     inner: InnerRegisterInfo,
 }
 
@@ -142,14 +230,105 @@ impl RegisterInfo {
         };
         format!("{}.{}.{}", self.device_name, self.peripheral_name, name,)
     }
+
+    /// Width in bits, 32 unless specified otherwise
+    pub fn size(&self) -> u32 {
+        self.properties.size.unwrap_or(32)
+    }
+
+    fn size_bytes(&self) -> u32 {
+        self.size().div_ceil(8).clamp(1, MAX_REGISTER_BYTES as u32)
+    }
+
+    /// `resetValue`, possibly inherited from the cluster, peripheral or device
+    pub fn reset_value(&self) -> Option<u64> {
+        self.properties.reset_value
+    }
+
+    /// Bits with a defined reset value, all of them unless `resetMask` says otherwise
+    pub fn reset_mask(&self) -> u64 {
+        let size_mask = u64::MAX.checked_shr(64 - self.size()).unwrap_or(0);
+        self.properties.reset_mask.unwrap_or(size_mask) & size_mask
+    }
+}
+
+/// Part of a register touched by a memory access
+#[derive(Copy, Clone)]
+pub struct RegisterSlice<'a> {
+    pub info: &'a RegisterInfo,
+    /// Offset of the first touched byte lane within the register
+    pub register_offset: u32,
+    /// Offset of the first touched byte lane within the access
+    pub access_offset: u32,
+    /// Number of touched byte lanes
+    pub len: u32,
+}
+
+impl<'a> RegisterSlice<'a> {
+    pub fn is_whole(&self) -> bool {
+        self.register_offset == 0 && self.len == self.info.size_bytes()
+    }
+
+    /// Register identifier, suffixed with the touched bit range if the access is partial
+    pub fn identifier(&self) -> String {
+        let identifier = self.info.identifier();
+        if self.is_whole() {
+            return identifier;
+        }
+        let low = self.register_offset * 8;
+        let high = low + self.len * 8 - 1;
+        format!("{identifier}[{high}:{low}]")
+    }
+
+    /// Register bits covered by the access
+    pub fn mask(&self) -> u64 {
+        lanes_mask(self.register_offset, self.len)
+    }
+
+    /// Decode `value` as seen by the access
+    ///
+    /// Only fields lying entirely within the touched byte lanes are decoded.
+    pub fn decode_value(&self, value: u64) -> Register {
+        self.info
+            .decode_masked(self.to_register(value), self.mask())
+    }
+
+    /// Move the touched byte lanes of an access value to their place in the register
+    fn to_register(&self, value: u64) -> u64 {
+        let value =
+            value.checked_shr(self.access_offset * 8).unwrap_or(0) & lanes_mask(0, self.len);
+        value << (self.register_offset * 8)
+    }
+}
+
+/// `properties` with the gaps filled from `defaults`
+fn inherit(properties: &RegisterProperties, defaults: &RegisterProperties) -> RegisterProperties {
+    let mut inherited = defaults.clone();
+    inherited
+        .modify_from(properties.clone(), ValidateLevel::Disabled)
+        .unwrap();
+    inherited
+}
+
+fn lanes_mask(offset: u32, len: u32) -> u64 {
+    let mask = u64::MAX.checked_shr(64 - len * 8).unwrap_or(0);
+    mask << (offset * 8)
 }
 
 impl RegisterInfo {
     pub fn decode_value(&self, value: u64) -> Register {
+        self.decode_masked(value, u64::MAX)
+    }
+
+    /// Decode fields fully covered by `mask`
+    fn decode_masked(&self, value: u64, mask: u64) -> Register {
         let mut fields = Vec::new();
         'fields: for field in self.inner.fields() {
             let field = field.deref();
             let BitRange { offset, width, .. } = field.bit_range;
+            if field_mask(field) & !mask != 0 {
+                continue;
+            }
             let field_value = to_field_value(value, offset, width);
             // https://arm-software.github.io/CMSIS_5/SVD/html/elem_registers.html#elem_enumeratedValue
             //
@@ -206,19 +385,28 @@ impl RegisterInfo {
         Register {
             info: self.clone(),
             value,
+            mask,
             fields,
         }
     }
 }
 
+/// Register bits occupied by the field
+fn field_mask(field: &FieldInfo) -> u64 {
+    let BitRange { offset, width, .. } = field.bit_range;
+    u64::MAX.checked_shr(64 - width).unwrap_or(0) << offset
+}
+
 fn to_field_value(value: u64, offset: u32, width: u32) -> u64 {
-    let mask = !(u64::MAX >> width << width);
-    (value >> offset) & mask
+    let mask = u64::MAX.checked_shr(64 - width).unwrap_or(0);
+    value.checked_shr(offset).unwrap_or(0) & mask
 }
 
 pub struct Register {
     info: RegisterInfo,
     value: u64,
+    /// Bits covered by the decoded access
+    mask: u64,
     // Should be sorted by their bit offset
     fields: Vec<Field>,
 }
@@ -252,7 +440,15 @@ impl Debug for Register {
 #[derive(Debug, Copy, Clone)]
 pub struct WrongRegister;
 
+#[derive(Debug, Copy, Clone)]
+pub struct NoResetValue;
+
 impl Register {
+    /// Decoded fields, sorted by their bit offset
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
     pub fn diff_from_nothing<'a>(&'a self) -> RegisterDiffFromNothing<'a> {
         RegisterDiffFromNothing(self)
     }
@@ -276,7 +472,7 @@ impl Register {
                 if old.value != new.value {
                     Some(FieldDiff {
                         info: old.info.clone(),
-                        old: old.value,
+                        old: Some(old.value),
                         new: new.value,
                         old_variant: old.variant.clone(),
                         new_variant: new.variant.clone(),
@@ -287,14 +483,51 @@ impl Register {
             })
             .collect();
         Ok(Some(RegisterDiff {
+            identifier: old.info.identifier(),
             old: old.value,
             new: new.value,
             fields,
         }))
     }
+
+    /// Diff from the SVD reset state instead of from nothing
+    ///
+    /// Only fields differing from their reset value are listed. Fields reaching outside of
+    /// `resetMask` have no defined reset value, these are always listed.
+    pub fn diff_from_reset(&self) -> Result<Option<RegisterDiff>, NoResetValue> {
+        let reset_mask = self.info.reset_mask() & self.mask;
+        let reset_value = self.info.reset_value().ok_or(NoResetValue)? & reset_mask;
+        let reset = self.info.decode_masked(reset_value, self.mask);
+        let fields: Vec<_> = reset
+            .fields
+            .iter()
+            .zip(self.fields.iter())
+            .filter_map(|(old, new)| {
+                let known = field_mask(&old.info) & !reset_mask == 0;
+                let old_value = known.then_some(old.value);
+                (old_value != Some(new.value)).then(|| FieldDiff {
+                    info: old.info.clone(),
+                    old: old_value,
+                    new: new.value,
+                    old_variant: old.variant.clone().filter(|_| known),
+                    new_variant: new.variant.clone(),
+                })
+            })
+            .collect();
+        if fields.is_empty() && (self.value & reset_mask) == reset_value {
+            return Ok(None);
+        }
+        Ok(Some(RegisterDiff {
+            identifier: self.info.identifier(),
+            old: reset_value,
+            new: self.value,
+            fields,
+        }))
+    }
 }
 
 pub struct RegisterDiff {
+    identifier: String,
     old: u64,
     new: u64,
     fields: Vec<FieldDiff>,
@@ -304,11 +537,16 @@ impl Display for RegisterDiff {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "{:#010x} → {:#010x}", self.old, self.new)?;
         for field in self.fields.iter() {
-            write!(
-                f,
-                "  {} : {:#0x} → {:#0x}",
-                field.info.name, field.old, field.new
-            )?;
+            let Some(old) = field.old else {
+                // Unknown old state, same as `RegisterDiffFromNothing`
+                write!(f, "  {} : 0x? → {:#0x}", field.info.name, field.new)?;
+                match &field.new_variant {
+                    Some(variant) => writeln!(f, " / ? → {}", variant.name)?,
+                    None => writeln!(f)?,
+                }
+                continue;
+            };
+            write!(f, "  {} : {:#0x} → {:#0x}", field.info.name, old, field.new)?;
             match (&field.old_variant, &field.new_variant) {
                 (Some(old), Some(new)) => writeln!(f, " / {} → {}", old.name, new.name)?,
                 (None, None) => writeln!(f)?,
@@ -322,7 +560,8 @@ impl Display for RegisterDiff {
 
 pub struct FieldDiff {
     info: FieldInfo,
-    old: u64,
+    /// `None` if the field has no defined reset value
+    old: Option<u64>,
     new: u64,
     old_variant: Option<EnumeratedValue>,
     new_variant: Option<EnumeratedValue>,
@@ -772,7 +1011,7 @@ mod tests {
         ).unwrap();
         let xcache_pc_before = device.get_peripheral("XCACHE_PC").unwrap().deref().clone();
         let xcache_ps_before = device.get_peripheral("XCACHE_PS").unwrap().deref().clone();
-        let device = Database::expand_derived_from_attribute(device);
+        let device = Database::expand_derived_from_attribute(device).unwrap();
         let xcache_ps_after = device.get_peripheral("XCACHE_PS").unwrap().deref();
         assert_eq!(xcache_ps_before.registers.iter().count(), 0);
         assert_eq!(
@@ -829,7 +1068,7 @@ mod tests {
             )])
             .build(Weak)
             .unwrap();
-        let db = Database::from_svd(svd);
+        let db = Database::from_svd(svd).unwrap();
         let register_info = db.get_register(0xDEAD0004).unwrap();
         let register = register_info.decode_value(0x89abcdef);
         for (field_name, expected_value) in test_samples {
@@ -843,6 +1082,203 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sub_word_and_multi_word_lookup() {
+        let device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>UART</name>
+      <baseAddress>0x40000000</baseAddress>
+      <size>8</size>
+      <registers>
+        <register>
+          <name>DATA</name>
+          <addressOffset>0x0</addressOffset>
+        </register>
+        <register>
+          <name>STATUS</name>
+          <addressOffset>0x1</addressOffset>
+          <fields>
+            <field><name>TXE</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>RXNE</name><bitOffset>1</bitOffset><bitWidth>1</bitWidth></field>
+          </fields>
+        </register>
+        <register>
+          <name>TIMESTAMP</name>
+          <addressOffset>0x8</addressOffset>
+          <size>64</size>
+          <fields>
+            <field><name>LOW</name><bitOffset>0</bitOffset><bitWidth>32</bitWidth></field>
+            <field><name>HIGH</name><bitOffset>32</bitOffset><bitWidth>16</bitWidth></field>
+            <field><name>EPOCH</name><bitOffset>48</bitOffset><bitWidth>16</bitWidth></field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        let db = Database::from_svd(device).unwrap();
+
+        // Byte access to base+1
+        let slices: Vec<_> = db.get_registers(0x40000001, 1).collect();
+        assert_eq!(slices.len(), 1);
+        assert_eq!(slices[0].identifier(), "DEV.UART.STATUS");
+        let register = slices[0].decode_value(0b10);
+        assert_eq!(register.fields[1].value, 1);
+
+        // Word access covering both 8-bit registers
+        let slices: Vec<_> = db.get_registers(0x40000000, 4).collect();
+        let identifiers: Vec<_> = slices.iter().map(|v| v.identifier()).collect();
+        assert_eq!(identifiers, ["DEV.UART.DATA", "DEV.UART.STATUS"]);
+        assert_eq!(slices[1].access_offset, 1);
+        assert_eq!(slices[1].decode_value(0x0100).fields[0].value, 1);
+
+        // Upper word of the 64-bit register, only fields within it are decoded
+        let slices: Vec<_> = db.get_registers(0x4000000C, 4).collect();
+        assert_eq!(slices.len(), 1);
+        assert_eq!(slices[0].identifier(), "DEV.UART.TIMESTAMP[63:32]");
+        let register = slices[0].decode_value(0x0002_0001);
+        let fields: Vec<_> = register
+            .fields
+            .iter()
+            .map(|v| (v.info.name.as_str(), v.value))
+            .collect();
+        assert_eq!(fields, [("HIGH", 1), ("EPOCH", 2)]);
+
+        // Halfword straddling the end of the 64-bit register
+        assert_eq!(db.get_registers(0x4000000F, 2).count(), 1);
+        assert_eq!(db.get_registers(0x40000010, 4).count(), 0);
+    }
+
+    #[test]
+    fn reset_value_diffing() {
+        let device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <resetValue>0x00000000</resetValue>
+  <peripherals>
+    <peripheral>
+      <name>GPIO</name>
+      <baseAddress>0x40000000</baseAddress>
+      <resetValue>0x00000003</resetValue>
+      <registers>
+        <register>
+          <name>MODER</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field><name>MODE0</name><bitOffset>0</bitOffset><bitWidth>2</bitWidth></field>
+            <field><name>MODE1</name><bitOffset>2</bitOffset><bitWidth>2</bitWidth></field>
+          </fields>
+        </register>
+        <register>
+          <name>IDR</name>
+          <addressOffset>0x4</addressOffset>
+          <resetMask>0x0000FFFF</resetMask>
+          <fields>
+            <field><name>LOW</name><bitOffset>0</bitOffset><bitWidth>16</bitWidth></field>
+            <field><name>HIGH</name><bitOffset>16</bitOffset><bitWidth>16</bitWidth></field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        let db = Database::from_svd(device).unwrap();
+        let changed = |diff: RegisterDiff| -> Vec<_> {
+            diff.fields
+                .iter()
+                .map(|v| (v.info.name.clone(), v.old, v.new))
+                .collect()
+        };
+
+        // Peripheral default wins over the device one
+        let moder = db.get_register(0x40000000).unwrap();
+        assert_eq!(moder.reset_value(), Some(3));
+        assert!(moder.decode_value(3).diff_from_reset().unwrap().is_none());
+        let diff = moder
+            .decode_value(0b0111)
+            .diff_from_reset()
+            .unwrap()
+            .unwrap();
+        assert_eq!(changed(diff), [("MODE1".into(), Some(0), 1)]);
+
+        // HIGH has no defined reset value
+        let idr = db.get_register(0x40000004).unwrap();
+        let diff = idr.decode_value(3).diff_from_reset().unwrap().unwrap();
+        assert_eq!(changed(diff), [("HIGH".into(), None, 0)]);
+
+        // Byte access to the second byte lane, MODER fields are not covered
+        let slice = db.get_registers(0x40000001, 1).next().unwrap();
+        assert!(slice.decode_value(0).diff_from_reset().unwrap().is_none());
+    }
+
+    #[test]
+    fn dim_arrays_expanding() {
+        let device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <dim>2</dim>
+      <dimIncrement>0x1000</dimIncrement>
+      <name>DMA%s</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register>
+          <dim>3</dim>
+          <dimIncrement>4</dimIncrement>
+          <dimIndex>A,B,C</dimIndex>
+          <name>ISR_%s</name>
+          <addressOffset>0x0</addressOffset>
+        </register>
+        <cluster>
+          <dim>2</dim>
+          <dimIncrement>0x20</dimIncrement>
+          <name>CH[%s]</name>
+          <addressOffset>0x100</addressOffset>
+          <register>
+            <name>CTRL</name>
+            <addressOffset>0x0</addressOffset>
+          </register>
+          <cluster>
+            <name>DESC</name>
+            <addressOffset>0x10</addressOffset>
+            <register>
+              <dim>2</dim>
+              <dimIncrement>4</dimIncrement>
+              <name>ADDR[%s]</name>
+              <addressOffset>0x0</addressOffset>
+            </register>
+          </cluster>
+        </cluster>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        let db = Database::from_svd(device).unwrap();
+        let identifier = |address| db.get_register(address).unwrap().identifier();
+        assert_eq!(db.regs.len(), 2 * (3 + 2 * (1 + 2)));
+        assert_eq!(identifier(0x40000000), "DEV.DMA0.ISR_A");
+        assert_eq!(identifier(0x40000008), "DEV.DMA0.ISR_C");
+        assert_eq!(identifier(0x40001004), "DEV.DMA1.ISR_B");
+        assert_eq!(identifier(0x40000120), "DEV.DMA0.CH1.CTRL");
+        assert_eq!(identifier(0x40001134), "DEV.DMA1.CH1.DESC.ADDR1");
+    }
+
     #[test]
     fn to_field_value() {
         let value = 0b00110101;
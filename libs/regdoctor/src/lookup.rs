@@ -0,0 +1,159 @@
+//! Queries by identifier (`DEVICE.PERIPHERAL.REGISTER[.FIELD]`) instead of by address
+use std::ops::{Deref, Range};
+
+use svd_rs::FieldInfo;
+
+use crate::{Database, RegisterInfo};
+
+/// Register (and optionally one of its fields) found by name
+#[derive(Copy, Clone)]
+pub struct Lookup<'a> {
+    pub address: u64,
+    pub info: &'a RegisterInfo,
+    pub field: Option<&'a FieldInfo>,
+}
+
+impl Lookup<'_> {
+    /// Bytes occupied by the register
+    pub fn range(&self) -> Range<u64> {
+        self.address..self.address + self.info.size_bytes() as u64
+    }
+}
+
+impl Database {
+    /// Register by its identifier, or a field if the identifier has a field suffix
+    pub fn find(&self, identifier: &str) -> Option<Lookup<'_>> {
+        if let Some(lookup) = self.registers().find(|v| v.info.identifier() == identifier) {
+            return Some(lookup);
+        }
+        let (register, field) = identifier.rsplit_once('.')?;
+        self.registers()
+            .filter(|v| v.info.identifier() == register)
+            .find_map(|lookup| {
+                let field = lookup.info.inner.get_field(field)?;
+                Some(Lookup {
+                    field: Some(field.deref()),
+                    ..lookup
+                })
+            })
+    }
+
+    /// Registers with identifiers matching `pattern`, in address order
+    ///
+    /// `*` matches any (possibly empty) sequence of characters, dots included,
+    /// `?` matches exactly one character.
+    pub fn glob<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = Lookup<'a>> {
+        self.registers()
+            .filter(move |v| glob_match(pattern.as_bytes(), v.info.identifier().as_bytes()))
+    }
+
+    /// Addresses spanned by the registers of a peripheral (`DEVICE.PERIPHERAL`)
+    pub fn peripheral_range(&self, identifier: &str) -> Option<Range<u64>> {
+        self.registers()
+            .filter(|v| v.info.peripheral_identifier() == identifier)
+            .map(|v| v.range())
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+    }
+
+    /// Registers starting within `range`, in address order
+    pub fn get_registers_in(&self, range: Range<u64>) -> impl Iterator<Item = Lookup<'_>> {
+        self.regs.range(range).map(|(&address, info)| Lookup {
+            address,
+            info,
+            field: None,
+        })
+    }
+
+    fn registers(&self) -> impl Iterator<Item = Lookup<'_>> {
+        self.regs.iter().map(|(&address, info)| Lookup {
+            address,
+            info,
+            field: None,
+        })
+    }
+}
+
+impl RegisterInfo {
+    pub fn peripheral_identifier(&self) -> String {
+        format!("{}.{}", self.device_name, self.peripheral_name)
+    }
+}
+
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        Some((&c, rest)) => match text.split_first() {
+            Some((&t, text)) if c == b'?' || c == t => glob_match(rest, text),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn by_name() {
+        let device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>SCB</name>
+      <baseAddress>0xE000ED00</baseAddress>
+      <registers>
+        <register>
+          <name>CPUID</name>
+          <addressOffset>0x0</addressOffset>
+        </register>
+        <register>
+          <name>VTOR</name>
+          <addressOffset>0x8</addressOffset>
+          <fields>
+            <field><name>TBLOFF</name><bitOffset>7</bitOffset><bitWidth>25</bitWidth></field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+    <peripheral>
+      <name>GPIO</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <cluster>
+          <name>PORT</name>
+          <addressOffset>0x10</addressOffset>
+          <register>
+            <name>ODR</name>
+            <addressOffset>0x4</addressOffset>
+          </register>
+        </cluster>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        let db = Database::from_svd(device).unwrap();
+
+        let vtor = db.find("DEV.SCB.VTOR").unwrap();
+        assert_eq!(vtor.address, 0xE000ED08);
+        assert!(vtor.field.is_none());
+        let tbloff = db.find("DEV.SCB.VTOR.TBLOFF").unwrap();
+        assert_eq!(tbloff.field.unwrap().name, "TBLOFF");
+        assert_eq!(db.find("DEV.GPIO.PORT.ODR").unwrap().address, 0x40000014);
+        assert!(db.find("DEV.SCB.VTOR.NOPE").is_none());
+
+        let addresses = |pattern| db.glob(pattern).map(|v| v.address).collect::<Vec<_>>();
+        assert_eq!(addresses("*.SCB.*"), [0xE000ED00, 0xE000ED08]);
+        assert_eq!(addresses("DEV.*.?DR"), [0x40000014]);
+        assert_eq!(addresses("*"), [0x40000014, 0xE000ED00, 0xE000ED08]);
+
+        assert_eq!(db.peripheral_range("DEV.SCB"), Some(0xE000ED00..0xE000ED0C));
+        assert_eq!(db.peripheral_range("DEV.UART"), None);
+        assert_eq!(db.get_registers_in(0xE000ED04..0xE000EE00).count(), 1);
+    }
+}
@@ -0,0 +1,629 @@
+//! svdtools-style YAML patches
+//!
+//! https://github.com/rust-embedded/svdtools
+//!
+//! Supported subset, applied in this order on every level:
+//! - `_delete`: list of peripherals (registers, fields) to remove
+//! - `_modify`: peripheral (register, field) attributes to change, `name` renames
+//! - `_add`: new peripherals (registers, fields), nesting `registers`/`fields` mappings
+//! - `PERIPHERAL`/`REGISTER` keys descend into matching elements
+//! - `FIELD` keys under a register replace enumerated values of matching fields, either
+//!   `NAME: [value, description]` pairs (optionally split into `_read`/`_write`),
+//!   or `[minimum, maximum]` for a write constraint range
+//!
+//! Element names are matched with `*`/`?` wildcards, comma-separated alternatives are allowed.
+//! Only registers directly in a peripheral are reachable (not the ones in clusters).
+//! `_svd` names the SVD file the patch is meant for, `_include` is not supported.
+use std::path::{Path, PathBuf};
+
+use serde_yaml::{Mapping, Value};
+use svd_rs::{
+    Access, BitRange, EnumeratedValue, EnumeratedValues, FieldInfo, MaybeArray, PeripheralInfo,
+    RegisterCluster, RegisterInfo, Usage, ValidateLevel, WriteConstraint, WriteConstraintRange,
+};
+
+use crate::{lookup::glob_match, Error};
+
+/// Register size if neither the register, its peripheral nor the device sets one
+const DEFAULT_REGISTER_SIZE: u32 = 32;
+
+/// Parsed patch file
+#[derive(Clone, Debug)]
+pub struct Patch {
+    source: String,
+    root: Mapping,
+}
+
+impl Patch {
+    pub fn from_yaml(yaml: &str) -> Result<Self, Error> {
+        let root = serde_yaml::from_str(yaml).map_err(|e| Error::Patch(e.to_string()))?;
+        Ok(Self {
+            source: yaml.to_owned(),
+            root,
+        })
+    }
+
+    /// SVD file the patch is meant for (`_svd`), `None` if it does not say
+    pub fn svd(&self) -> Option<PathBuf> {
+        self.root.get("_svd")?.as_str().map(PathBuf::from)
+    }
+
+    /// Whether the patch should be applied to the SVD file at `path`
+    ///
+    /// `_svd` paths are relative to the patch file, so only the file names are compared.
+    pub fn applies_to(&self, path: &Path) -> bool {
+        match self.svd() {
+            Some(svd) => svd.file_name() == path.file_name(),
+            None => true,
+        }
+    }
+
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn apply(&self, device: &mut svd_rs::Device) -> Result<(), Error> {
+        let size = device
+            .default_register_properties
+            .size
+            .unwrap_or(DEFAULT_REGISTER_SIZE);
+        let peripherals = &mut device.peripherals;
+        if let Some(names) = self.root.get("_delete") {
+            for pattern in patterns(names, "_peripherals")? {
+                peripherals.retain(|v| !matches(&pattern, &v.name));
+            }
+        }
+        for (pattern, attributes) in entries(self.root.get("_modify"))? {
+            for peripheral in peripherals
+                .iter_mut()
+                .filter(|v| matches(&pattern, &v.name))
+            {
+                modify_peripheral(peripheral, attributes, size)?;
+            }
+        }
+        for (name, attributes) in entries(self.root.get("_add"))? {
+            let mut peripheral = PeripheralInfo::builder()
+                .name(name.clone())
+                .base_address(0)
+                .build(ValidateLevel::Disabled)
+                .map_err(|e| Error::Patch(e.to_string()))?;
+            modify_peripheral(&mut peripheral, attributes, size)?;
+            peripherals.push(MaybeArray::Single(peripheral));
+        }
+        for (pattern, patch) in children(&self.root)? {
+            let mut matched = false;
+            for peripheral in peripherals
+                .iter_mut()
+                .filter(|v| matches(&pattern, &v.name))
+            {
+                matched = true;
+                patch_peripheral(peripheral, patch, size)?;
+            }
+            if !matched {
+                log::warn!("Patch: no peripheral matches {pattern:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `size` is the default register size the peripheral inherits
+fn patch_peripheral(
+    peripheral: &mut PeripheralInfo,
+    patch: &Mapping,
+    size: u32,
+) -> Result<(), Error> {
+    let size = peripheral.default_register_properties.size.unwrap_or(size);
+    if let Some(names) = patch.get("_delete") {
+        let patterns = patterns(names, "_registers")?;
+        if let Some(registers) = &mut peripheral.registers {
+            registers.retain(|v| match v {
+                RegisterCluster::Register(register) => {
+                    !patterns.iter().any(|p| matches(p, &register.name))
+                }
+                RegisterCluster::Cluster(_) => true,
+            });
+        }
+    }
+    for (pattern, attributes) in entries(patch.get("_modify"))? {
+        for register in peripheral
+            .registers_mut()
+            .filter(|v| matches(&pattern, &v.name))
+        {
+            modify_register(register, attributes, size)?;
+        }
+    }
+    for (name, attributes) in entries(patch.get("_add"))? {
+        let register = new_register(name, attributes, size)?;
+        peripheral
+            .registers
+            .get_or_insert_with(Vec::new)
+            .push(RegisterCluster::Register(MaybeArray::Single(register)));
+    }
+    for (pattern, patch) in children(patch)? {
+        let mut matched = false;
+        for register in peripheral
+            .registers_mut()
+            .filter(|v| matches(&pattern, &v.name))
+        {
+            matched = true;
+            patch_register(register, patch, size)?;
+        }
+        if !matched {
+            log::warn!("Patch: no register matches {}.{pattern:?}", peripheral.name);
+        }
+    }
+    Ok(())
+}
+
+fn patch_register(register: &mut RegisterInfo, patch: &Mapping, size: u32) -> Result<(), Error> {
+    let size = register.properties.size.unwrap_or(size);
+    if let Some(names) = patch.get("_delete") {
+        let patterns = patterns(names, "_fields")?;
+        if let Some(fields) = &mut register.fields {
+            fields.retain(|v| !patterns.iter().any(|p| matches(p, &v.name)));
+        }
+    }
+    for (pattern, attributes) in entries(patch.get("_modify"))? {
+        for field in register.fields_mut().filter(|v| matches(&pattern, &v.name)) {
+            modify_field(field, attributes, size)?;
+        }
+    }
+    for (name, attributes) in entries(patch.get("_add"))? {
+        let field = new_field(name, attributes, size)?;
+        register
+            .fields
+            .get_or_insert_with(Vec::new)
+            .push(MaybeArray::Single(field));
+    }
+    for (key, value) in patch.iter() {
+        let Some(pattern) = key.as_str().filter(|v| !v.starts_with('_')) else {
+            continue;
+        };
+        let mut matched = false;
+        for field in register.fields_mut().filter(|v| matches(pattern, &v.name)) {
+            matched = true;
+            match value {
+                Value::Sequence(range) => {
+                    let [min, max] = range.as_slice() else {
+                        return Err(error(pattern, "range has to be [minimum, maximum]"));
+                    };
+                    field.write_constraint = Some(WriteConstraint::Range(WriteConstraintRange {
+                        min: number(min, pattern)?,
+                        max: number(max, pattern)?,
+                    }));
+                }
+                Value::Mapping(values) => field.enumerated_values = enumerated_values(values)?,
+                _ => return Err(error(pattern, "expected enumerated values or a range")),
+            }
+        }
+        if !matched {
+            log::warn!("Patch: no field matches {}.{pattern:?}", register.name);
+        }
+    }
+    Ok(())
+}
+
+fn enumerated_values(values: &Mapping) -> Result<Vec<EnumeratedValues>, Error> {
+    let by_usage = [("_read", Usage::Read), ("_write", Usage::Write)];
+    if !by_usage.iter().any(|(key, _)| values.contains_key(key)) {
+        return Ok(vec![enumerated_value_set(values, Usage::ReadWrite)?]);
+    }
+    let mut sets = Vec::new();
+    for (key, usage) in by_usage {
+        let Some(values) = values.get(key) else {
+            continue;
+        };
+        let values = values
+            .as_mapping()
+            .ok_or_else(|| error(key, "expected enumerated values"))?;
+        sets.push(enumerated_value_set(values, usage)?);
+    }
+    Ok(sets)
+}
+
+fn enumerated_value_set(values: &Mapping, usage: Usage) -> Result<EnumeratedValues, Error> {
+    let mut variants = Vec::new();
+    for (name, value) in values.iter() {
+        let name = name
+            .as_str()
+            .ok_or_else(|| error("enumerated value", "name has to be a string"))?;
+        let (value, description) = match value {
+            Value::Sequence(pair) => match pair.as_slice() {
+                [value, description] => (value, description.as_str().map(ToOwned::to_owned)),
+                [value] => (value, None),
+                _ => return Err(error(name, "expected [value, description]")),
+            },
+            value => (value, None),
+        };
+        let value = match value.as_str() {
+            Some("_default") => None,
+            _ => Some(number(value, name)?),
+        };
+        variants.push(
+            EnumeratedValue::builder()
+                .name(name.to_owned())
+                .description(description)
+                .value(value)
+                .is_default(value.is_none().then_some(true))
+                .build(ValidateLevel::Disabled)
+                .map_err(|e| Error::Patch(e.to_string()))?,
+        );
+    }
+    EnumeratedValues::builder()
+        .usage(Some(usage))
+        .values(variants)
+        .build(ValidateLevel::Disabled)
+        .map_err(|e| Error::Patch(e.to_string()))
+}
+
+fn modify_peripheral(
+    peripheral: &mut PeripheralInfo,
+    attributes: &Mapping,
+    size: u32,
+) -> Result<(), Error> {
+    for (key, value) in attributes.iter() {
+        let key = key.as_str().unwrap_or_default();
+        match key {
+            "name" => peripheral.name = string(value, key)?,
+            "description" => peripheral.description = Some(string(value, key)?),
+            "groupName" => peripheral.group_name = Some(string(value, key)?),
+            "baseAddress" => peripheral.base_address = number(value, key)?,
+            "size" => peripheral.default_register_properties.size = Some(number(value, key)? as _),
+            "access" => peripheral.default_register_properties.access = Some(access(value)?),
+            "resetValue" => {
+                peripheral.default_register_properties.reset_value = Some(number(value, key)?)
+            }
+            "resetMask" => {
+                peripheral.default_register_properties.reset_mask = Some(number(value, key)?)
+            }
+            "registers" => {
+                for (name, attributes) in entries(Some(value))? {
+                    let size = peripheral.default_register_properties.size.unwrap_or(size);
+                    let register = new_register(name, attributes, size)?;
+                    peripheral
+                        .registers
+                        .get_or_insert_with(Vec::new)
+                        .push(RegisterCluster::Register(MaybeArray::Single(register)));
+                }
+            }
+            _ => log::warn!("Patch: unsupported peripheral attribute {key:?}"),
+        }
+    }
+    Ok(())
+}
+
+fn new_register(name: String, attributes: &Mapping, size: u32) -> Result<RegisterInfo, Error> {
+    let mut register = RegisterInfo::builder()
+        .name(name)
+        .address_offset(0)
+        .build(ValidateLevel::Disabled)
+        .map_err(|e| Error::Patch(e.to_string()))?;
+    modify_register(&mut register, attributes, size)?;
+    Ok(register)
+}
+
+/// `size` is the register size inherited from the peripheral or device
+fn modify_register(
+    register: &mut RegisterInfo,
+    attributes: &Mapping,
+    size: u32,
+) -> Result<(), Error> {
+    for (key, value) in attributes.iter() {
+        let key = key.as_str().unwrap_or_default();
+        match key {
+            "name" => register.name = string(value, key)?,
+            "description" => register.description = Some(string(value, key)?),
+            "addressOffset" => register.address_offset = number(value, key)? as _,
+            "size" => register.properties.size = Some(number(value, key)? as _),
+            "access" => register.properties.access = Some(access(value)?),
+            "resetValue" => register.properties.reset_value = Some(number(value, key)?),
+            "resetMask" => register.properties.reset_mask = Some(number(value, key)?),
+            "fields" => {
+                for (name, attributes) in entries(Some(value))? {
+                    let size = register.properties.size.unwrap_or(size);
+                    let field = new_field(name, attributes, size)?;
+                    register
+                        .fields
+                        .get_or_insert_with(Vec::new)
+                        .push(MaybeArray::Single(field));
+                }
+            }
+            _ => log::warn!("Patch: unsupported register attribute {key:?}"),
+        }
+    }
+    Ok(())
+}
+
+fn new_field(name: String, attributes: &Mapping, size: u32) -> Result<FieldInfo, Error> {
+    let mut field = FieldInfo::builder()
+        .name(name)
+        .bit_range(BitRange::from_offset_width(0, 1))
+        .build(ValidateLevel::Disabled)
+        .map_err(|e| Error::Patch(e.to_string()))?;
+    modify_field(&mut field, attributes, size)?;
+    Ok(field)
+}
+
+/// `size` is the bit size of the field's register
+fn modify_field(field: &mut FieldInfo, attributes: &Mapping, size: u32) -> Result<(), Error> {
+    let BitRange { offset, width, .. } = field.bit_range;
+    let (mut offset, mut width) = (offset, width);
+    for (key, value) in attributes.iter() {
+        let key = key.as_str().unwrap_or_default();
+        match key {
+            "name" => field.name = string(value, key)?,
+            "description" => field.description = Some(string(value, key)?),
+            "bitOffset" => offset = number(value, key)? as _,
+            "bitWidth" => width = number(value, key)? as _,
+            "access" => field.access = Some(access(value)?),
+            _ => log::warn!("Patch: unsupported field attribute {key:?}"),
+        }
+    }
+    if width == 0 || width > 64 || offset as u64 + width as u64 > size as u64 {
+        return Err(error(
+            &field.name,
+            &format!("bitOffset {offset}, bitWidth {width} does not fit a {size}-bit register"),
+        ));
+    }
+    field.bit_range = BitRange::from_offset_width(offset, width);
+    Ok(())
+}
+
+/// Entries of a `_modify`/`_add` mapping
+fn entries(mapping: Option<&Value>) -> Result<Vec<(String, &Mapping)>, Error> {
+    let Some(mapping) = mapping else {
+        return Ok(Vec::new());
+    };
+    let mapping = mapping
+        .as_mapping()
+        .ok_or_else(|| error("_modify/_add", "expected a mapping"))?;
+    mapping
+        .iter()
+        .map(|(key, value)| {
+            let key = string(key, "name")?;
+            let value = value
+                .as_mapping()
+                .ok_or_else(|| error(&key, "expected a mapping"))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// Non-command keys, descending into child elements
+fn children(mapping: &Mapping) -> Result<Vec<(String, &Mapping)>, Error> {
+    mapping
+        .iter()
+        .filter(|(key, _)| key.as_str().is_some_and(|v| !v.starts_with('_')))
+        .map(|(key, value)| {
+            let key = string(key, "name")?;
+            let value = value
+                .as_mapping()
+                .ok_or_else(|| error(&key, "expected a mapping"))?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// `_delete` list, or the `_registers`-like list within the mapping form
+fn patterns(value: &Value, key: &str) -> Result<Vec<String>, Error> {
+    let list = match value {
+        Value::Mapping(mapping) => mapping.get(key).cloned().unwrap_or_default(),
+        Value::String(_) => Value::Sequence(vec![value.clone()]),
+        value => value.clone(),
+    };
+    match list {
+        Value::Null => Ok(Vec::new()),
+        Value::Sequence(list) => list.iter().map(|v| string(v, "_delete")).collect(),
+        _ => Err(error("_delete", "expected a list")),
+    }
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    pattern
+        .split(',')
+        .any(|pattern| glob_match(pattern.trim().as_bytes(), name.as_bytes()))
+}
+
+fn string(value: &Value, key: &str) -> Result<String, Error> {
+    value
+        .as_str()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| error(key, "expected a string"))
+}
+
+/// Integer, or a string with decimal, `0x` or `0b` number
+fn number(value: &Value, key: &str) -> Result<u64, Error> {
+    if let Some(value) = value.as_u64() {
+        return Ok(value);
+    }
+    let value = value
+        .as_str()
+        .ok_or_else(|| error(key, "expected a number"))?;
+    let parsed = if let Some(hex) = value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(bin) = value.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2)
+    } else {
+        value.parse()
+    };
+    parsed.map_err(|_| error(key, "expected a number"))
+}
+
+fn access(value: &Value) -> Result<Access, Error> {
+    value
+        .as_str()
+        .and_then(Access::parse_str)
+        .ok_or_else(|| error("access", "expected an SVD access type"))
+}
+
+fn error(key: &str, message: &str) -> Error {
+    Error::Patch(format!("{key}: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Database;
+
+    #[test]
+    fn patching() {
+        let mut device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>UART0</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register>
+          <name>CR</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field><name>EN</name><bitOffset>0</bitOffset><bitWidth>1</bitWidth></field>
+            <field><name>MODE</name><bitOffset>1</bitOffset><bitWidth>2</bitWidth></field>
+            <field><name>BOGUS</name><bitOffset>7</bitOffset><bitWidth>1</bitWidth></field>
+          </fields>
+        </register>
+        <register>
+          <name>SR</name>
+          <addressOffset>0x4</addressOffset>
+        </register>
+      </registers>
+    </peripheral>
+    <peripheral>
+      <name>UART1</name>
+      <baseAddress>0x40001000</baseAddress>
+      <registers>
+        <register>
+          <name>CR</name>
+          <addressOffset>0x0</addressOffset>
+        </register>
+      </registers>
+    </peripheral>
+    <peripheral>
+      <name>OBSOLETE</name>
+      <baseAddress>0x50000000</baseAddress>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        let patch = Patch::from_yaml(
+            r#"
+_svd: ../svds/dev.svd
+_delete: [OBSOLETE]
+_modify:
+  UART1:
+    baseAddress: 0x40002000
+_add:
+  TIMER:
+    baseAddress: "0x40010000"
+    registers:
+      CNT:
+        addressOffset: 0x8
+        fields:
+          VALUE: {bitOffset: 0, bitWidth: 16}
+UART*:
+  _modify:
+    SR:
+      name: STATUS
+      access: read-only
+  _add:
+    DR:
+      addressOffset: 0xC
+  CR:
+    _delete: [BOGUS]
+    _modify:
+      MODE:
+        bitWidth: 3
+    EN:
+      Disabled: [0, "Off"]
+      Enabled: [1, "On"]
+    MODE: [0, 4]
+"#,
+        )
+        .unwrap();
+        assert!(patch.applies_to(Path::new("/somewhere/else/dev.svd")));
+        assert!(!patch.applies_to(Path::new("other.svd")));
+        patch.apply(&mut device).unwrap();
+        let db = Database::from_svd(device).unwrap();
+
+        let identifiers: Vec<_> = db.regs.iter().map(|(&a, v)| (a, v.identifier())).collect();
+        assert_eq!(
+            identifiers,
+            [
+                (0x40000000, "DEV.UART0.CR".to_owned()),
+                (0x40000004, "DEV.UART0.STATUS".to_owned()),
+                (0x4000000C, "DEV.UART0.DR".to_owned()),
+                (0x40002000, "DEV.UART1.CR".to_owned()),
+                (0x4000200C, "DEV.UART1.DR".to_owned()),
+                (0x40010008, "DEV.TIMER.CNT".to_owned()),
+            ]
+        );
+        let status = db.get_register(0x40000004).unwrap();
+        assert_eq!(status.access(), Some(Access::ReadOnly));
+
+        let cr = db.get_register(0x40000000).unwrap().decode_value(0b1011);
+        let fields: Vec<_> = cr
+            .fields
+            .iter()
+            .map(|v| {
+                let variant = v.variant.as_ref().map(|v| v.name.as_str());
+                (v.info.name.as_str(), v.value, variant)
+            })
+            .collect();
+        assert_eq!(fields, [("EN", 1, Some("Enabled")), ("MODE", 5, None)]);
+        assert_eq!(
+            cr.violations(crate::AccessKind::Write),
+            [crate::Violation::OutOfRange {
+                field: "MODE".into(),
+                value: 5,
+                min: 0,
+                max: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Patch::from_yaml("- not a mapping").is_err());
+        let mut device = svd_rs::Device::builder()
+            .name("DEV".into())
+            .peripherals(Vec::new())
+            .build(ValidateLevel::Disabled)
+            .unwrap();
+        let patch = Patch::from_yaml("_add: {TIMER: {baseAddress: nope}}").unwrap();
+        assert_eq!(
+            patch.apply(&mut device),
+            Err(Error::Patch("baseAddress: expected a number".into()))
+        );
+        for (field, message) in [
+            ("{bitOffset: 0, bitWidth: 64}", "bitOffset 0, bitWidth 64"),
+            ("{bitOffset: 24, bitWidth: 16}", "bitOffset 24, bitWidth 16"),
+            ("{bitWidth: 0}", "bitOffset 0, bitWidth 0"),
+        ] {
+            let patch = Patch::from_yaml(&format!(
+                "_add: {{TIMER: {{registers: {{CNT: {{fields: {{VALUE: {field}}}}}}}}}}}"
+            ))
+            .unwrap();
+            assert_eq!(
+                patch.apply(&mut device),
+                Err(Error::Patch(format!(
+                    "VALUE: {message} does not fit a 32-bit register"
+                )))
+            );
+        }
+        // 64-bit registers take 64-bit fields
+        let patch = Patch::from_yaml(
+            "_add: {TIMER: {registers: {CNT: {size: 64, fields: {VALUE: {bitWidth: 64}}}}}}",
+        )
+        .unwrap();
+        assert_eq!(patch.apply(&mut device), Ok(()));
+        let db = Database::from_svd(device).unwrap();
+        let cnt = db.get_register(0).unwrap().decode_value(u64::MAX);
+        assert_eq!(cnt.fields[0].value, u64::MAX);
+    }
+}
@@ -0,0 +1,153 @@
+//! Structured output of decoded registers and diffs
+//!
+//! Fields carry their bit range and enumerated variant (`name` and `description`) next to
+//! the raw value, so the decoding can be consumed without parsing the text output.
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use svd_rs::{EnumeratedValue, FieldInfo};
+
+use crate::{Field, FieldDiff, Register, RegisterDiff};
+
+impl Serialize for Register {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Register", 3)?;
+        s.serialize_field("identifier", &self.info.identifier())?;
+        s.serialize_field("value", &self.value)?;
+        s.serialize_field("fields", &self.fields)?;
+        s.end()
+    }
+}
+
+impl Serialize for Field {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Field", 6)?;
+        serialize_field_info(&mut s, &self.info)?;
+        s.serialize_field("value", &self.value)?;
+        s.serialize_field("variant", &self.variant.as_ref().map(Variant))?;
+        s.end()
+    }
+}
+
+impl Serialize for RegisterDiff {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("RegisterDiff", 4)?;
+        s.serialize_field("identifier", &self.identifier)?;
+        s.serialize_field("old", &self.old)?;
+        s.serialize_field("new", &self.new)?;
+        s.serialize_field("fields", &self.fields)?;
+        s.end()
+    }
+}
+
+impl Serialize for FieldDiff {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("FieldDiff", 8)?;
+        serialize_field_info(&mut s, &self.info)?;
+        // `None` if unknown (field without a defined reset value)
+        s.serialize_field("old", &self.old)?;
+        s.serialize_field("new", &self.new)?;
+        s.serialize_field("old_variant", &self.old_variant.as_ref().map(Variant))?;
+        s.serialize_field("new_variant", &self.new_variant.as_ref().map(Variant))?;
+        s.end()
+    }
+}
+
+fn serialize_field_info<S: SerializeStruct>(s: &mut S, info: &FieldInfo) -> Result<(), S::Error> {
+    s.serialize_field("name", &info.name)?;
+    s.serialize_field("description", &info.description)?;
+    s.serialize_field("bit_offset", &info.bit_offset())?;
+    s.serialize_field("bit_width", &info.bit_width())?;
+    Ok(())
+}
+
+struct Variant<'a>(&'a EnumeratedValue);
+
+impl Serialize for Variant<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Variant", 2)?;
+        s.serialize_field("name", &self.0.name)?;
+        s.serialize_field("description", &self.0.description)?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::Database;
+
+    #[test]
+    fn json() {
+        let device = svd_parser::parse(
+            r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>UART</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register>
+          <name>CR</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field>
+              <name>EN</name><description>Enable</description>
+              <bitOffset>0</bitOffset><bitWidth>1</bitWidth>
+              <enumeratedValues>
+                <enumeratedValue><name>OFF</name><description>Disabled</description><value>0</value></enumeratedValue>
+                <enumeratedValue><name>ON</name><description>Enabled</description><value>1</value></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field><name>DIV</name><bitOffset>4</bitOffset><bitWidth>4</bitWidth></field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+            "#,
+        )
+        .unwrap();
+        let db = Database::from_svd(device).unwrap();
+        let cr = db.get_register(0x40000000).unwrap();
+        let old = cr.decode_value(0x00);
+        let new = cr.decode_value(0x31);
+
+        assert_eq!(
+            serde_json::to_value(&new).unwrap(),
+            json!({
+                "identifier": "DEV.UART.CR",
+                "value": 0x31,
+                "fields": [
+                    {
+                        "name": "EN",
+                        "description": "Enable",
+                        "bit_offset": 0,
+                        "bit_width": 1,
+                        "value": 1,
+                        "variant": {"name": "ON", "description": "Enabled"}
+                    },
+                    {
+                        "name": "DIV",
+                        "description": null,
+                        "bit_offset": 4,
+                        "bit_width": 4,
+                        "value": 3,
+                        "variant": null
+                    }
+                ]
+            })
+        );
+
+        let diff = crate::Register::diff(&old, &new).unwrap().unwrap();
+        let diff = serde_json::to_value(&diff).unwrap();
+        assert_eq!(diff["identifier"], "DEV.UART.CR");
+        assert_eq!(diff["old"], 0);
+        assert_eq!(diff["new"], 0x31);
+        assert_eq!(diff["fields"][0]["old_variant"]["name"], "OFF");
+        assert_eq!(diff["fields"][0]["new_variant"]["name"], "ON");
+        assert_eq!(diff["fields"][1]["old"], 0);
+        assert_eq!(diff["fields"][1]["new"], 3);
+    }
+}
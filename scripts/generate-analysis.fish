@@ -7,6 +7,7 @@ set analysis_name $argv[1]
 set script_dir (path dirname (status --current-filename))
 set repo_root $script_dir/..
 set svds --svd $repo_root/svds/MIMXRT1189_cm33.svd --svd $repo_root/svds/MIMXRT1189_cm33-SecureExt.svd --svd $repo_root/svds/CortexM33.svd
+set -a svds --svd-cache $repo_root/target/svd-cache
 set src $repo_root/target/sniffing-results
 set work_dir $repo_root/target/analysis-results/$analysis_name
 set input_dir $work_dir/input
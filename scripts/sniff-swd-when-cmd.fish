@@ -54,4 +54,13 @@ wait (jobs -p)
 echo "Post-processing captured SWD"
 sigrok-cli -i "$out_dir/swd.sr" -P swd:swclk=SWCLK:swdio=SWDIO --protocol-decoder-samplenum > "$out_dir/swd.txt" 2>$log_dir/sigrok.err.log
 
+# SWO is NRZ (UART) encoded unless SWO_MANCHESTER is set, baud rate must be known upfront
+if set -q SWO_MANCHESTER
+    echo "Dumping raw SWO samples"
+    sigrok-cli -i "$out_dir/swd.sr" -C SWO -O binary > "$out_dir/swo.bin" 2>>$log_dir/sigrok.err.log
+else if set -q SWO_BAUDRATE
+    echo "Post-processing captured SWO"
+    sigrok-cli -i "$out_dir/swd.sr" -P uart:rx=SWO:baudrate=$SWO_BAUDRATE:format=hex -A uart=rx-data --protocol-decoder-samplenum > "$out_dir/swo.txt" 2>>$log_dir/sigrok.err.log
+end
+
 echo "Done"
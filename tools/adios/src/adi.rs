@@ -3,29 +3,146 @@ use bilge::prelude::*;
 use std::{
     collections::HashMap,
     fmt::{Debug, Display},
+    rc::Rc,
 };
 
-use adios_common::{Input, Timestamp};
+use adios_common::{itm, swd, Input, Timestamp};
 
 pub struct Vm {
     command_cursor: usize,
     state: VmState,
+    memory_model: Option<Rc<dyn MemoryModel>>,
+}
+
+/// Target-side semantics of MEM-AP writes
+///
+/// By default written values land in the memory as they are. Registers might not behave
+/// like that (e.g. write-one-to-clear flags), a model knowing them computes what the
+/// target actually ends up with.
+pub trait MemoryModel {
+    /// `old` and `written` are `size` bytes wide values accessed at `address` through
+    /// the MEM-AP `apsel`, the returned value is what gets stored
+    ///
+    /// Only consulted when the old value is known, otherwise the written value is stored.
+    fn write(&self, apsel: u8, address: u64, size: u32, old: u32, written: u32) -> u32;
 }
 
 #[derive(Clone)]
 pub struct VmState {
     pub dp: Dp,
     pub aps: [Ap; 256],
+    /// Endianness forced onto all MEM-APs, CFG.BE is used otherwise
+    pub endianness: Option<Endianness>,
+    /// Size of the block TAR auto-increment wraps within, a power of two
+    pub tar_wrap: u32,
+    /// Resolves wire-level [`Input::Swd`] into commands
+    pub swd: swd::Resolver,
+}
+
+/// Auto-increment is only guaranteed to work on the lowest 10 bits of TAR, C.2.2.4, IHI0031G
+pub const TAR_WRAP_DEFAULT: u32 = 1024;
+
+/// Byte order of a MEM-AP, C.2.2.7, IHI0031G
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    #[default]
+    Little,
+    /// Legacy BE-32, byte lanes are swapped within a word
+    Big,
 }
 
 #[derive(Default, Copy, Clone, Debug)]
 pub struct Dp {
     pub select: dp::Select,
+    /// Last read value with the writable fields of later writes and ABORT clears applied
+    pub ctrl_stat: dp::CtrlStat,
 }
 
 pub mod dp {
     use super::*;
 
+    #[bitsize(32)]
+    #[derive(Default, FromBits, Copy, Clone, DebugBits, PartialEq, Eq)]
+    pub struct CtrlStat {
+        pub orundetect: bool,
+        pub stickyorun: bool,
+        pub trnmode: TrnMode,
+        pub stickycmp: bool,
+        pub stickyerr: bool,
+        pub readok: bool,
+        pub wdataerr: bool,
+        pub masklane: u4,
+        pub trncnt: u12,
+        pub reserved: u2,
+        pub cdbgrstreq: bool,
+        pub cdbgrstack: bool,
+        pub cdbgpwrupreq: bool,
+        pub cdbgpwrupack: bool,
+        pub csyspwrupreq: bool,
+        pub csyspwrupack: bool,
+    }
+
+    impl CtrlStat {
+        /// Writes only affect the control fields, sticky flags are cleared with ABORT in SWD
+        pub fn write(&mut self, written: Self) {
+            self.set_orundetect(written.orundetect());
+            self.set_trnmode(written.trnmode());
+            self.set_masklane(written.masklane());
+            self.set_trncnt(written.trncnt());
+            self.set_cdbgrstreq(written.cdbgrstreq());
+            self.set_cdbgpwrupreq(written.cdbgpwrupreq());
+            self.set_csyspwrupreq(written.csyspwrupreq());
+        }
+
+        pub fn abort(&mut self, abort: Abort) {
+            if abort.stkcmpclr() {
+                self.set_stickycmp(false);
+            }
+            if abort.stkerrclr() {
+                self.set_stickyerr(false);
+            }
+            if abort.wderrclr() {
+                self.set_wdataerr(false);
+            }
+            if abort.orunerrclr() {
+                self.set_stickyorun(false);
+            }
+        }
+
+        /// Sticky errors make the DP ignore AP transactions until cleared
+        pub fn sticky_error(&self) -> bool {
+            self.stickyerr() || self.stickyorun() || self.wdataerr()
+        }
+
+        /// Byte lanes compared by pushed operations
+        pub fn masklane_mask(&self) -> u32 {
+            (0..4)
+                .filter(|lane| self.masklane().value() & (1 << lane) != 0)
+                .fold(0, |mask, lane| mask | 0xFF << (lane * 8))
+        }
+    }
+
+    #[bitsize(2)]
+    #[derive(Default, FromBits, Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum TrnMode {
+        #[default]
+        Normal = 0b00,
+        PushedVerify = 0b01,
+        PushedCompare = 0b10,
+        Reserved = 0b11,
+    }
+
+    #[bitsize(32)]
+    #[derive(Default, FromBits, Copy, Clone, DebugBits, PartialEq, Eq)]
+    pub struct Abort {
+        pub dapabort: bool,
+        pub stkcmpclr: bool,
+        pub stkerrclr: bool,
+        pub wderrclr: bool,
+        pub orunerrclr: bool,
+        pub reserved: u27,
+    }
+
     #[bitsize(32)]
     #[derive(Default, FromBits, Copy, Clone, DebugBits)]
     pub struct Select {
@@ -38,10 +155,54 @@ pub mod dp {
 
 #[derive(PartialEq, Eq, Default, Clone, Debug)]
 pub struct Ap {
-    pub memory: HashMap<u32, u32>,
+    pub memory: HashMap<u64, u32>,
     pub tar: Option<u32>,
+    /// Upper half of 64-bit addresses (Long Address Extension)
+    pub tar_msw: u32,
+    /// Last auto-increment wrapped TAR at the `VmState::tar_wrap` boundary
+    pub tar_wrapped: bool,
     pub csw: Option<ap::Csw>,
     pub idr: Option<ap::Idr>,
+    /// Last read BASE, where the debug components (ROM table) are
+    pub base: Option<u32>,
+    pub endianness: Endianness,
+    /// DRW beats of an unfinished Large Data Extension access
+    pub large_data: Vec<u32>,
+}
+
+impl Ap {
+    /// Reconstructed content of the word containing `address`, as read with a word access
+    pub fn word(&self, address: u64) -> Option<u32> {
+        self.memory.get(&(address & !0b11)).copied()
+    }
+
+    /// Reconstructed value of a `size` bytes wide access (up to a doubleword) to `address`
+    pub fn value(&self, address: u64, size: u32) -> Option<u64> {
+        if size > 8 {
+            return None;
+        }
+        let bytes = self.bytes(address, size as usize)?;
+        let byte = |value: u64, byte: &u8| value << 8 | *byte as u64;
+        Some(match self.endianness {
+            Endianness::Little => bytes.iter().rev().fold(0, byte),
+            Endianness::Big => bytes.iter().fold(0, byte),
+        })
+    }
+
+    /// Reconstructed memory content, `None` if any of the words was never accessed
+    pub fn bytes(&self, address: u64, len: usize) -> Option<Vec<u8>> {
+        (0..len as u64)
+            .map(|i| {
+                let address = address.checked_add(i)?;
+                let word = self.word(address)?;
+                let bytes = match self.endianness {
+                    Endianness::Little => word.to_le_bytes(),
+                    Endianness::Big => word.to_be_bytes(),
+                };
+                Some(bytes[(address & 0b11) as usize])
+            })
+            .collect()
+    }
 }
 
 pub mod ap {
@@ -72,14 +233,22 @@ pub mod ap {
         }
     }
 
+    #[bitsize(32)]
+    #[derive(Default, FromBits, Copy, Clone, DebugBits, PartialEq, Eq)]
+    pub struct Cfg {
+        pub be: bool,
+        pub la: bool,
+        pub ld: bool,
+        pub reserved: u29,
+    }
+
     #[bitsize(2)]
     #[derive(Default, TryFromBits, Copy, Clone, Debug, PartialEq, Eq)]
     pub enum CswAddrInc {
         #[default]
         Disabled = 0b00,
         Single = 0b01,
-        // Packed increment is not supported
-        // Packed = 0b10,
+        Packed = 0b10,
     }
 
     #[bitsize(3)]
@@ -89,10 +258,9 @@ pub mod ap {
         Byte = 0b000,
         Halfword = 0b001,
         Word = 0b010,
-        // MEM-AP Large Data Extension is not supported
-        // Doubleword = 0b011,
-        // Bits128 = 0b100,
-        // Bits256 = 0b101,
+        Doubleword = 0b011,
+        Bits128 = 0b100,
+        Bits256 = 0b101,
     }
 
     #[bitsize(32)]
@@ -106,6 +274,16 @@ pub mod ap {
         pub revision: u4,
     }
 
+    impl Idr {
+        pub fn csw_type(&self) -> CswType {
+            match self.class() {
+                // Shares the type with JTAG-APs
+                IdrClass::ComAccessPort => CswType::Generic,
+                _ => self.type_().csw_type(),
+            }
+        }
+    }
+
     #[bitsize(4)]
     #[repr(u8)]
     #[derive(FromBits, Copy, Clone, Debug, PartialEq, Eq)]
@@ -129,8 +307,15 @@ pub mod ap {
 
         pub fn csw_type(&self) -> CswType {
             match self {
+                Self::JtagConnectionOrComAp => CswType::Jtag,
                 Self::AmbaAhb3Bus => CswType::AmbaAhb3,
-                _ => CswType::Generic,
+                Self::AmbaApb2OrApb3Bus => CswType::AmbaApb2OrApb3,
+                Self::AmbaAxi3OrAxi4BusWithOptionalAceLiteSupport => CswType::AmbaAxi3OrAxi4,
+                Self::AmbaAhb5Bus => CswType::AmbaAhb5,
+                Self::AmbaApb4AndApb5Bus => CswType::AmbaApb4OrApb5,
+                Self::AmbaAxi5Bus => CswType::AmbaAxi5,
+                Self::AmbaAhb5WithEnhancedHprot => CswType::AmbaAhb5WithEnhancedHprot,
+                Self::Reserved(_) => CswType::Generic,
             }
         }
     }
@@ -181,13 +366,48 @@ impl Vm {
         Self {
             command_cursor: 0,
             state: Default::default(),
+            memory_model: None,
         }
     }
 
-    pub fn step_forward(&mut self, commands: &Vec<Input>) -> Option<VmStateStep> {
-        let command = commands.get(self.command_cursor)?;
+    pub fn with_memory_model(mut self, memory_model: Rc<dyn MemoryModel>) -> Self {
+        self.memory_model = Some(memory_model);
+        self
+    }
+
+    /// Ignore CFG.BE and treat all MEM-APs as `endianness`
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.state.endianness = Some(endianness);
+        self.state.force_endianness();
+        self
+    }
+
+    /// Wrap TAR auto-increment within `tar_wrap` sized blocks instead of [`TAR_WRAP_DEFAULT`]
+    ///
+    /// # Panics
+    ///
+    /// If `tar_wrap` is not a power of two.
+    pub fn with_tar_wrap(mut self, tar_wrap: u32) -> Self {
+        assert!(
+            tar_wrap.is_power_of_two(),
+            "TAR wrap must be a power of two"
+        );
+        self.state.tar_wrap = tar_wrap;
+        self
+    }
+
+    pub fn state(&self) -> &VmState {
+        &self.state
+    }
+
+    /// Step over the next input, one more step past the last one flushes what the wire-level
+    /// resolution still holds back
+    pub fn step_forward(&mut self, commands: &[Input]) -> Option<VmStateStep> {
+        if self.command_cursor > commands.len() {
+            return None;
+        }
         let previous_state = self.state.clone();
-        let operations = self.state.step(command.clone());
+        let operations = self.step_at(commands, self.command_cursor);
         let current_state = self.state.clone();
         self.command_cursor += 1;
         Some(VmStateStep {
@@ -198,12 +418,12 @@ impl Vm {
     }
 
     #[allow(unused)]
-    pub fn step_back(&mut self, commands: &Vec<Input>) -> Option<VmStateStep> {
+    pub fn step_back(&mut self, commands: &[Input]) -> Option<VmStateStep> {
         let previous_state = self.state.clone();
         let command_cursor = self.command_cursor.checked_sub(1)?;
         self.state.reset();
-        for command in commands.iter().take(command_cursor) {
-            let _ = self.state.step(command.clone());
+        for index in 0..command_cursor {
+            let _ = self.step_at(commands, index);
         }
         self.command_cursor = command_cursor;
         let current_state = self.state.clone();
@@ -213,20 +433,66 @@ impl Vm {
             current: (current_state, self.command_cursor),
         })
     }
+
+    fn step_at(&mut self, commands: &[Input], index: usize) -> Vec<Operation> {
+        let memory_model = self.memory_model.as_deref();
+        match commands.get(index) {
+            Some(command) => self.state.step(command.clone(), memory_model),
+            None => self.state.finish(memory_model),
+        }
+    }
 }
 
 impl VmState {
+    /// Step the inputs [`swd::Resolver`] still holds at the end of the input
+    fn finish(&mut self, memory_model: Option<&dyn MemoryModel>) -> Vec<Operation> {
+        self.swd
+            .finish()
+            .into_iter()
+            .flat_map(|input| self.step(input, memory_model))
+            .collect()
+    }
+
+    fn force_endianness(&mut self) {
+        if let Some(endianness) = self.endianness {
+            for ap in self.aps.iter_mut() {
+                ap.endianness = endianness;
+            }
+        }
+    }
+
     fn reset(&mut self) {
-        *self = Default::default();
+        *self = Self {
+            endianness: self.endianness,
+            tar_wrap: self.tar_wrap,
+            ..Default::default()
+        };
+        self.force_endianness();
     }
 
-    fn step(&mut self, cmd: Input) -> Vec<Operation> {
+    fn step(&mut self, cmd: Input, memory_model: Option<&dyn MemoryModel>) -> Vec<Operation> {
         let mut operations = Vec::new();
         let cmd = match cmd {
             Input::Landmark(message) => {
                 operations.push(Operation::Landmark { message });
                 return operations;
             }
+            Input::Trace(itm::TracePacket { ts, packet }) => {
+                operations.push(Operation::Trace { ts, packet });
+                return operations;
+            }
+            Input::Failure(failure) => {
+                operations.push(self.failure(failure));
+                return operations;
+            }
+            Input::Swd(event) => {
+                return self
+                    .swd
+                    .push(event)
+                    .into_iter()
+                    .flat_map(|input| self.step(input, memory_model))
+                    .collect();
+            }
             Input::Command(cmd) => cmd,
         };
         let ts = cmd.ts;
@@ -245,6 +511,7 @@ impl VmState {
                 }
                 (_, 0x0, RoW::W) => {
                     log::debug!("DP.ABORT: {:#0x}", cmd.data);
+                    self.dp.ctrl_stat.abort(dp::Abort::from(cmd.data));
                     operations.push(Operation::DpRegisterAccess {
                         ts,
                         rw,
@@ -253,10 +520,12 @@ impl VmState {
                     });
                 }
                 (0x0, 0x4, rw) => {
-                    // TODO: Add more verbose information, verify
-                    // that NXP is not using some weird MEM-AP features
-                    // that has to be handled, like transaction counters?
                     log::debug!("DP.CTRL/STAT: {}:{:#0x}", rw, cmd.data);
+                    let ctrl_stat = dp::CtrlStat::from(cmd.data);
+                    match rw {
+                        RoW::R => self.dp.ctrl_stat = ctrl_stat,
+                        RoW::W => self.dp.ctrl_stat.write(ctrl_stat),
+                    }
                     operations.push(Operation::DpRegisterAccess {
                         ts,
                         rw,
@@ -347,10 +616,6 @@ impl VmState {
             let ap_addr = (self.dp.select.apbanksel().value() << 4) | a;
             match (ap_addr, rw) {
                 (0x0, rw) => {
-                    let new_csw = ap::Csw::try_from(cmd.data).unwrap();
-                    // TODO: This log is a little bit confusing as readonly fields on write should be ignored
-                    // Keep it?
-                    log::debug!("AP[{apsel}].CSW: {}:{:#0x?}", rw, new_csw);
                     operations.push(Operation::ApRegisterAccess {
                         ts,
                         rw,
@@ -358,6 +623,22 @@ impl VmState {
                         value: cmd.data,
                         apsel,
                     });
+                    let csw_type = self.current_ap().idr.map(|v| v.csw_type());
+                    if csw_type == Some(regdoctor_adios_ext::CswType::Jtag) {
+                        log::debug!("AP[{apsel}].CSW (JTAG-AP): {}:{:#0x}", rw, cmd.data);
+                        return operations;
+                    }
+                    let Ok(new_csw) = ap::Csw::try_from(cmd.data) else {
+                        log::error!(
+                            "AP[{apsel}].CSW: {}:{:#0x} has reserved Size/AddrInc, keeping the previous CSW",
+                            rw,
+                            cmd.data
+                        );
+                        return operations;
+                    };
+                    // TODO: This log is a little bit confusing as readonly fields on write should be ignored
+                    // Keep it?
+                    log::debug!("AP[{apsel}].CSW: {}:{:#0x?}", rw, new_csw);
                     let csw = &mut self.current_ap_mut().csw;
                     match rw {
                         RoW::R => *csw = Some(new_csw),
@@ -377,10 +658,43 @@ impl VmState {
                         apsel,
                     });
                     if rw == RoW::W {
-                        self.current_ap_mut().tar = Some(cmd.data);
+                        let ap = self.current_ap_mut();
+                        ap.tar = Some(cmd.data);
+                        ap.tar_wrapped = false;
+                        ap.large_data.clear();
                     } else {
-                        assert_eq!(self.current_ap().tar, Some(cmd.data));
+                        let tar_wrap = self.tar_wrap;
+                        let ap = self.current_ap_mut();
+                        match ap.tar {
+                            Some(tar) if tar != cmd.data && ap.tar_wrapped => log::warn!(
+                                "AP[{apsel}].TAR reads {:#010x} instead of {tar:#010x}, \
+                                 auto-increment does not wrap at {tar_wrap} bytes (--tar-wrap)",
+                                cmd.data
+                            ),
+                            Some(tar) if tar != cmd.data => log::warn!(
+                                "AP[{apsel}].TAR reads {:#010x} instead of {tar:#010x}",
+                                cmd.data
+                            ),
+                            _ => {}
+                        }
+                        ap.tar = Some(cmd.data);
+                        ap.tar_wrapped = false;
+                    }
+                }
+                (0x8, rw) => {
+                    log::debug!("AP[{apsel}].TAR_MSW: {}:{:#0x}", rw, cmd.data);
+                    operations.push(Operation::ApRegisterAccess {
+                        ts,
+                        rw,
+                        name: "TAR_MSW",
+                        value: cmd.data,
+                        apsel,
+                    });
+                    let ap = self.current_ap_mut();
+                    if rw == RoW::W {
+                        ap.large_data.clear();
                     }
+                    ap.tar_msw = cmd.data;
                 }
                 (0xc, rw) => {
                     // TODO: Configurability of what needs to be printed out has to be improved
@@ -392,9 +706,8 @@ impl VmState {
                         value: cmd.data,
                         apsel,
                     });
-                    // Unwrap: If TAR is not set, we have no clue what address we are accessing.
-                    let addr = self.current_ap().tar.unwrap();
-                    self.drw_access(&mut operations, ts, rw, addr, cmd.data);
+                    let addr = self.tar_address();
+                    self.drw_access(&mut operations, ts, rw, addr, cmd.data, memory_model);
                 }
                 (0x10, rw) => {
                     log::debug!("AP[{apsel}].BD0: {}:{:#0x}", rw, cmd.data);
@@ -406,9 +719,8 @@ impl VmState {
                         apsel,
                     });
                     // Memory addressing for BDx C.2.6.2, IHI0031G
-                    // Unwrap: If TAR is not set, we have no clue what address we are accessing.
-                    let addr = self.current_ap().tar.unwrap() & 0xFFFFFFF0;
-                    self.bd_access(&mut operations, ts, rw, addr, cmd.data);
+                    let addr = self.tar_address() & !0xF;
+                    self.bd_access(&mut operations, ts, rw, addr, cmd.data, memory_model);
                 }
                 (0x14, rw) => {
                     log::debug!("AP[{apsel}].BD1: {}:{:#0x}", rw, cmd.data);
@@ -420,9 +732,8 @@ impl VmState {
                         apsel,
                     });
                     // Memory addressing for BDx C.2.6.2, IHI0031G
-                    // Unwrap: If TAR is not set, we have no clue what address we are accessing.
-                    let addr = self.current_ap().tar.unwrap() & 0xFFFFFFF0 | 0x4;
-                    self.bd_access(&mut operations, ts, rw, addr, cmd.data);
+                    let addr = self.tar_address() & !0xF | 0x4;
+                    self.bd_access(&mut operations, ts, rw, addr, cmd.data, memory_model);
                 }
                 (0x18, rw) => {
                     log::debug!("AP[{apsel}].BD2: {}:{:#0x}", rw, cmd.data);
@@ -434,9 +745,8 @@ impl VmState {
                         apsel,
                     });
                     // Memory addressing for BDx C.2.6.2, IHI0031G
-                    // Unwrap: If TAR is not set, we have no clue what address we are accessing.
-                    let addr = self.current_ap().tar.unwrap() & 0xFFFFFFF0 | 0x8;
-                    self.bd_access(&mut operations, ts, rw, addr, cmd.data);
+                    let addr = self.tar_address() & !0xF | 0x8;
+                    self.bd_access(&mut operations, ts, rw, addr, cmd.data, memory_model);
                 }
                 (0x1c, rw) => {
                     log::debug!("AP[{apsel}].BD3: {}:{:#0x}", rw, cmd.data);
@@ -448,12 +758,28 @@ impl VmState {
                         apsel,
                     });
                     // Memory addressing for BDx C.2.6.2, IHI0031G
-                    // Unwrap: If TAR is not set, we have no clue what address we are accessing.
-                    let addr = self.current_ap().tar.unwrap() & 0xFFFFFFF0 | 0xc;
-                    self.bd_access(&mut operations, ts, rw, addr, cmd.data);
+                    let addr = self.tar_address() & !0xF | 0xc;
+                    self.bd_access(&mut operations, ts, rw, addr, cmd.data, memory_model);
+                }
+                (0xe0, rw) => {
+                    log::debug!("AP[{apsel}].CFG1: {}:{:#0x}", rw, cmd.data);
+                    operations.push(Operation::ApRegisterAccess {
+                        ts,
+                        rw,
+                        name: "CFG1",
+                        value: cmd.data,
+                        apsel,
+                    });
                 }
                 (0xf4, rw) => {
                     log::debug!("AP[{apsel}].CFG: {}:{:#0x}", rw, cmd.data);
+                    let cfg = ap::Cfg::from(cmd.data);
+                    if self.endianness.is_none() {
+                        self.current_ap_mut().endianness = match cfg.be() {
+                            true => Endianness::Big,
+                            false => Endianness::Little,
+                        };
+                    }
                     operations.push(Operation::ApRegisterAccess {
                         ts,
                         rw,
@@ -464,6 +790,9 @@ impl VmState {
                 }
                 (0xf8, rw) => {
                     log::debug!("AP[{apsel}].BASE: {}:{:#0x}", rw, cmd.data);
+                    if rw == RoW::R {
+                        self.current_ap_mut().base = Some(cmd.data);
+                    }
                     operations.push(Operation::ApRegisterAccess {
                         ts,
                         rw,
@@ -495,6 +824,67 @@ impl VmState {
         operations
     }
 
+    fn failure(&mut self, failure: adios_common::Failure) -> Operation {
+        // FAULT is answered only once a sticky flag is set, B4.2.4, IHI0031G. Which one is not
+        // known, STICKYERR stands in unless one already is.
+        if failure.ack == swd::Ack::Fault && !self.dp.ctrl_stat.sticky_error() {
+            self.dp.ctrl_stat.set_stickyerr(true);
+        }
+        let rw = if failure.rnw { RoW::R } else { RoW::W };
+        let a = failure.a.value() << 2;
+        let (apsel, name, address) = if failure.apndp {
+            let ap = self.current_ap();
+            let ap_addr = (self.dp.select.apbanksel().value() << 4) | a;
+            let name = match ap_addr {
+                0x0 => "CSW",
+                0x4 => "TAR",
+                0x8 => "TAR_MSW",
+                0xc => "DRW",
+                0x10 => "BD0",
+                0x14 => "BD1",
+                0x18 => "BD2",
+                0x1c => "BD3",
+                0xe0 => "CFG1",
+                0xf4 => "CFG",
+                0xf8 => "BASE",
+                0xfc => "IDR",
+                _ => "?",
+            };
+            let tar_address = ap.tar.map(|tar| (ap.tar_msw as u64) << 32 | tar as u64);
+            let address = match ap_addr {
+                0xc => tar_address,
+                // Memory addressing for BDx C.2.6.2, IHI0031G
+                0x10..=0x1c => tar_address.map(|v| v & !0xF | (ap_addr & 0xc) as u64),
+                _ => None,
+            };
+            (Some(self.dp.select.apsel()), name, address)
+        } else {
+            let name = match (self.dp.select.dpbanksel().value(), a, rw) {
+                (_, 0x0, RoW::R) => "DPIDR",
+                (_, 0x0, RoW::W) => "ABORT",
+                (0x0, 0x4, _) => "CTRL",
+                (0x1, 0x4, _) => "DLCR",
+                (0x2, 0x4, RoW::R) => "TARGETID",
+                (0x3, 0x4, RoW::R) => "DLPIDR",
+                (0x4, 0x4, RoW::R) => "EVENTSTAT",
+                (_, 0x8, RoW::R) => "RESEND",
+                (_, 0x8, RoW::W) => "SELECT",
+                (_, 0xc, RoW::R) => "RDBUFF",
+                _ => "?",
+            };
+            (None, name, None)
+        };
+        Operation::Failure {
+            ts: failure.ts,
+            apsel,
+            rw,
+            name,
+            ack: failure.ack,
+            retries: failure.retries,
+            address,
+        }
+    }
+
     fn current_ap(&self) -> &Ap {
         &self.aps[self.dp.select.apsel() as usize]
     }
@@ -503,102 +893,221 @@ impl VmState {
         &mut self.aps[self.dp.select.apsel() as usize]
     }
 
+    /// Address in TAR_MSW:TAR, accessed through DRW/BDx
+    fn tar_address(&mut self) -> u64 {
+        let apsel = self.dp.select.apsel();
+        let tar_wrap = self.tar_wrap;
+        let ap = self.current_ap_mut();
+        // Unwrap: If TAR is not set, we have no clue what address we are accessing.
+        let address = (ap.tar_msw as u64) << 32 | ap.tar.unwrap() as u64;
+        if std::mem::take(&mut ap.tar_wrapped) {
+            log::warn!(
+                "AP[{apsel}] access to {address:#010x} relies on TAR auto-increment \
+                 wrapping at the {tar_wrap} byte boundary"
+            );
+        }
+        address
+    }
+
+    /// Auto-increment TAR within the `tar_wrap` block it points to
+    fn increment_tar(&mut self, increment: u32) {
+        let block = !(self.tar_wrap - 1);
+        let ap = self.current_ap_mut();
+        // Unwrap: If we got here, we must have accessed TAR so it exists for sure
+        let tar = ap.tar.unwrap();
+        let incremented = tar.wrapping_add(increment);
+        ap.tar = Some(tar & block | incremented & !block);
+        ap.tar_wrapped = tar & block != incremented & block;
+    }
+
     // Apply address increment if enabled according to CSW configuration
     fn drw_access(
         &mut self,
         operations: &mut Vec<Operation>,
         ts: Option<Timestamp>,
         rw: RoW,
-        address: u32,
+        address: u64,
         value: u32,
+        memory_model: Option<&dyn MemoryModel>,
     ) {
         // Unwrap: If CSW is not known, VM does not know data access details
         let csw = self.current_ap().csw.unwrap();
-        let tar_two_lsbs = (address as u8) & 0b11;
         log::debug!("Access size: {:?}", csw.size());
         log::debug!("Address incrementing: {:?}", csw.addr_inc());
 
-        // Byte lanes C.2.2.6, IHI0031G
-        let mem_value = self
-            .current_ap_mut()
-            .memory
-            .entry(address & 0xFFFFFFFC)
-            .or_insert(0x0);
-        // Does not matter if read or write, this is a simulator after all
-        let value = match (tar_two_lsbs, csw.size()) {
-            // TODO: unittest? `value` might be incorrectly interpreted here
-            (0b00, ap::CswSize::Word) => {
-                *mem_value = value;
-                value
-            }
-            (0b00, ap::CswSize::Halfword) => {
-                let value = value & 0x0000FFFF;
-                *mem_value = (*mem_value & 0xFFFF0000) | value;
-                value
-            }
-            (0b10, ap::CswSize::Halfword) => {
-                let value = value & 0xFFFF0000;
-                *mem_value = (*mem_value & 0x0000FFFF) | value;
-                value >> 16
-            }
-            (0b00, ap::CswSize::Byte) => {
-                let value = value & 0x000000FF;
-                *mem_value = (*mem_value & 0xFFFFFF00) | value;
-                value
-            }
-            (0b01, ap::CswSize::Byte) => {
-                let value = value & 0x0000FF00;
-                *mem_value = (*mem_value & 0xFFFF00FF) | value;
-                value >> 8
+        let size = match csw.size() {
+            ap::CswSize::Byte => 1,
+            ap::CswSize::Halfword => 2,
+            ap::CswSize::Word => 4,
+            ap::CswSize::Doubleword | ap::CswSize::Bits128 | ap::CswSize::Bits256 => {
+                return self.large_data_access(operations, ts, rw, address, value, memory_model);
             }
-            (0b10, ap::CswSize::Byte) => {
-                let value = value & 0x00FF0000;
-                *mem_value = (*mem_value & 0xFF00FFFF) | value;
-                value >> 16
-            }
-            (0b11, ap::CswSize::Byte) => {
-                let value = value & 0xFF000000;
-                *mem_value = (*mem_value & 0x00FFFFFF) | value;
-                value >> 24
+        };
+        // Packed transfers C.2.2.7, IHI0031G: every DRW access carries as many transfers
+        // as fit in a word, each in the byte lanes of its own address
+        let transfers = match csw.addr_inc() {
+            ap::CswAddrInc::Packed => 4 / size,
+            _ => 1,
+        };
+        let trnmode = self.dp.ctrl_stat.trnmode();
+        if rw == RoW::W && trnmode != dp::TrnMode::Normal {
+            // Pushed operations: the AP reads from TAR and compares it with the written value,
+            // the outcome only shows up in CTRL/STAT.STICKYCMP
+            log::info!("{trnmode:?}:{address:#010x} {value:#010x}");
+            operations.push(Operation::PushedCompare {
+                ts,
+                apsel: self.dp.select.apsel(),
+                mode: trnmode,
+                address,
+                value,
+                mask: self.dp.ctrl_stat.masklane_mask(),
+                suspect: self.dp.ctrl_stat.sticky_error(),
+            });
+        } else {
+            for i in 0..transfers {
+                let address = address.wrapping_add((i * size) as u64);
+                let Some(value) = self.lanes_access(rw, address, size, value, memory_model) else {
+                    log::error!("Invalid or impl defined DRW byte lanes usage?");
+                    return;
+                };
+                let rw_arrow = rw.arrow();
+                let mem_ap_value = match size {
+                    4 => {
+                        log::info!("{rw}:{address:#010x} {rw_arrow} {:#010x}", value);
+                        MemApValue::Word(value)
+                    }
+                    2 => {
+                        let value = value as u16;
+                        log::info!("{rw}:{address:#010x} {rw_arrow} {:#06x}", value);
+                        MemApValue::Halfword(value)
+                    }
+                    _ => {
+                        let value = value as u8;
+                        log::info!("{rw}:{address:#010x} {rw_arrow} {:#04x}", value);
+                        MemApValue::Byte(value)
+                    }
+                };
+                operations.push(Operation::MemAp {
+                    ts,
+                    apsel: self.dp.select.apsel(),
+                    rw,
+                    address,
+                    value: mem_ap_value,
+                    suspect: self.dp.ctrl_stat.sticky_error(),
+                });
             }
-            (_lsbs, _size) => {
-                log::error!("Invalid or impl defined DRW byte lanes usage?");
-                return;
+        }
+
+        match csw.addr_inc() {
+            ap::CswAddrInc::Single => self.increment_tar(size),
+            ap::CswAddrInc::Packed => self.increment_tar(4),
+            ap::CswAddrInc::Disabled => {}
+        }
+    }
+
+    /// `size` bytes at `address` carried in their byte lanes of `drw`
+    ///
+    /// Byte lanes C.2.2.6 and C.2.2.7, IHI0031G. Memory words are kept as read with a word
+    /// access, so the lanes are the same in DRW and in memory. Returns the accessed value,
+    /// `None` for unaligned accesses.
+    fn lanes_access(
+        &mut self,
+        rw: RoW,
+        address: u64,
+        size: u32,
+        drw: u32,
+        memory_model: Option<&dyn MemoryModel>,
+    ) -> Option<u32> {
+        if !address.is_multiple_of(size as u64) {
+            return None;
+        }
+        let lane = (address & 0b11) as u32;
+        let shift = match self.current_ap().endianness {
+            Endianness::Little => lane * 8,
+            Endianness::Big => (4 - lane - size) * 8,
+        };
+        let lanes = u32::MAX >> (32 - size * 8);
+        let value = (drw >> shift) & lanes;
+        let apsel = self.dp.select.apsel();
+        let memory = &mut self.current_ap_mut().memory;
+        let known = memory.contains_key(&(address & !0b11));
+        let mem_value = memory.entry(address & !0b11).or_insert(0x0);
+        // Does not matter if read or write, this is a simulator after all
+        let stored = match (rw, memory_model) {
+            (RoW::W, Some(memory_model)) if known => {
+                let old = (*mem_value >> shift) & lanes;
+                memory_model.write(apsel, address, size, old, value) & lanes
             }
+            _ => value,
         };
+        *mem_value = (*mem_value & !(lanes << shift)) | (stored << shift);
+        Some(value)
+    }
 
-        let rw_arrow = rw.arrow();
-        let mem_ap_value = match csw.size() {
-            ap::CswSize::Word => {
-                log::info!("{rw}:{address:#010x} {rw_arrow} {:#010x}", value);
-                MemApValue::Word(value)
-            }
-            ap::CswSize::Halfword => {
-                let value = value as u16;
-                log::info!("{rw}:{address:#010x} {rw_arrow} {:#06x}", value);
-                MemApValue::Halfword(value)
-            }
-            ap::CswSize::Byte => {
-                let value = value as u8;
-                log::info!("{rw}:{address:#010x} {rw_arrow} {:#04x}", value);
-                MemApValue::Byte(value)
-            }
+    /// One DRW beat of a MEM-AP Large Data Extension access, C.2.2.8, IHI0031G
+    ///
+    /// Beats carry the value least significant word first to consecutive words starting at TAR.
+    /// The access is complete, reported and TAR incremented, with the last beat.
+    fn large_data_access(
+        &mut self,
+        operations: &mut Vec<Operation>,
+        ts: Option<Timestamp>,
+        rw: RoW,
+        address: u64,
+        value: u32,
+        memory_model: Option<&dyn MemoryModel>,
+    ) {
+        // Unwrap: Only called with a known CSW
+        let csw = self.current_ap().csw.unwrap();
+        let beats = match csw.size() {
+            ap::CswSize::Doubleword => 2,
+            ap::CswSize::Bits128 => 4,
+            _ => 8,
+        };
+        if !address.is_multiple_of(beats as u64 * 4) {
+            log::error!("Unaligned large data access at {address:#010x}");
+            return;
+        }
+        let apsel = self.dp.select.apsel();
+        let ap = self.current_ap_mut();
+        let beat_address = address.wrapping_add(ap.large_data.len() as u64 * 4);
+        let stored = match (rw, memory_model) {
+            (RoW::W, Some(memory_model)) => match ap.memory.get(&beat_address) {
+                Some(&old) => memory_model.write(apsel, beat_address, 4, old, value),
+                None => value,
+            },
+            _ => value,
+        };
+        ap.memory.insert(beat_address, stored);
+        ap.large_data.push(value);
+        if ap.large_data.len() < beats as usize {
+            return;
+        }
+        let mut words = std::mem::take(&mut ap.large_data);
+        // The first beat is the most significant one on big-endian APs
+        if ap.endianness == Endianness::Big {
+            words.reverse();
+        }
+        let word = |i: usize| words[i] as u128;
+        let mem_ap_value = match beats {
+            2 => MemApValue::Doubleword((word(1) << 32 | word(0)) as u64),
+            4 => MemApValue::Bits128(word(3) << 96 | word(2) << 64 | word(1) << 32 | word(0)),
+            _ => MemApValue::Bits256([
+                word(3) << 96 | word(2) << 64 | word(1) << 32 | word(0),
+                word(7) << 96 | word(6) << 64 | word(5) << 32 | word(4),
+            ]),
         };
+        log::info!("{rw}:{address:#010x} {} {mem_ap_value:?}", rw.arrow());
         operations.push(Operation::MemAp {
             ts,
             apsel: self.dp.select.apsel(),
             rw,
             address,
             value: mem_ap_value,
+            suspect: self.dp.ctrl_stat.sticky_error(),
         });
-
-        // Unwrap: If we got here, we must have accessed TAR so it exists for sure
-        let tar = self.current_ap_mut().tar.as_mut().unwrap();
-        match (csw.addr_inc(), csw.size()) {
-            (ap::CswAddrInc::Single, ap::CswSize::Word) => *tar += 4,
-            (ap::CswAddrInc::Single, ap::CswSize::Halfword) => *tar += 2,
-            (ap::CswAddrInc::Single, ap::CswSize::Byte) => *tar += 1,
-            _ => {}
+        if csw.addr_inc() == ap::CswAddrInc::Single {
+            self.increment_tar(beats * 4);
         }
     }
 
@@ -607,11 +1116,19 @@ impl VmState {
         operations: &mut Vec<Operation>,
         ts: Option<Timestamp>,
         rw: RoW,
-        address: u32,
+        address: u64,
         value: u32,
+        memory_model: Option<&dyn MemoryModel>,
     ) {
         let rw_arrow = rw.arrow();
-        self.current_ap_mut().memory.insert(address, value);
+        let stored = match (rw, memory_model) {
+            (RoW::W, Some(memory_model)) => match self.current_ap().memory.get(&address) {
+                Some(&old) => memory_model.write(self.dp.select.apsel(), address, 4, old, value),
+                None => value,
+            },
+            _ => value,
+        };
+        self.current_ap_mut().memory.insert(address, stored);
         log::info!("{rw}:{address:#010x} {rw_arrow} {value:#010x}");
         operations.push(Operation::MemAp {
             ts,
@@ -619,6 +1136,7 @@ impl VmState {
             rw,
             address,
             value: MemApValue::Word(value),
+            suspect: self.dp.ctrl_stat.sticky_error(),
         });
     }
 }
@@ -628,6 +1146,9 @@ impl Default for VmState {
         Self {
             dp: Dp::default(),
             aps: core::array::from_fn(|_| Ap::default()),
+            endianness: None,
+            tar_wrap: TAR_WRAP_DEFAULT,
+            swd: Default::default(),
         }
     }
 }
@@ -643,6 +1164,11 @@ pub enum Operation {
     Landmark {
         message: String,
     },
+    /// ITM/DWT packet from SWO, does not affect the VM state
+    Trace {
+        ts: Option<Timestamp>,
+        packet: itm::Packet,
+    },
     DpRegisterAccess {
         ts: Option<Timestamp>,
         rw: RoW,
@@ -660,24 +1186,544 @@ pub enum Operation {
         ts: Option<Timestamp>,
         apsel: u8,
         rw: RoW,
-        address: u32,
+        address: u64,
         value: MemApValue,
+        /// Happened while a sticky error was pending, the target should have ignored it
+        suspect: bool,
+    },
+    /// Transfer the target did not complete, does not affect the VM state
+    Failure {
+        ts: Option<Timestamp>,
+        /// `None` for DP registers
+        apsel: Option<u8>,
+        rw: RoW,
+        name: &'static str,
+        ack: swd::Ack,
+        /// Attempts after the first one, if known
+        retries: Option<u32>,
+        /// Memory a DRW/BDx access was going for
+        address: Option<u64>,
+    },
+    /// DRW write in the pushed-verify or pushed-compare CTRL/STAT.TRNMODE
+    PushedCompare {
+        ts: Option<Timestamp>,
+        apsel: u8,
+        mode: dp::TrnMode,
+        address: u64,
+        /// Value the memory is compared with
+        value: u32,
+        /// Byte lanes taking part in the comparison (CTRL/STAT.MASKLANE)
+        mask: u32,
+        suspect: bool,
     },
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum MemApValue {
+    /// `[bits 127:0, bits 255:128]`
+    Bits256([u128; 2]),
+    Bits128(u128),
+    Doubleword(u64),
     Word(u32),
     Halfword(u16),
     Byte(u8),
 }
 
 impl MemApValue {
+    /// Least significant 32 bits
     pub fn as_(&self) -> u32 {
         match *self {
+            MemApValue::Bits256([v, _]) => v as _,
+            MemApValue::Bits128(v) => v as _,
+            MemApValue::Doubleword(v) => v as _,
             MemApValue::Word(v) => v,
             MemApValue::Halfword(v) => v as _,
             MemApValue::Byte(v) => v as _,
         }
     }
+
+    /// Whole value, `None` if it is wider than 64 bits
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            MemApValue::Bits256(_) | MemApValue::Bits128(_) => None,
+            MemApValue::Doubleword(v) => Some(v),
+            v => Some(v.as_() as u64),
+        }
+    }
+
+    /// Access size in bytes
+    pub fn size(&self) -> u32 {
+        match self {
+            MemApValue::Bits256(_) => 32,
+            MemApValue::Bits128(_) => 16,
+            MemApValue::Doubleword(_) => 8,
+            MemApValue::Word(_) => 4,
+            MemApValue::Halfword(_) => 2,
+            MemApValue::Byte(_) => 1,
+        }
+    }
+
+    /// Bytes as they are laid out in the memory of an AP with the given byte order
+    pub fn to_bytes(self, endianness: Endianness) -> Vec<u8> {
+        let mut bytes = self.to_le_bytes();
+        if endianness == Endianness::Big {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    fn to_le_bytes(self) -> Vec<u8> {
+        match self {
+            MemApValue::Bits256([low, high]) => [low.to_le_bytes(), high.to_le_bytes()].concat(),
+            MemApValue::Bits128(v) => v.to_le_bytes().to_vec(),
+            MemApValue::Doubleword(v) => v.to_le_bytes().to_vec(),
+            MemApValue::Word(v) => v.to_le_bytes().to_vec(),
+            MemApValue::Halfword(v) => v.to_le_bytes().to_vec(),
+            MemApValue::Byte(v) => vec![v],
+        }
+    }
+}
+
+impl Display for MemApValue {
+    /// Hexadecimal, zero-padded to at least a word
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            MemApValue::Bits256([low, high]) => write!(f, "{high:#034x}{low:032x}"),
+            MemApValue::Bits128(v) => write!(f, "{v:#034x}"),
+            MemApValue::Doubleword(v) => write!(f, "{v:#018x}"),
+            v => write!(f, "{:#010x}", v.as_()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use adios_common::Command;
+
+    use super::*;
+
+    fn ap_write(a: u8, data: u32) -> Input {
+        Input::Command(Command {
+            ts: None,
+            apndp: true,
+            rnw: false,
+            a: u2::new(a >> 2),
+            data,
+        })
+    }
+
+    fn ap_read(a: u8, data: u32) -> Input {
+        Input::Command(Command {
+            ts: None,
+            apndp: true,
+            rnw: true,
+            a: u2::new(a >> 2),
+            data,
+        })
+    }
+
+    fn dp(rnw: bool, a: u8, data: u32) -> Input {
+        Input::Command(Command {
+            ts: None,
+            apndp: false,
+            rnw,
+            a: u2::new(a >> 2),
+            data,
+        })
+    }
+
+    fn run(commands: &[Input]) -> (Vec<(u64, MemApValue)>, VmState) {
+        let mut vm = Vm::new();
+        let mut accesses = Vec::new();
+        while let Some(step) = vm.step_forward(commands) {
+            for operation in step.operations {
+                if let Operation::MemAp { address, value, .. } = operation {
+                    accesses.push((address, value));
+                }
+            }
+        }
+        (accesses, vm.state().clone())
+    }
+
+    #[test]
+    fn packed_transfers() {
+        let (accesses, state) = run(&[
+            // Halfword, packed increment
+            ap_write(0x0, 0x23000021),
+            ap_write(0x4, 0x20000002),
+            ap_write(0xc, 0x1111_2222),
+            ap_write(0xc, 0x3333_4444),
+        ]);
+        assert_eq!(
+            accesses,
+            [
+                (0x20000002, MemApValue::Halfword(0x1111)),
+                (0x20000004, MemApValue::Halfword(0x2222)),
+                (0x20000006, MemApValue::Halfword(0x3333)),
+                (0x20000008, MemApValue::Halfword(0x4444)),
+            ]
+        );
+        assert_eq!(state.aps[0].tar, Some(0x2000000a));
+        assert_eq!(state.aps[0].word(0x20000004), Some(0x3333_2222));
+
+        let (accesses, _) = run(&[
+            // Byte, packed increment
+            ap_write(0x0, 0x23000020),
+            ap_write(0x4, 0x20000000),
+            ap_write(0xc, 0x4433_2211),
+        ]);
+        let bytes: Vec<_> = accesses.iter().map(|(_, v)| *v).collect();
+        assert_eq!(
+            bytes,
+            [0x11, 0x22, 0x33, 0x44].map(MemApValue::Byte).to_vec()
+        );
+    }
+
+    #[test]
+    fn unusable_csw_values() {
+        let (_, state) = run(&[
+            ap_write(0x0, 0x23000012),
+            // Reserved Size, then reserved AddrInc
+            ap_write(0x0, 0x23000016),
+            ap_write(0x0, 0x23000032),
+        ]);
+        assert_eq!(state.aps[0].csw.map(u32::from), Some(0x23000012));
+
+        // JTAG-AP CSW is not a MEM-AP one
+        let (_, state) = run(&[
+            dp(false, 0x8, 0x0000_00f0),
+            ap_read(0xc, 0x0476_0010),
+            dp(false, 0x8, 0x0000_0000),
+            ap_write(0x0, 0x2300_0002),
+        ]);
+        assert!(state.aps[0].csw.is_none());
+    }
+
+    /// Every register is write-one-to-clear
+    struct OneToClear;
+
+    impl MemoryModel for OneToClear {
+        fn write(&self, _apsel: u8, _address: u64, _size: u32, old: u32, written: u32) -> u32 {
+            old & !written
+        }
+    }
+
+    #[test]
+    fn memory_model_needs_old_value() {
+        let commands = [
+            // Word, no increment
+            ap_write(0x0, 0x23000002),
+            ap_write(0x4, 0x20000000),
+            // Never seen before, stored as written
+            ap_write(0xc, 0x0000_00ff),
+            ap_write(0xc, 0x0000_000f),
+        ];
+        let mut vm = Vm::new().with_memory_model(Rc::new(OneToClear));
+        while vm.step_forward(&commands).is_some() {}
+        assert_eq!(vm.state().aps[0].word(0x20000000), Some(0x0000_00f0));
+    }
+
+    #[test]
+    fn big_endian_byte_lanes() {
+        let commands = [
+            ap_write(0x0, 0x23000010),
+            ap_write(0x4, 0x20000000),
+            ap_write(0xc, 0x1100_0000),
+            ap_write(0xc, 0x0022_0000),
+            ap_write(0x0, 0x23000011),
+            ap_write(0xc, 0x0000_3344),
+        ];
+        let mut vm = Vm::new().with_endianness(Endianness::Big);
+        let mut accesses = Vec::new();
+        while let Some(step) = vm.step_forward(&commands) {
+            for operation in step.operations {
+                if let Operation::MemAp { address, value, .. } = operation {
+                    accesses.push((address, value));
+                }
+            }
+        }
+        assert_eq!(
+            accesses,
+            [
+                (0x20000000, MemApValue::Byte(0x11)),
+                (0x20000001, MemApValue::Byte(0x22)),
+                (0x20000002, MemApValue::Halfword(0x3344)),
+            ]
+        );
+        let ap = &vm.state().aps[0];
+        assert_eq!(ap.word(0x20000000), Some(0x1122_3344));
+        assert_eq!(ap.bytes(0x20000000, 4), Some(vec![0x11, 0x22, 0x33, 0x44]));
+    }
+
+    #[test]
+    fn tar_wrap_and_msw() {
+        let (accesses, state) = run(&[
+            ap_write(0x0, 0x23000012),
+            ap_write(0x8, 0x1),
+            ap_write(0x4, 0x200003fc),
+            ap_write(0xc, 0x1111_1111),
+            // Wraps to the start of the 1KB block
+            ap_write(0xc, 0x2222_2222),
+            // BD0 in APBANKSEL 1
+            dp(false, 0x8, 0x10),
+            ap_write(0x0, 0x3333_3333),
+        ]);
+        assert_eq!(
+            accesses,
+            [
+                (0x1_200003fc, MemApValue::Word(0x1111_1111)),
+                (0x1_20000000, MemApValue::Word(0x2222_2222)),
+                (0x1_20000000, MemApValue::Word(0x3333_3333)),
+            ]
+        );
+        assert_eq!(state.aps[0].tar, Some(0x20000004));
+        assert_eq!(state.aps[0].word(0x1_20000000), Some(0x3333_3333));
+
+        let mut vm = Vm::new().with_tar_wrap(4096);
+        let commands = [
+            ap_write(0x0, 0x23000012),
+            ap_write(0x4, 0x200003fc),
+            ap_write(0xc, 0x0),
+        ];
+        while vm.step_forward(&commands).is_some() {}
+        assert_eq!(vm.state().aps[0].tar, Some(0x20000400));
+
+        // The AP increments past 1KB after all, TAR read re-syncs
+        let (accesses, state) = run(&[
+            ap_write(0x0, 0x23000012),
+            ap_write(0x4, 0x200003fc),
+            ap_write(0xc, 0x0),
+            ap_read(0x4, 0x20000400),
+            ap_write(0xc, 0x0),
+        ]);
+        assert_eq!(accesses[1].0, 0x20000400);
+        assert_eq!(state.aps[0].tar, Some(0x20000404));
+    }
+
+    #[test]
+    fn sticky_errors_and_pushed_operations() {
+        let commands = [
+            ap_write(0x0, 0x23000012),
+            ap_write(0x4, 0x20000000),
+            ap_write(0xc, 0x1234_5678),
+            // STICKYERR
+            dp(true, 0x4, 0x0000_0020),
+            ap_write(0xc, 0x0),
+            // STKERRCLR
+            dp(false, 0x0, 0x4),
+            // Pushed verify of the lower halfword
+            dp(false, 0x4, 0x5000_0304),
+            ap_write(0x4, 0x20000000),
+            ap_write(0xc, 0x9999_5678),
+        ];
+        let mut vm = Vm::new();
+        let mut operations = Vec::new();
+        while let Some(step) = vm.step_forward(&commands) {
+            operations.extend(step.operations.into_iter().filter_map(|v| match v {
+                Operation::MemAp { suspect, .. } => Some((suspect, None)),
+                Operation::PushedCompare {
+                    mode,
+                    address,
+                    value,
+                    mask,
+                    ..
+                } => Some((false, Some((mode, address, value, mask)))),
+                _ => None,
+            }));
+        }
+        assert_eq!(
+            operations,
+            [
+                (false, None),
+                (true, None),
+                (
+                    false,
+                    Some((dp::TrnMode::PushedVerify, 0x20000000, 0x9999_5678, 0xFFFF))
+                ),
+            ]
+        );
+        let state = vm.state();
+        assert!(!state.dp.ctrl_stat.sticky_error());
+        assert!(state.dp.ctrl_stat.cdbgpwrupreq());
+        // Pushed operations do not write the memory but still increment TAR
+        assert_eq!(state.aps[0].word(0x20000000), Some(0x1234_5678));
+        assert_eq!(state.aps[0].tar, Some(0x20000004));
+    }
+
+    #[test]
+    fn fault_sets_stickyerr() {
+        let fault = adios_common::Failure {
+            ts: None,
+            apndp: true,
+            rnw: false,
+            a: u2::new(3),
+            ack: swd::Ack::Fault,
+            retries: None,
+        };
+        let commands = [
+            ap_write(0x0, 0x23000012),
+            ap_write(0x4, 0x20000000),
+            fault.into(),
+            ap_write(0xc, 0x1234_5678),
+            // STKERRCLR
+            dp(false, 0x0, 0x4),
+            ap_write(0xc, 0x1234_5678),
+        ];
+        let mut vm = Vm::new();
+        let mut suspects = Vec::new();
+        while let Some(step) = vm.step_forward(&commands) {
+            suspects.extend(step.operations.into_iter().filter_map(|v| match v {
+                Operation::MemAp { suspect, .. } => Some(suspect),
+                _ => None,
+            }));
+        }
+        assert_eq!(suspects, [true, false]);
+
+        // STICKYORUN already explains the FAULT
+        let mut vm = Vm::new();
+        let commands = [dp(true, 0x4, 0x0000_0002), fault.into()];
+        while vm.step_forward(&commands).is_some() {}
+        assert!(!vm.state().dp.ctrl_stat.stickyerr());
+        assert!(vm.state().dp.ctrl_stat.stickyorun());
+    }
+
+    #[test]
+    fn large_data() {
+        let (accesses, state) = run(&[
+            // Doubleword, single increment
+            ap_write(0x0, 0x23000013),
+            ap_write(0x4, 0x20000008),
+            ap_write(0xc, 0x5555_6666),
+            ap_write(0xc, 0x7777_8888),
+            // 128 bits
+            ap_write(0x0, 0x23000014),
+            ap_write(0xc, 0x0000_0000),
+            ap_write(0xc, 0x1111_1111),
+            ap_write(0xc, 0x2222_2222),
+            ap_write(0xc, 0x3333_3333),
+        ]);
+        assert_eq!(
+            accesses,
+            [
+                (0x20000008, MemApValue::Doubleword(0x7777_8888_5555_6666)),
+                (
+                    0x20000010,
+                    MemApValue::Bits128(0x3333_3333_2222_2222_1111_1111_0000_0000)
+                ),
+            ]
+        );
+        assert_eq!(state.aps[0].tar, Some(0x20000020));
+        assert_eq!(state.aps[0].word(0x2000000c), Some(0x7777_8888));
+        assert_eq!(accesses[0].1.to_string(), "0x7777888855556666",);
+
+        // Big-endian, the first beat is the upper word
+        let commands = [
+            ap_write(0x0, 0x23000013),
+            ap_write(0x4, 0x20000008),
+            ap_write(0xc, 0x5555_6666),
+            ap_write(0xc, 0x7777_8888),
+        ];
+        let mut vm = Vm::new().with_endianness(Endianness::Big);
+        let mut values = Vec::new();
+        while let Some(step) = vm.step_forward(&commands) {
+            for operation in step.operations {
+                if let Operation::MemAp { value, .. } = operation {
+                    values.push(value);
+                }
+            }
+        }
+        assert_eq!(values, [MemApValue::Doubleword(0x5555_6666_7777_8888)]);
+        assert_eq!(
+            vm.state().aps[0].value(0x20000008, 8),
+            Some(0x5555_6666_7777_8888)
+        );
+    }
+
+    #[test]
+    fn wire_level_reads_and_failures() {
+        let packet = |rnw, apndp, a: u8, ack, data| {
+            Input::Swd(
+                swd::Packet {
+                    ts: None,
+                    apndp,
+                    rnw,
+                    a: u2::new(a >> 2),
+                    ack,
+                    data,
+                }
+                .into(),
+            )
+        };
+        let commands = [
+            packet(false, true, 0x0, swd::Ack::Ok, Some(0x23000012)),
+            packet(false, true, 0x4, swd::Ack::Ok, Some(0x20000000)),
+            // Posted reads, results come with the next DRW read and RDBUFF
+            packet(true, true, 0xc, swd::Ack::Ok, Some(0xffff_ffff)),
+            packet(true, true, 0xc, swd::Ack::Ok, Some(0x1111_1111)),
+            packet(true, false, 0xc, swd::Ack::Ok, Some(0x2222_2222)),
+            packet(false, true, 0xc, swd::Ack::Wait, None),
+            packet(false, true, 0xc, swd::Ack::Fault, None),
+            // Never retried, flushed at the end
+            packet(false, true, 0xc, swd::Ack::Wait, None),
+        ];
+        let mut vm = Vm::new();
+        let mut operations = Vec::new();
+        while let Some(step) = vm.step_forward(&commands) {
+            operations.extend(step.operations.into_iter().filter_map(|v| match v {
+                Operation::MemAp { address, value, .. } => Some((address, value.as_(), None)),
+                Operation::Failure {
+                    name,
+                    ack,
+                    retries,
+                    address,
+                    ..
+                } => Some((address?, 0, Some((name, ack, retries)))),
+                _ => None,
+            }));
+        }
+        assert_eq!(
+            operations,
+            [
+                (0x20000000, 0x1111_1111, None),
+                (0x20000004, 0x2222_2222, None),
+                (0x20000008, 0, Some(("DRW", swd::Ack::Fault, Some(1)))),
+                (0x20000008, 0, Some(("DRW", swd::Ack::Wait, Some(0)))),
+            ]
+        );
+    }
+
+    #[test]
+    fn wire_level_read_in_its_select() {
+        let packet = |rnw, apndp, a: u8, data| {
+            Input::Swd(
+                swd::Packet {
+                    ts: None,
+                    apndp,
+                    rnw,
+                    a: u2::new(a >> 2),
+                    ack: swd::Ack::Ok,
+                    data: Some(data),
+                }
+                .into(),
+            )
+        };
+        let commands = [
+            packet(true, true, 0x0, 0xffff_ffff),
+            // SELECT AP[1] before the CSW of AP[0] is read out
+            packet(false, false, 0x8, 0x0100_0000),
+            packet(true, false, 0xc, 0x2300_0012),
+        ];
+        let mut vm = Vm::new();
+        let mut accesses = Vec::new();
+        while let Some(step) = vm.step_forward(&commands) {
+            accesses.extend(step.operations.into_iter().filter_map(|v| match v {
+                Operation::ApRegisterAccess {
+                    apsel, name, value, ..
+                } => Some((apsel, name, value)),
+                _ => None,
+            }));
+        }
+        assert_eq!(accesses, [(0, "CSW", 0x2300_0012)]);
+        assert_eq!(vm.state().dp.select.apsel(), 1);
+    }
 }
@@ -1,3 +1,6 @@
+use std::path::PathBuf;
+
+use crate::adi;
 use clap::{Parser, ValueEnum};
 use clio::Input;
 
@@ -7,7 +10,7 @@ pub enum Mode {
     ///
     /// - It must exclusively contain CMSIS-DAP traffic between the host and the probe.
     /// - It must contain decoded CMSIS-DAP communication via
-    /// https://github.com/glaeqen/cmsis-dap-v2-dissector
+    ///   <https://github.com/glaeqen/cmsis-dap-v2-dissector>
     CmsisDapWsPdml,
     /// TXT file generated via sigrok-cli
     ///
@@ -27,6 +30,29 @@ pub enum Mode {
     SigrokSwd,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Endianness {
+    Little,
+    /// Legacy BE-32 byte lanes
+    Big,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SwoMode {
+    /// TXT file generated via sigrok-cli
+    ///
+    /// - It must contain `rx-data` annotations (hex format) from the sigrok's UART decoder
+    // Example:
+    // ```
+    // sigrok-cli -i swd.sr -P uart:rx=SWO:baudrate=1000000:format=hex -A uart=rx-data --protocol-decoder-samplenum
+    // ```
+    Uart,
+    /// Raw samples dumped via `sigrok-cli -O binary`
+    ///
+    /// - One byte per sample, `--swo-bit` selects the SWO channel
+    Manchester,
+}
+
 /// ARM ADIv5 replaying tool
 #[derive(Parser, Debug)]
 pub struct Args {
@@ -34,6 +60,29 @@ pub struct Args {
     #[arg(short = 's', long, value_parser)]
     pub svd: Vec<Input>,
 
+    /// svdtools-style YAML patches applied to the `--svd` files
+    ///
+    /// A patch with `_svd` only applies to the SVD file of the same file name,
+    /// otherwise it applies to all of them.
+    #[arg(long, value_parser)]
+    pub svd_patch: Vec<Input>,
+
+    /// Directory caching the registers parsed out of `--svd` files
+    ///
+    /// Cache entries are keyed by the SVD content hash, so editing an SVD file
+    /// simply results in a new entry.
+    #[arg(long)]
+    pub svd_cache: Option<PathBuf>,
+
+    /// Target profile registry (YAML) mapping what the target identifies as to SVD files
+    ///
+    /// The first DPIDR, TARGETID and AP IDR reads in the trace select the profile. Without
+    /// `--svd`, its SVD files are loaded into the per-AP address spaces it describes. Otherwise
+    /// the `--svd` files decode all APs and a mismatch with the profile is warned about.
+    /// Can be given multiple times.
+    #[arg(long)]
+    pub profiles: Vec<PathBuf>,
+
     /// An input file which content is interpreted depending on the chosen `--mode`
     #[arg(short = 'i', long, value_parser)]
     pub input: Input,
@@ -45,19 +94,143 @@ pub struct Args {
     #[arg(short = 'm', long, default_value_t = false)]
     pub mem_diffs: bool,
 
+    /// Diff first seen SVD registers against their reset values (with `--mem-diffs`)
+    ///
+    /// Only fields differing from the reset state are shown. Registers without
+    /// a reset value are shown in full.
+    #[arg(long, default_value_t = false)]
+    pub diff_from_reset: bool,
+
+    /// Only show MEM-AP accesses and diffs touching SVD registers matching the pattern
+    ///
+    /// Patterns are matched against register identifiers (`DEVICE.PERIPHERAL.REGISTER`),
+    /// `*` matches any sequence of characters and `?` a single one, e.g. `*.SCB.*`.
+    /// Can be given multiple times.
+    #[arg(long)]
+    pub only: Vec<String>,
+
     /// Show raw MEM-AP accesses
+    ///
+    /// DRW writes in the pushed-verify/pushed-compare CTRL/STAT.TRNMODE are shown with
+    /// the `PV:`/`PC:` prefix. Accesses done while a sticky error is pending are marked.
     #[arg(short = 'M', long, default_value_t = false)]
     pub raw_mem_ap: bool,
 
     /// Show raw DP accesses
+    ///
+    /// Each access is followed by the field diff against the previous access
+    /// of the same DP register.
     #[arg(long = "dp", default_value_t = false)]
     pub raw_dp: bool,
 
     /// Show raw AP accesses
+    ///
+    /// CSW, IDR, CFG, BASE and CFG1 accesses are followed by the field diff against the previous
+    /// access of the same register of that AP. CSW is decoded according to the bus type from IDR.
     #[arg(long = "ap", default_value_t = false)]
     pub raw_ap: bool,
 
+    /// MEM-AP byte order, overriding what the APs report in CFG.BE
+    ///
+    /// Without it, APs are little-endian until their CFG register is read.
+    #[arg(long, value_enum)]
+    pub endianness: Option<Endianness>,
+
+    /// Size of the block (a power of two) TAR auto-increment wraps within
+    ///
+    /// ADIv5 only guarantees auto-increment within 1KB blocks, bigger ones are
+    /// implementation defined. Accesses relying on a wrap are warned about.
+    #[arg(long, default_value_t = adi::TAR_WRAP_DEFAULT, value_parser = power_of_two)]
+    pub tar_wrap: u32,
+
     /// Enable timestamps (if available (SWD - yes, PDML - no))
     #[arg(long = "ts", default_value_t = false)]
     pub ts: bool,
+
+    /// Firmware ELF file used for symbol annotation
+    ///
+    /// MEM-AP accesses outside of SVD registers are annotated with `symbol+offset`.
+    /// If the ELF carries DWARF info, statically allocated variables are decoded
+    /// according to their type.
+    #[arg(long, value_parser)]
+    pub elf: Option<Input>,
+
+    /// Disassemble (Thumb-2) contiguous memory regions written through MEM-APs
+    ///
+    /// Listings are printed after the whole input has been replayed.
+    /// Regions overlapping registers known from the SVD files are skipped.
+    #[arg(long, default_value_t = false)]
+    pub disasm: bool,
+
+    /// Smallest written region (in bytes) that gets disassembled
+    #[arg(long, default_value_t = 16)]
+    pub disasm_min_size: usize,
+
+    /// Decode SEGGER RTT channels
+    ///
+    /// The control block is located via the `_SEGGER_RTT` symbol if `--elf` is given,
+    /// otherwise by its signature in the reconstructed memory.
+    #[arg(long, default_value_t = false)]
+    pub rtt: bool,
+
+    /// SWO capture carrying ITM/DWT trace packets
+    ///
+    /// Packets are interleaved with the `--input` by sample number, so it only makes
+    /// sense if both were taken from the same sigrok capture.
+    #[arg(long, value_parser)]
+    pub swo: Option<Input>,
+
+    /// Format of the `--swo` capture
+    #[arg(long, value_enum, default_value_t = SwoMode::Uart)]
+    pub swo_mode: SwoMode,
+
+    /// Bit of a raw sample carrying the SWO channel (`--swo-mode manchester`)
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..8))]
+    pub swo_bit: u8,
+
+    /// Store what MEM-AP writes leave in SVD registers instead of the written value
+    ///
+    /// Follows `modifiedWriteValues` (e.g. write-one-to-clear) and read-only registers.
+    /// Writes to memory never seen before are stored as they are.
+    #[arg(long, default_value_t = false)]
+    pub model_writes: bool,
+
+    /// Report MEM-AP accesses the SVD registers are not meant for
+    ///
+    /// Writes to read-only registers/fields, reads of write-only registers, reads
+    /// with side effects (`readAction`) and field values violating `writeConstraint`
+    /// or missing from the `enumeratedValues` are printed with the `A:` prefix.
+    #[arg(long, default_value_t = false)]
+    pub anomalies: bool,
+
+    /// List transfers the target did not complete (WAIT, FAULT, no ACK)
+    ///
+    /// Printed with the `F:` prefix in between the other operations, DRW/BDx
+    /// ones with the memory address they were going for.
+    #[arg(long, default_value_t = false)]
+    pub failures: bool,
+
+    /// Count transfers the target did not complete per register and ACK, printed at the end
+    #[arg(long, default_value_t = false)]
+    pub failure_summary: bool,
+
+    /// Reconstruct the CoreSight topology from the ROM table reads in the trace
+    ///
+    /// Printed as a tree with the `CS:` prefix at the end. Register layouts of the
+    /// identified components (ROM tables, SCS, DWT, FPB, ITM, TPIU) are used for
+    /// decoding, registers already described by `--svd` take precedence.
+    #[arg(long, default_value_t = false)]
+    pub coresight: bool,
+
+    /// Decode semihosting calls (halts on `BKPT 0xAB` serviced by the debugger)
+    #[arg(long, default_value_t = false)]
+    pub semihosting: bool,
+}
+
+fn power_of_two(value: &str) -> Result<u32, String> {
+    let value: u32 = value.parse().map_err(|e| format!("{e}"))?;
+    match value.is_power_of_two() {
+        true => Ok(value),
+        false => Err(format!("{value} is not a power of two")),
+    }
 }
@@ -0,0 +1,543 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use crate::{
+    adi::{Ap, VmState},
+    profile::AddressSpaces,
+};
+
+/// Offsets of the identification registers within the last 4KB block of a component
+const DEVARCH: u64 = 0xFBC;
+const DEVTYPE: u64 = 0xFCC;
+const PIDR4: u64 = 0xFD0;
+const PIDR0: u64 = 0xFE0;
+const CIDR0: u64 = 0xFF0;
+
+const CLASS_ROM_TABLE: u8 = 0x1;
+const CLASS_CORESIGHT: u8 = 0x9;
+
+/// JEP106 continuation code and identity code of Arm
+const ARM: (u8, u8) = (0x4, 0x3B);
+/// DEVARCH.ARCHITECT of Arm
+const ARM_ARCHITECT: u32 = 0x23B;
+
+/// What the CIDR/PIDR/DEVARCH/DEVTYPE reads of a component told the debugger
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identification {
+    /// CIDR1.CLASS
+    pub class: u8,
+    /// JEP106 continuation code and identity code, `None` for legacy ASCII identities
+    pub designer: Option<(u8, u8)>,
+    pub part: u16,
+    pub revision: u8,
+    /// Number of 4KB blocks the component occupies
+    pub blocks: u64,
+    /// Only CoreSight (class 0x9) components have these
+    pub devarch: Option<u32>,
+    pub devtype: Option<u8>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    RomTable,
+    Scs,
+    Dwt,
+    Fpb,
+    Itm,
+    Tpiu,
+    Etm,
+    Cti,
+    Other,
+}
+
+impl Kind {
+    fn of(id: &Identification) -> Self {
+        if id.class == CLASS_ROM_TABLE {
+            return Kind::RomTable;
+        }
+        let archid = id
+            .devarch
+            .filter(|v| v >> 21 == ARM_ARCHITECT)
+            .map(|v| v & 0xFFFF);
+        match archid {
+            Some(0x0AF7) => return Kind::RomTable,
+            Some(0x2A04) => return Kind::Scs,
+            Some(0x1A02) => return Kind::Dwt,
+            Some(0x1A03) => return Kind::Fpb,
+            Some(0x1A01) => return Kind::Itm,
+            Some(0x4A13) => return Kind::Etm,
+            Some(0x1A14) => return Kind::Cti,
+            _ => {}
+        }
+        if id.designer == Some(ARM) {
+            match id.part {
+                0x000 | 0x008 | 0x00C => return Kind::Scs,
+                0x001 => return Kind::Itm,
+                0x002 | 0x00A => return Kind::Dwt,
+                0x003 | 0x00B | 0x00E => return Kind::Fpb,
+                0x912 | 0x923 | 0x9A1 => return Kind::Tpiu,
+                0x924 | 0x925 | 0x975 => return Kind::Etm,
+                0x906 => return Kind::Cti,
+                _ => {}
+            }
+        }
+        match id.devtype {
+            Some(0x11) => Kind::Tpiu,
+            Some(0x13) => Kind::Etm,
+            Some(0x14) => Kind::Cti,
+            Some(0x43) => Kind::Itm,
+            _ => Kind::Other,
+        }
+    }
+
+    /// Layout name in [`regdoctor_adios_ext::CoreSight`]
+    pub fn layout(&self) -> &'static str {
+        match self {
+            Kind::RomTable => "ROM",
+            Kind::Scs => "SCS",
+            Kind::Dwt => "DWT",
+            Kind::Fpb => "FPB",
+            Kind::Itm => "ITM",
+            Kind::Tpiu => "TPIU",
+            Kind::Etm => "ETM",
+            Kind::Cti => "CTI",
+            Kind::Other => "Component",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Component {
+    /// Base of the 4KB block holding the identification registers
+    pub address: u64,
+    /// `None` if the trace does not contain (all of) the identification reads
+    pub id: Option<Identification>,
+    pub children: Vec<Component>,
+}
+
+impl Component {
+    pub fn kind(&self) -> Option<Kind> {
+        self.id.as_ref().map(Kind::of)
+    }
+
+    /// Components bigger than 4KB have the identification registers in their last block
+    ///
+    /// A size reaching below address 0 is bogus, the component is taken as a single block.
+    pub fn base(&self) -> u64 {
+        let blocks = self.id.as_ref().map_or(1, |v| v.blocks);
+        self.address
+            .checked_sub((blocks - 1) * 0x1000)
+            .unwrap_or(self.address)
+    }
+}
+
+/// Where the discovery of an AP started
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Root {
+    pub apsel: u8,
+    /// AP BASE register, `None` for components no ROM table in the trace points to
+    pub base: Option<u32>,
+    /// `None` if BASE says there are no debug components
+    pub component: Option<Component>,
+}
+
+/// CoreSight components the debugger discovered, as ROM table trees per AP
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub roots: Vec<Root>,
+}
+
+impl Topology {
+    /// Walk the ROM tables from the AP BASE registers through the memory read in the trace
+    pub fn discover(state: &VmState) -> Self {
+        let mut roots = Vec::new();
+        for (apsel, ap) in state.aps.iter().enumerate() {
+            let apsel = apsel as u8;
+            let mut visited = BTreeSet::new();
+            if let Some(base) = ap.base {
+                let component = rom_table_address(base).map(|v| walk(ap, v, &mut visited));
+                roots.push(Root {
+                    apsel,
+                    base: Some(base),
+                    component,
+                });
+            }
+            // Debuggers often skip BASE and go for the well-known addresses directly
+            let identified: BTreeSet<u64> = ap
+                .memory
+                .keys()
+                .filter(|v| *v & 0xFFF == CIDR0)
+                .map(|v| v & !0xFFF)
+                .filter(|v| !visited.contains(v))
+                .filter(|v| identify(ap, *v).is_some())
+                .collect();
+            let referenced: BTreeSet<u64> = identified
+                .iter()
+                .flat_map(|v| entries(ap, *v, &identify(ap, *v).unwrap()))
+                .collect();
+            for address in identified.difference(&referenced) {
+                if visited.contains(address) {
+                    continue;
+                }
+                roots.push(Root {
+                    apsel,
+                    base: None,
+                    component: Some(walk(ap, *address, &mut visited)),
+                });
+            }
+        }
+        Self { roots }
+    }
+
+    /// All components with the AP they were found on, parents before children
+    pub fn components(&self) -> Vec<(u8, &Component)> {
+        fn flatten<'a>(apsel: u8, component: &'a Component, out: &mut Vec<(u8, &'a Component)>) {
+            out.push((apsel, component));
+            for child in component.children.iter() {
+                flatten(apsel, child, out);
+            }
+        }
+        let mut components = Vec::new();
+        for root in self.roots.iter() {
+            if let Some(component) = &root.component {
+                flatten(root.apsel, component, &mut components);
+            }
+        }
+        components
+    }
+
+    /// Add the register layouts of the identified components to the address space
+    /// of the AP they were found on
+    ///
+    /// Registers already there (e.g. from `--svd`) take precedence.
+    pub fn register_layouts(&self, spaces: &mut AddressSpaces) {
+        let layouts = regdoctor_adios_ext::CoreSight::new();
+        for (apsel, component) in self.components() {
+            let Some(kind) = component.kind() else {
+                continue;
+            };
+            let layout = layouts.component(kind.layout(), component.base(), component.address);
+            for (address, register) in layout.regs {
+                spaces.ap_mut(apsel).regs.entry(address).or_insert(register);
+            }
+        }
+    }
+}
+
+impl Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_component(
+            f: &mut std::fmt::Formatter<'_>,
+            apsel: u8,
+            component: &Component,
+            depth: usize,
+        ) -> std::fmt::Result {
+            let indent = "  ".repeat(depth);
+            write!(f, "CS:AP[{apsel}] {indent}{:#010x} ", component.address)?;
+            match &component.id {
+                Some(id) => writeln!(f, "{}", Description(id))?,
+                None => writeln!(f, "identification not read")?,
+            }
+            for child in component.children.iter() {
+                write_component(f, apsel, child, depth + 1)?;
+            }
+            Ok(())
+        }
+        for root in self.roots.iter() {
+            match (root.base, &root.component) {
+                (Some(base), Some(_)) => writeln!(f, "CS:AP[{}] BASE {base:#010x}", root.apsel)?,
+                (Some(base), None) => writeln!(
+                    f,
+                    "CS:AP[{}] BASE {base:#010x} (no debug components)",
+                    root.apsel
+                )?,
+                (None, _) => writeln!(f, "CS:AP[{}] outside of the ROM tables", root.apsel)?,
+            }
+            if let Some(root_component) = &root.component {
+                write_component(f, root.apsel, root_component, 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Description<'a>(&'a Identification);
+
+impl Display for Description<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self.0;
+        match (Kind::of(id), id.class) {
+            (Kind::RomTable, _) => write!(f, "ROM table")?,
+            (Kind::Other, 0x0) => write!(f, "Generic verification component")?,
+            (Kind::Other, CLASS_CORESIGHT) => write!(f, "CoreSight component")?,
+            (Kind::Other, 0xB) => write!(f, "Peripheral test block")?,
+            (Kind::Other, 0xE) => write!(f, "Generic IP component")?,
+            (Kind::Other, 0xF) => write!(f, "CoreLink/PrimeCell component")?,
+            (Kind::Other, class) => write!(f, "Class {class:#x} component")?,
+            (kind, _) => write!(f, "{}", kind.layout())?,
+        }
+        match id.designer {
+            Some(designer) => match designer_name(designer) {
+                Some(name) => write!(f, ", {name}")?,
+                None => write!(f, ", JEP106 {:#x}:{:#04x}", designer.0, designer.1)?,
+            },
+            None => write!(f, ", legacy designer")?,
+        }
+        write!(f, " part {:#05x} rev {}", id.part, id.revision)?;
+        if let Some(name) = id.designer.and_then(|v| part_name(v, id.part)) {
+            write!(f, " ({name})")?;
+        }
+        if id.blocks > 1 {
+            write!(f, ", {} 4KB blocks", id.blocks)?;
+        }
+        if let Some(devarch) = id.devarch {
+            write!(f, ", DEVARCH {devarch:#010x}")?;
+        }
+        if let Some(devtype) = id.devtype {
+            write!(f, ", DEVTYPE {devtype:#04x}")?;
+        }
+        Ok(())
+    }
+}
+
+fn designer_name(designer: (u8, u8)) -> Option<&'static str> {
+    Some(match designer {
+        ARM => "Arm",
+        (0x0, 0x0E) => "Freescale",
+        (0x0, 0x15) => "NXP",
+        (0x0, 0x17) => "Texas Instruments",
+        (0x0, 0x1F) => "Atmel",
+        (0x0, 0x20) => "STMicroelectronics",
+        (0x2, 0x44) => "Nordic Semiconductor",
+        (0x9, 0x13) => "Raspberry Pi",
+        _ => return None,
+    })
+}
+
+fn part_name(designer: (u8, u8), part: u16) -> Option<&'static str> {
+    if designer != ARM {
+        return None;
+    }
+    Some(match part {
+        0x471 => "Cortex-M0 ROM table",
+        0x4C0 => "Cortex-M0+ ROM table",
+        0x4C3 => "Cortex-M3 ROM table",
+        0x4C4 => "Cortex-M4 ROM table",
+        0x4C7 => "Cortex-M7 PPB ROM table",
+        0xD20 => "Cortex-M23",
+        0xD21 => "Cortex-M33",
+        _ => return None,
+    })
+}
+
+/// ROM table address out of BASE, C2.6.1, IHI0031G
+fn rom_table_address(base: u32) -> Option<u64> {
+    let legacy_not_present = base == 0xFFFF_FFFF;
+    let arm_debug_format = base & 0b10 != 0;
+    let present = base & 0b1 != 0;
+    if legacy_not_present || (arm_debug_format && !present) {
+        return None;
+    }
+    Some((base & 0xFFFF_F000) as u64)
+}
+
+fn walk(ap: &Ap, address: u64, visited: &mut BTreeSet<u64>) -> Component {
+    let id = identify(ap, address);
+    let children = match &id {
+        Some(id) if visited.insert(address) => entries(ap, address, id)
+            .into_iter()
+            .map(|child| walk(ap, child, visited))
+            .collect(),
+        _ => Vec::new(),
+    };
+    Component {
+        address,
+        id,
+        children,
+    }
+}
+
+fn identify(ap: &Ap, address: u64) -> Option<Identification> {
+    // Every ID register holds a single byte
+    let byte = |offset: u64| ap.word(address + offset).map(|v| v & 0xFF);
+    let cidr = [
+        byte(CIDR0)?,
+        byte(CIDR0 + 4)?,
+        byte(CIDR0 + 8)?,
+        byte(CIDR0 + 12)?,
+    ];
+    if cidr[0] != 0x0D || cidr[1] & 0xF != 0x0 || cidr[2] != 0x05 || cidr[3] != 0xB1 {
+        return None;
+    }
+    let class = (cidr[1] >> 4) as u8;
+    let pidr = [byte(PIDR0)?, byte(PIDR0 + 4)?, byte(PIDR0 + 8)?];
+    let pidr4 = byte(PIDR4).unwrap_or(0);
+    let jedec = pidr[2] & 0x8 != 0;
+    let coresight = class == CLASS_CORESIGHT;
+    Some(Identification {
+        class,
+        designer: jedec.then(|| {
+            (
+                (pidr4 & 0xF) as u8,
+                ((pidr[1] >> 4) | (pidr[2] & 0x7) << 4) as u8,
+            )
+        }),
+        part: (pidr[0] | (pidr[1] & 0xF) << 8) as u16,
+        revision: (pidr[2] >> 4) as u8,
+        blocks: 1 << (pidr4 >> 4),
+        devarch: ap
+            .word(address + DEVARCH)
+            .filter(|v| coresight && v & 1 << 20 != 0),
+        devtype: byte(DEVTYPE).filter(|_| coresight).map(|v| v as u8),
+    })
+}
+
+/// Components a ROM table points to, as far as the debugger read it
+fn entries(ap: &Ap, address: u64, id: &Identification) -> Vec<u64> {
+    if Kind::of(id) != Kind::RomTable {
+        return Vec::new();
+    }
+    // Class 0x1 tables end with a zero entry, class 0x9 ones with PRESENT 0b00
+    let count = match id.class {
+        CLASS_ROM_TABLE => 960,
+        _ => 512,
+    };
+    let mut children = Vec::new();
+    for i in 0..count {
+        let Some(entry) = ap.word(address + i * 4) else {
+            break;
+        };
+        let present = match id.class {
+            CLASS_ROM_TABLE if entry == 0 => break,
+            CLASS_ROM_TABLE => entry & 0b1 != 0,
+            _ if entry & 0b11 == 0b00 => break,
+            _ => entry & 0b11 == 0b11,
+        };
+        if !present {
+            continue;
+        }
+        if entry & 0b10 == 0 {
+            log::warn!("ROM table at {address:#010x} uses the unsupported 8-bit format");
+            break;
+        }
+        let offset = (entry & 0xFFFF_F000) as i32 as i64;
+        children.push(address.wrapping_add_signed(offset));
+    }
+    children
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Identification registers of a 4KB component at `address`
+    fn id_block(ap: &mut Ap, address: u64, class: u8, pidr: [u32; 5]) {
+        let cidr = [0x0D, (class as u32) << 4, 0x05, 0xB1];
+        for (i, value) in cidr.into_iter().enumerate() {
+            ap.memory.insert(address + CIDR0 + i as u64 * 4, value);
+        }
+        for (i, value) in pidr[..4].iter().enumerate() {
+            ap.memory.insert(address + PIDR0 + i as u64 * 4, *value);
+        }
+        ap.memory.insert(address + PIDR4, pidr[4]);
+    }
+
+    #[test]
+    fn bogus_size() {
+        let mut state = VmState::default();
+        let ap = &mut state.aps[0];
+        // 32KB would start below address 0
+        id_block(ap, 0x2000, 0x9, [0x00, 0x00, 0x0A, 0x00, 0x30]);
+        id_block(ap, 0x1_0000, 0x9, [0x00, 0x00, 0x0A, 0x00, 0x30]);
+        let topology = Topology::discover(&state);
+        let bases: Vec<_> = topology
+            .components()
+            .into_iter()
+            .map(|(_, v)| (v.address, v.base()))
+            .collect();
+        assert_eq!(bases, [(0x2000, 0x2000), (0x1_0000, 0x9000)]);
+    }
+
+    #[test]
+    fn cortex_m4_rom_table() {
+        let mut state = VmState::default();
+        let ap = &mut state.aps[0];
+        ap.base = Some(0xE00F_F003);
+        // Cortex-M4 ROM table: SCS, DWT, FPB, ITM, TPIU, a not present entry and
+        // ETM the debugger never identified
+        let entries = [
+            0xFFF0_F003,
+            0xFFF0_2003,
+            0xFFF0_3003,
+            0xFFF0_1003,
+            0xFFF4_1003,
+            0xFFF4_2002,
+            0xFFF4_3003,
+            0x0000_0000,
+        ];
+        for (i, entry) in entries.into_iter().enumerate() {
+            ap.memory.insert(0xE00F_F000 + i as u64 * 4, entry);
+        }
+        id_block(ap, 0xE00F_F000, 0x1, [0xC4, 0xB4, 0x0B, 0x00, 0x04]);
+        id_block(ap, 0xE000_E000, 0xE, [0x0C, 0xB0, 0x0B, 0x00, 0x04]);
+        id_block(ap, 0xE000_1000, 0xE, [0x02, 0xB0, 0x3B, 0x00, 0x04]);
+        id_block(ap, 0xE000_2000, 0xE, [0x03, 0xB0, 0x2B, 0x00, 0x04]);
+        id_block(ap, 0xE000_0000, 0xE, [0x01, 0xB0, 0x3B, 0x00, 0x04]);
+        id_block(ap, 0xE004_0000, 0x9, [0xA1, 0xB9, 0x0B, 0x00, 0x04]);
+        // Read directly, no ROM table points to it
+        id_block(ap, 0x4000_0000, 0x9, [0x00, 0x00, 0x0A, 0x00, 0x00]);
+
+        let topology = Topology::discover(&state);
+        let kinds: Vec<_> = topology
+            .components()
+            .into_iter()
+            .map(|(_, v)| (v.address, v.kind()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (0xE00F_F000, Some(Kind::RomTable)),
+                (0xE000_E000, Some(Kind::Scs)),
+                (0xE000_1000, Some(Kind::Dwt)),
+                (0xE000_2000, Some(Kind::Fpb)),
+                (0xE000_0000, Some(Kind::Itm)),
+                (0xE004_0000, Some(Kind::Tpiu)),
+                (0xE004_2000, None),
+                (0x4000_0000, Some(Kind::Other)),
+            ]
+        );
+        let tree = topology.to_string();
+        assert!(
+            tree.contains(
+                "CS:AP[0]   0xe00ff000 ROM table, Arm part 0x4c4 rev 0 (Cortex-M4 ROM table)"
+            ),
+            "{tree}"
+        );
+        assert!(
+            tree.contains("CS:AP[0]     0xe000e000 SCS, Arm part 0x00c"),
+            "{tree}"
+        );
+        assert!(
+            tree.contains("CS:AP[0]     0xe0042000 identification not read"),
+            "{tree}"
+        );
+        assert!(
+            tree.contains("CS:AP[0] outside of the ROM tables"),
+            "{tree}"
+        );
+        assert!(
+            tree.contains("0x40000000 CoreSight component, STMicroelectronics"),
+            "{tree}"
+        );
+
+        // AP[1] with an address space of its own does not see AP[0] components
+        let mut spaces = AddressSpaces::default();
+        spaces.aps.insert(1, regdoctor::Database::new());
+        topology.register_layouts(&mut spaces);
+        assert!(spaces.ap(1).regs.is_empty());
+        let db = spaces.ap(0);
+        let dhcsr = db.get_register(0xE000_EDF0).unwrap();
+        assert_eq!(dhcsr.identifier(), "CoreSight.SCS.DHCSR");
+        let tcr = db.get_register(0xE000_0E80).unwrap();
+        assert_eq!(tcr.identifier(), "CoreSight.ITM.TCR");
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use crate::adi::{Ap, RoW};
+
+/// Debug Halting Control and Status Register
+pub const DHCSR: u64 = 0xE000_EDF0;
+/// Debug Core Register Selector Register
+pub const DCRSR: u64 = 0xE000_EDF4;
+/// Debug Core Register Data Register
+pub const DCRDR: u64 = 0xE000_EDF8;
+
+const DHCSR_DBGKEY: u32 = 0xA05F;
+const DHCSR_C_DEBUGEN: u32 = 1 << 0;
+const DHCSR_C_HALT: u32 = 1 << 1;
+const DCRSR_REGSEL_MASK: u32 = 0x7F;
+const DCRSR_REGWNR: u32 = 1 << 16;
+
+pub const REGSEL_R0: u8 = 0;
+pub const REGSEL_R1: u8 = 1;
+/// `DebugReturnAddress`, i.e. the PC of the halted core
+pub const REGSEL_PC: u8 = 15;
+
+/// Core register file reconstructed from DCRSR/DCRDR traffic
+///
+/// Values are only known while the core is halted; they get invalidated when
+/// the debugger lets the core run again.
+#[derive(Default, Clone, Debug)]
+pub struct CoreRegisters {
+    registers: BTreeMap<u8, u32>,
+    /// REGSEL of a read transfer whose result is waiting in DCRDR
+    pending_read: Option<u8>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegisterAccess {
+    Read {
+        regsel: u8,
+        value: u32,
+    },
+    Write {
+        regsel: u8,
+        value: u32,
+    },
+    /// DHCSR write clearing C_HALT
+    Resume,
+}
+
+impl CoreRegisters {
+    /// Feed a MEM-AP word access, `ap` is the MEM-AP state after the access
+    pub fn access(&mut self, ap: &Ap, rw: RoW, address: u64, value: u32) -> Option<RegisterAccess> {
+        match (address, rw) {
+            (DCRSR, RoW::W) => {
+                let regsel = (value & DCRSR_REGSEL_MASK) as u8;
+                if value & DCRSR_REGWNR == 0 {
+                    self.pending_read = Some(regsel);
+                    return None;
+                }
+                self.pending_read = None;
+                // DCRDR content is transferred into the register
+                let value = ap.word(DCRDR)?;
+                self.registers.insert(regsel, value);
+                Some(RegisterAccess::Write { regsel, value })
+            }
+            (DCRDR, RoW::R) => {
+                let regsel = self.pending_read?;
+                self.registers.insert(regsel, value);
+                Some(RegisterAccess::Read { regsel, value })
+            }
+            (DHCSR, RoW::W)
+                if value >> 16 == DHCSR_DBGKEY
+                    && value & DHCSR_C_DEBUGEN != 0
+                    && value & DHCSR_C_HALT == 0 =>
+            {
+                self.registers.clear();
+                self.pending_read = None;
+                Some(RegisterAccess::Resume)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, regsel: u8) -> Option<u32> {
+        self.registers.get(&regsel).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_transfers() {
+        let mut ap = Ap::default();
+        let mut registers = CoreRegisters::default();
+        assert_eq!(registers.access(&ap, RoW::W, DCRSR, 15), None);
+        assert_eq!(
+            registers.access(&ap, RoW::R, DCRDR, 0x0800_0100),
+            Some(RegisterAccess::Read {
+                regsel: 15,
+                value: 0x0800_0100
+            })
+        );
+        ap.memory.insert(DCRDR, 0x1234);
+        assert_eq!(
+            registers.access(&ap, RoW::W, DCRSR, DCRSR_REGWNR),
+            Some(RegisterAccess::Write {
+                regsel: 0,
+                value: 0x1234
+            })
+        );
+        assert_eq!(registers.get(REGSEL_R0), Some(0x1234));
+        assert_eq!(registers.get(REGSEL_PC), Some(0x0800_0100));
+        assert_eq!(
+            registers.access(&ap, RoW::W, DHCSR, 0xA05F_0001),
+            Some(RegisterAccess::Resume)
+        );
+        assert_eq!(registers.get(REGSEL_PC), None);
+    }
+}
@@ -0,0 +1,184 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use yaxpeax_arch::{Decoder, LengthedInstruction, U8Reader};
+use yaxpeax_arm::armv7::{InstDecoder, Instruction};
+
+use crate::{
+    adi::{Operation, RoW, VmState},
+    elf::Symbols,
+};
+
+/// Bytes written through MEM-APs over the whole trace
+#[derive(Default)]
+pub struct WrittenMemory {
+    aps: BTreeMap<u8, BTreeMap<u64, u8>>,
+}
+
+impl WrittenMemory {
+    /// Note the bytes a MEM-AP write `operation` left in the memory, in the byte order
+    /// of the AP in `state`
+    pub fn record(&mut self, operation: &Operation, state: &VmState) {
+        let Operation::MemAp {
+            apsel,
+            rw: RoW::W,
+            address,
+            value,
+            ..
+        } = operation
+        else {
+            return;
+        };
+        let bytes = value.to_bytes(state.aps[*apsel as usize].endianness);
+        let memory = self.aps.entry(*apsel).or_default();
+        for (i, byte) in bytes.into_iter().enumerate() {
+            memory.insert(address + i as u64, byte);
+        }
+    }
+
+    /// Contiguous written regions, lowest address first
+    pub fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        for (&apsel, memory) in self.aps.iter() {
+            for (&address, &byte) in memory.iter() {
+                match regions.last_mut() {
+                    Some(region) if region.apsel == apsel && region.end() == Some(address) => {
+                        region.bytes.push(byte)
+                    }
+                    _ => regions.push(Region {
+                        apsel,
+                        address,
+                        bytes: vec![byte],
+                    }),
+                }
+            }
+        }
+        regions
+    }
+}
+
+pub struct Region {
+    pub apsel: u8,
+    pub address: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl Region {
+    /// First address past the region (`None` if it reaches the end of the address space)
+    pub fn end(&self) -> Option<u64> {
+        self.address.checked_add(self.bytes.len() as u64)
+    }
+
+    pub fn disassemble<'a>(&'a self, symbols: Option<&'a Symbols>) -> Disassembly<'a> {
+        Disassembly {
+            region: self,
+            symbols,
+        }
+    }
+}
+
+/// Thumb-2 (ARMv6-M/ARMv7-M/ARMv8-M) listing of a written region
+pub struct Disassembly<'a> {
+    region: &'a Region,
+    symbols: Option<&'a Symbols>,
+}
+
+impl Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Region {
+            apsel,
+            address,
+            bytes,
+        } = self.region;
+        write!(
+            f,
+            "D:AP[{apsel}]:{address:#010x}..{:#010x} ({} bytes)",
+            *address + bytes.len() as u64,
+            bytes.len()
+        )?;
+        match self.symbols.and_then(|v| v.lookup(*address)) {
+            Some(symbol) => writeln!(f, " <{symbol}>")?,
+            None => writeln!(f)?,
+        }
+        // Thumb instructions are halfword aligned
+        let mut offset = (*address & 0b1) as usize;
+        let decoder = InstDecoder::armv7_thumb();
+        while offset + 2 <= bytes.len() {
+            let inst_address = address + offset as u64;
+            if let Some(symbols) = self.symbols {
+                for symbol in symbols.at(inst_address) {
+                    writeln!(f, "<{}>:", symbol.name)?;
+                }
+            }
+            let mut reader = U8Reader::new(&bytes[offset..]);
+            let decoded: Result<Instruction, _> = decoder.decode(&mut reader);
+            let (len, text) = match decoded {
+                Ok(inst) => (inst.len().to_const() as usize, inst.to_string()),
+                Err(_) => (2, String::from("<undefined>")),
+            };
+            let encoding = bytes[offset..offset + len]
+                .chunks(2)
+                .map(|hw| format!("{:04x}", u16::from_le_bytes([hw[0], hw[1]])))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(f, "  {inst_address:#010x}: {encoding:<9} {text}")?;
+            offset += len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adi::{Endianness, MemApValue};
+
+    fn write(address: u64, value: MemApValue) -> Operation {
+        Operation::MemAp {
+            ts: None,
+            apsel: 0,
+            rw: RoW::W,
+            address,
+            value,
+            suspect: false,
+        }
+    }
+
+    #[test]
+    fn contiguous_writes_are_merged() {
+        let mut state = VmState::default();
+        let mut memory = WrittenMemory::default();
+        memory.record(&write(0x2000_0000, MemApValue::Word(0x4f_f0_48_02)), &state);
+        memory.record(&write(0x2000_0004, MemApValue::Halfword(0x0101)), &state);
+        memory.record(&write(0x2000_0006, MemApValue::Byte(0x70)), &state);
+        memory.record(&write(0x2000_0007, MemApValue::Byte(0x47)), &state);
+        memory.record(&write(0x2000_0100, MemApValue::Word(0x0)), &state);
+        let regions = memory.regions();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].address, 0x2000_0000);
+        assert_eq!(
+            regions[0].bytes,
+            [0x02, 0x48, 0xf0, 0x4f, 0x01, 0x01, 0x70, 0x47]
+        );
+        assert_eq!(regions[1].address, 0x2000_0100);
+
+        state.aps[0].endianness = Endianness::Big;
+        memory.record(&write(0x2000_0000, MemApValue::Word(0x0248_f04f)), &state);
+        assert_eq!(memory.regions()[0].bytes[..4], [0x02, 0x48, 0xf0, 0x4f]);
+    }
+
+    #[test]
+    fn thumb_listing() {
+        let region = Region {
+            apsel: 0,
+            address: 0x2000_0000,
+            bytes: vec![0x02, 0x48, 0x4f, 0xf0, 0x01, 0x01, 0x70, 0x47],
+        };
+        let listing = region.disassemble(None).to_string();
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(lines[0], "D:AP[0]:0x20000000..0x20000008 (8 bytes)");
+        assert!(lines[1].ends_with("ldr r0, [pc, 0x8]"));
+        assert!(lines[2].contains("f04f 0101"));
+        assert!(lines[3].starts_with("  0x20000006:"));
+        assert!(lines[3].ends_with("bx lr"));
+    }
+}
@@ -0,0 +1,206 @@
+pub mod dwarf;
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use dwarf::{DecodedValue, Variable, Variables};
+use object::{Object, ObjectSymbol, SymbolKind};
+
+use crate::adi::{Endianness, MemApValue};
+
+/// Everything adios knows about the firmware running on the target
+pub struct Firmware {
+    pub symbols: Symbols,
+    pub variables: Variables,
+}
+
+impl Firmware {
+    pub fn from_elf(data: &[u8]) -> Result<Self, object::Error> {
+        let file = object::File::parse(data)?;
+        let symbols = Symbols::from_object(&file);
+        let variables = match Variables::from_elf(&file) {
+            Ok(variables) => variables,
+            Err(e) => {
+                log::warn!("Cannot read DWARF variables, only symbols will be used: {e}");
+                Variables::default()
+            }
+        };
+        Ok(Self { symbols, variables })
+    }
+
+    /// Describe what lives at `address`, decoding the accessed `value` if possible
+    pub fn annotate(
+        &self,
+        address: u64,
+        value: MemApValue,
+        endianness: Endianness,
+    ) -> Option<Annotation<'_>> {
+        if let Some(variable) = self.variables.lookup(address) {
+            return Some(Annotation::Variable {
+                variable,
+                offset: address - variable.address,
+                value: variable.decode(address, value, endianness),
+            });
+        }
+        self.symbols.lookup(address).map(Annotation::Symbol)
+    }
+}
+
+pub enum Annotation<'a> {
+    Variable {
+        variable: &'a Variable,
+        offset: u64,
+        value: Option<DecodedValue<'a>>,
+    },
+    Symbol(SymbolOffset<'a>),
+}
+
+impl Display for Annotation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Annotation::Variable {
+                variable,
+                offset: 0,
+                value: Some(value),
+            } => write!(f, "{}: {} = {value}", variable.name, variable.type_),
+            Annotation::Variable {
+                variable,
+                offset: 0,
+                value: None,
+            } => write!(f, "{}", variable.name),
+            Annotation::Variable {
+                variable, offset, ..
+            } => write!(f, "{}+{offset:#x}", variable.name),
+            Annotation::Symbol(symbol) => write!(f, "{symbol}"),
+        }
+    }
+}
+
+/// Symbol table of the firmware running on the target
+#[derive(Default)]
+pub struct Symbols {
+    by_address: BTreeMap<u64, Vec<Symbol>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    pub kind: SymbolKind,
+}
+
+impl Symbols {
+    pub fn from_object(file: &object::File) -> Self {
+        let mut symbols = Self::default();
+        for symbol in file.symbols() {
+            let kind = symbol.kind();
+            if !matches!(kind, SymbolKind::Text | SymbolKind::Data) {
+                continue;
+            }
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            // Mapping symbols ($t, $d, $a) only mark instruction set boundaries
+            if name.is_empty() || name.starts_with('$') {
+                continue;
+            }
+            // Thumb functions have their LSB set
+            let address = match kind {
+                SymbolKind::Text => symbol.address() & !0b1,
+                _ => symbol.address(),
+            };
+            symbols.insert(Symbol {
+                name: name.to_owned(),
+                address,
+                size: symbol.size(),
+                kind,
+            });
+        }
+        symbols
+    }
+
+    pub fn insert(&mut self, symbol: Symbol) {
+        let symbols = self.by_address.entry(symbol.address).or_default();
+        if !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Symbol> {
+        self.by_address.values().flatten().find(|v| v.name == name)
+    }
+
+    /// Symbols starting exactly at `address`
+    pub fn at(&self, address: u64) -> &[Symbol] {
+        self.by_address
+            .get(&address)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Symbol covering `address` together with the offset into it
+    ///
+    /// Zero-sized symbols are matched only on their exact address.
+    pub fn lookup(&self, address: u64) -> Option<SymbolOffset<'_>> {
+        let (_, symbols) = self.by_address.range(..=address).next_back()?;
+        symbols
+            .iter()
+            .find(|symbol| {
+                let offset = address - symbol.address;
+                offset == 0 || offset < symbol.size
+            })
+            .map(|symbol| SymbolOffset {
+                symbol,
+                offset: address - symbol.address,
+            })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SymbolOffset<'a> {
+    pub symbol: &'a Symbol,
+    pub offset: u64,
+}
+
+impl Display for SymbolOffset<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            0 => write!(f, "{}", self.symbol.name),
+            offset => write!(f, "{}+{:#x}", self.symbol.name, offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, address: u64, size: u64) -> Symbol {
+        Symbol {
+            name: name.into(),
+            address,
+            size,
+            kind: SymbolKind::Data,
+        }
+    }
+
+    #[test]
+    fn lookup_honours_symbol_size() {
+        let mut symbols = Symbols::default();
+        symbols.insert(symbol("counter", 0x2000_0000, 4));
+        symbols.insert(symbol("buffer", 0x2000_0010, 0x20));
+        symbols.insert(symbol("_stack_top", 0x2000_1000, 0));
+
+        let found = symbols.lookup(0x2000_0000).unwrap();
+        assert_eq!(found.to_string(), "counter");
+        assert!(symbols.lookup(0x2000_0004).is_none());
+        let found = symbols.lookup(0x2000_0018).unwrap();
+        assert_eq!(found.to_string(), "buffer+0x8");
+        assert!(symbols.lookup(0x2000_0030).is_none());
+        assert_eq!(
+            symbols.lookup(0x2000_1000).unwrap().symbol.name,
+            "_stack_top"
+        );
+        assert!(symbols.lookup(0x2000_1004).is_none());
+    }
+}
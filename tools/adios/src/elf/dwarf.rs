@@ -0,0 +1,580 @@
+use std::{borrow::Cow, collections::BTreeMap, fmt::Display};
+
+use gimli::{
+    AttributeValue, DebuggingInformationEntry, DwAte, EndianSlice, Operation, RunTimeEndian, Unit,
+    UnitOffset,
+};
+use object::{Object, ObjectSection};
+
+use crate::adi::{Endianness, MemApValue};
+
+type Reader<'a> = EndianSlice<'a, RunTimeEndian>;
+
+/// Arrays with more elements than this are not split into separate variables
+const MAX_FLATTENED_ARRAY_LEN: u64 = 64;
+/// Arrays flattening into more variables than this (e.g. nested ones) are kept whole
+const MAX_FLATTENED_ENTRIES: u64 = 1024;
+/// Guards against pathological (or broken) type graphs
+const MAX_TYPE_DEPTH: usize = 16;
+
+/// Statically allocated program variables described by the DWARF info
+///
+/// Aggregates are flattened into their scalar members (`foo.bar[2].baz`), so every
+/// entry describes a value which can be decoded on its own.
+#[derive(Default)]
+pub struct Variables {
+    by_address: BTreeMap<u64, Variable>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Variable {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    pub type_: Type,
+}
+
+#[derive(Clone, Debug)]
+pub enum Type {
+    Base {
+        name: String,
+        encoding: DwAte,
+        size: u64,
+    },
+    Pointer {
+        size: u64,
+    },
+    Enumeration {
+        name: String,
+        size: u64,
+        variants: Vec<(i64, String)>,
+    },
+    Structure {
+        size: u64,
+        members: Vec<(String, u64, Type)>,
+    },
+    Array {
+        element: Box<Type>,
+        len: u64,
+    },
+    Unknown {
+        size: u64,
+    },
+}
+
+impl Type {
+    pub fn size(&self) -> u64 {
+        match self {
+            Type::Base { size, .. }
+            | Type::Pointer { size }
+            | Type::Enumeration { size, .. }
+            | Type::Structure { size, .. }
+            | Type::Unknown { size } => *size,
+            Type::Array { element, len } => element.size() * len,
+        }
+    }
+
+    /// Number of variables the type is flattened into
+    fn flattened_len(&self) -> u64 {
+        match self {
+            Type::Structure { members, .. } if !members.is_empty() => members
+                .iter()
+                .fold(0, |sum, (_, _, v)| sum.saturating_add(v.flattened_len())),
+            Type::Array { element, len } if self.is_flattened_array() => {
+                len.saturating_mul(element.flattened_len())
+            }
+            _ => 1,
+        }
+    }
+
+    fn is_flattened_array(&self) -> bool {
+        match self {
+            Type::Array { element, len } => {
+                *len <= MAX_FLATTENED_ARRAY_LEN
+                    && len.saturating_mul(element.flattened_len()) <= MAX_FLATTENED_ENTRIES
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Variables {
+    pub fn from_elf(file: &object::File) -> Result<Self, gimli::Error> {
+        let endian = if file.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        let sections = gimli::DwarfSections::load(|id| -> Result<_, gimli::Error> {
+            Ok(file
+                .section_by_name(id.name())
+                .and_then(|section| section.uncompressed_data().ok())
+                .unwrap_or(Cow::Borrowed(&[])))
+        })?;
+        let dwarf = sections.borrow(|section| EndianSlice::new(section, endian));
+
+        let mut variables = Self::default();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let mut entries = unit.entries();
+            while let Some((_, entry)) = entries.next_dfs()? {
+                if entry.tag() != gimli::DW_TAG_variable {
+                    continue;
+                }
+                let Some(address) = static_address(&dwarf, &unit, entry)? else {
+                    continue;
+                };
+                let Some(name) = name(&dwarf, &unit, entry)? else {
+                    continue;
+                };
+                let Some(type_offset) = type_of(&unit, entry)? else {
+                    continue;
+                };
+                let type_ = resolve_type(&dwarf, &unit, type_offset, 0)?;
+                variables.insert_flattened(name, address, type_);
+            }
+        }
+        Ok(variables)
+    }
+
+    fn insert_flattened(&mut self, name: String, address: u64, type_: Type) {
+        let flattened_array = type_.is_flattened_array();
+        match type_ {
+            Type::Structure { members, .. } if !members.is_empty() => {
+                for (member, offset, type_) in members {
+                    self.insert_flattened(format!("{name}.{member}"), address + offset, type_);
+                }
+            }
+            Type::Array { element, len } if flattened_array => {
+                let element_size = element.size();
+                for i in 0..len {
+                    self.insert_flattened(
+                        format!("{name}[{i}]"),
+                        address + i * element_size,
+                        (*element).clone(),
+                    );
+                }
+            }
+            type_ => {
+                let size = type_.size();
+                self.by_address.insert(
+                    address,
+                    Variable {
+                        name,
+                        address,
+                        size,
+                        type_,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Variable covering `address`
+    pub fn lookup(&self, address: u64) -> Option<&Variable> {
+        let (_, variable) = self.by_address.range(..=address).next_back()?;
+        (address - variable.address < variable.size.max(1)).then_some(variable)
+    }
+}
+
+fn static_address(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &Unit<Reader>,
+    entry: &DebuggingInformationEntry<Reader>,
+) -> gimli::Result<Option<u64>> {
+    let Some(expression) = entry
+        .attr_value(gimli::DW_AT_location)?
+        .and_then(|v| v.exprloc_value())
+    else {
+        return Ok(None);
+    };
+    let mut operations = expression.operations(unit.encoding());
+    let address = match operations.next()? {
+        Some(Operation::Address { address }) => address,
+        Some(Operation::AddressIndex { index }) => dwarf.address(unit, index)?,
+        _ => return Ok(None),
+    };
+    // Anything more complex than a plain address (TLS, offsets..) is out of scope
+    if operations.next()?.is_some() {
+        return Ok(None);
+    }
+    Ok(Some(address))
+}
+
+fn name(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &Unit<Reader>,
+    entry: &DebuggingInformationEntry<Reader>,
+) -> gimli::Result<Option<String>> {
+    if let Some(value) = entry.attr_value(gimli::DW_AT_name)? {
+        let name = dwarf.attr_string(unit, value)?;
+        return Ok(Some(name.to_string_lossy().into_owned()));
+    }
+    // Definitions of declared variables carry the name in the declaration
+    if let Some(AttributeValue::UnitRef(offset)) = entry.attr_value(gimli::DW_AT_specification)? {
+        let declaration = unit.entry(offset)?;
+        return name(dwarf, unit, &declaration);
+    }
+    Ok(None)
+}
+
+fn type_of(
+    unit: &Unit<Reader>,
+    entry: &DebuggingInformationEntry<Reader>,
+) -> gimli::Result<Option<UnitOffset>> {
+    match entry.attr_value(gimli::DW_AT_type)? {
+        Some(AttributeValue::UnitRef(offset)) => Ok(Some(offset)),
+        _ => match entry.attr_value(gimli::DW_AT_specification)? {
+            Some(AttributeValue::UnitRef(offset)) => type_of(unit, &unit.entry(offset)?),
+            _ => Ok(None),
+        },
+    }
+}
+
+fn resolve_type(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &Unit<Reader>,
+    offset: UnitOffset,
+    depth: usize,
+) -> gimli::Result<Type> {
+    let entry = unit.entry(offset)?;
+    let size = entry
+        .attr_value(gimli::DW_AT_byte_size)?
+        .and_then(|v| v.udata_value());
+    if depth > MAX_TYPE_DEPTH {
+        return Ok(Type::Unknown {
+            size: size.unwrap_or(0),
+        });
+    }
+    let type_name = match entry.attr_value(gimli::DW_AT_name)? {
+        Some(value) => dwarf
+            .attr_string(unit, value)?
+            .to_string_lossy()
+            .into_owned(),
+        None => String::new(),
+    };
+    let type_ = match entry.tag() {
+        gimli::DW_TAG_base_type => {
+            let encoding = match entry.attr_value(gimli::DW_AT_encoding)? {
+                Some(AttributeValue::Encoding(encoding)) => encoding,
+                _ => gimli::DW_ATE_unsigned,
+            };
+            Type::Base {
+                name: type_name,
+                encoding,
+                size: size.unwrap_or(0),
+            }
+        }
+        gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => Type::Pointer {
+            size: size.unwrap_or(unit.encoding().address_size as u64),
+        },
+        gimli::DW_TAG_enumeration_type => {
+            let mut variants = Vec::new();
+            let mut tree = unit.entries_tree(Some(offset))?;
+            let mut children = tree.root()?.children();
+            while let Some(child) = children.next()? {
+                let child = child.entry();
+                if child.tag() != gimli::DW_TAG_enumerator {
+                    continue;
+                }
+                let value = match child.attr_value(gimli::DW_AT_const_value)? {
+                    Some(AttributeValue::Sdata(v)) => v,
+                    Some(v) => v.udata_value().unwrap_or_default() as i64,
+                    None => continue,
+                };
+                let Some(name) = name(dwarf, unit, child)? else {
+                    continue;
+                };
+                variants.push((value, name));
+            }
+            Type::Enumeration {
+                name: type_name,
+                size: size.unwrap_or(4),
+                variants,
+            }
+        }
+        gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => {
+            let mut members = Vec::new();
+            let is_union = entry.tag() == gimli::DW_TAG_union_type;
+            let mut tree = unit.entries_tree(Some(offset))?;
+            let mut children = tree.root()?.children();
+            while let Some(child) = children.next()? {
+                let child = child.entry();
+                if child.tag() != gimli::DW_TAG_member {
+                    continue;
+                }
+                let member_offset = match child.attr_value(gimli::DW_AT_data_member_location)? {
+                    Some(value) => value.udata_value(),
+                    None if is_union => Some(0),
+                    None => None,
+                };
+                // Bit fields are not flattened
+                if child.attr_value(gimli::DW_AT_bit_size)?.is_some() {
+                    continue;
+                }
+                let (Some(member_offset), Some(member_name), Some(member_type)) = (
+                    member_offset,
+                    name(dwarf, unit, child)?,
+                    type_of(unit, child)?,
+                ) else {
+                    continue;
+                };
+                let member_type = resolve_type(dwarf, unit, member_type, depth + 1)?;
+                members.push((member_name, member_offset, member_type));
+            }
+            // Overlapping union members can't be told apart by address
+            if is_union {
+                members.clear();
+            }
+            Type::Structure {
+                size: size.unwrap_or(0),
+                members,
+            }
+        }
+        gimli::DW_TAG_array_type => {
+            let Some(element) = type_of(unit, &entry)? else {
+                return Ok(Type::Unknown {
+                    size: size.unwrap_or(0),
+                });
+            };
+            let element = resolve_type(dwarf, unit, element, depth + 1)?;
+            let mut len = 1;
+            let mut tree = unit.entries_tree(Some(offset))?;
+            let mut children = tree.root()?.children();
+            while let Some(child) = children.next()? {
+                let child = child.entry();
+                if child.tag() != gimli::DW_TAG_subrange_type {
+                    continue;
+                }
+                let count = match (
+                    child.attr_value(gimli::DW_AT_count)?,
+                    child.attr_value(gimli::DW_AT_upper_bound)?,
+                ) {
+                    (Some(count), _) => count.udata_value(),
+                    (None, Some(upper_bound)) => upper_bound.udata_value().map(|v| v + 1),
+                    (None, None) => None,
+                };
+                len *= count.unwrap_or(0);
+            }
+            Type::Array {
+                element: Box::new(element),
+                len,
+            }
+        }
+        // Qualifiers and aliases are transparent
+        gimli::DW_TAG_typedef
+        | gimli::DW_TAG_const_type
+        | gimli::DW_TAG_volatile_type
+        | gimli::DW_TAG_atomic_type
+        | gimli::DW_TAG_restrict_type => match type_of(unit, &entry)? {
+            Some(inner) => resolve_type(dwarf, unit, inner, depth + 1)?,
+            None => Type::Unknown {
+                size: size.unwrap_or(0),
+            },
+        },
+        _ => Type::Unknown {
+            size: size.unwrap_or(0),
+        },
+    };
+    Ok(type_)
+}
+
+impl Variable {
+    /// Decode an access to `address` of an AP with the given byte order, if it hits
+    /// the variable exactly
+    pub fn decode(
+        &self,
+        address: u64,
+        value: MemApValue,
+        endianness: Endianness,
+    ) -> Option<DecodedValue<'_>> {
+        let access_size = value.size() as u64;
+        if address != self.address || self.size == 0 || access_size < self.size {
+            return None;
+        }
+        // Variables wider than 64 bits are shown as their lowest 64 bits
+        let len = self.size.min(8) as usize;
+        let bytes = &value.to_bytes(endianness)[..len];
+        let byte = |value: u64, byte: &u8| value << 8 | *byte as u64;
+        let raw = match endianness {
+            Endianness::Little => bytes.iter().rev().fold(0, byte),
+            Endianness::Big => bytes.iter().fold(0, byte),
+        };
+        Some(DecodedValue {
+            type_: &self.type_,
+            size: len as u64,
+            raw,
+        })
+    }
+}
+
+pub struct DecodedValue<'a> {
+    type_: &'a Type,
+    /// Bytes in `raw`, at most 8
+    size: u64,
+    raw: u64,
+}
+
+impl Display for DecodedValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits = self.size * 8;
+        let signed = || ((self.raw << (64 - bits)) as i64) >> (64 - bits);
+        match self.type_ {
+            Type::Base { encoding, .. } => match *encoding {
+                gimli::DW_ATE_boolean => write!(f, "{}", self.raw != 0),
+                gimli::DW_ATE_float if self.size == 4 => {
+                    write!(f, "{}", f32::from_bits(self.raw as u32))
+                }
+                gimli::DW_ATE_signed => write!(f, "{}", signed()),
+                gimli::DW_ATE_signed_char | gimli::DW_ATE_unsigned_char
+                    if (0x20..0x7f).contains(&self.raw) =>
+                {
+                    write!(f, "{:?}", self.raw as u8 as char)
+                }
+                gimli::DW_ATE_signed_char => write!(f, "{}", signed()),
+                _ => write!(f, "{}", self.raw),
+            },
+            Type::Pointer { .. } => write!(f, "{:#010x}", self.raw),
+            Type::Enumeration { variants, .. } => {
+                let value = signed();
+                match variants
+                    .iter()
+                    .find(|(v, _)| *v == value || *v == self.raw as i64)
+                {
+                    Some((_, name)) => write!(f, "{name}"),
+                    None => write!(f, "{value}?!"),
+                }
+            }
+            _ => write!(f, "{:#x}", self.raw),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Base { name, .. } | Type::Enumeration { name, .. } => write!(f, "{name}"),
+            Type::Pointer { .. } => write!(f, "*"),
+            Type::Structure { .. } => write!(f, "struct"),
+            Type::Array { element, len } => write!(f, "[{element}; {len}]"),
+            Type::Unknown { .. } => write!(f, "?"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattening_and_decoding() {
+        let u16_ = Type::Base {
+            name: "u16".into(),
+            encoding: gimli::DW_ATE_unsigned,
+            size: 2,
+        };
+        let i8_ = Type::Base {
+            name: "i8".into(),
+            encoding: gimli::DW_ATE_signed,
+            size: 1,
+        };
+        let state = Type::Enumeration {
+            name: "State".into(),
+            size: 1,
+            variants: vec![(0, "Idle".into()), (1, "Busy".into())],
+        };
+        let mut variables = Variables::default();
+        variables.insert_flattened(
+            "CONFIG".into(),
+            0x2000_0000,
+            Type::Structure {
+                size: 8,
+                members: vec![
+                    ("rate".into(), 0, u16_),
+                    ("offset".into(), 2, i8_),
+                    ("state".into(), 3, state),
+                    (
+                        "history".into(),
+                        4,
+                        Type::Array {
+                            element: Box::new(Type::Base {
+                                name: "u8".into(),
+                                encoding: gimli::DW_ATE_unsigned,
+                                size: 1,
+                            }),
+                            len: 4,
+                        },
+                    ),
+                ],
+            },
+        );
+
+        let rate = variables.lookup(0x2000_0001).unwrap();
+        assert_eq!(rate.name, "CONFIG.rate");
+        assert!(rate
+            .decode(0x2000_0001, MemApValue::Byte(0), Endianness::Little)
+            .is_none());
+        let decoded = rate.decode(
+            0x2000_0000,
+            MemApValue::Word(0xff01_0400),
+            Endianness::Little,
+        );
+        assert_eq!(decoded.unwrap().to_string(), "1024");
+
+        let offset = variables.lookup(0x2000_0002).unwrap();
+        let decoded = offset.decode(0x2000_0002, MemApValue::Byte(0xfe), Endianness::Little);
+        assert_eq!(decoded.unwrap().to_string(), "-2");
+
+        // Big-endian AP, the upper half of the word is at the lower address
+        let decoded = rate.decode(0x2000_0000, MemApValue::Word(0x0400_01ff), Endianness::Big);
+        assert_eq!(decoded.unwrap().to_string(), "1024");
+
+        let state = variables.lookup(0x2000_0003).unwrap();
+        let decoded = state.decode(0x2000_0003, MemApValue::Byte(1), Endianness::Little);
+        assert_eq!(decoded.unwrap().to_string(), "Busy");
+
+        assert_eq!(
+            variables.lookup(0x2000_0006).unwrap().name,
+            "CONFIG.history[2]"
+        );
+        assert!(variables.lookup(0x2000_0008).is_none());
+
+        // [[[u8; 64]; 64]; 64] is only split as far as the number of entries stays bounded
+        let nested = (0..3).fold(
+            Type::Base {
+                name: "u8".into(),
+                encoding: gimli::DW_ATE_unsigned,
+                size: 1,
+            },
+            |element, _| Type::Array {
+                element: Box::new(element),
+                len: 64,
+            },
+        );
+        variables.insert_flattened("CUBE".into(), 0x2000_1000, nested);
+        assert_eq!(variables.lookup(0x2000_1fff).unwrap().name, "CUBE[0]");
+        assert_eq!(variables.by_address.len(), 7 + 64);
+    }
+
+    #[test]
+    fn wide_signed() {
+        let variable = Variable {
+            name: "TOTAL".into(),
+            address: 0x2000_0000,
+            size: 16,
+            type_: Type::Base {
+                name: "__int128".into(),
+                encoding: gimli::DW_ATE_signed,
+                size: 16,
+            },
+        };
+        let decoded = variable.decode(
+            0x2000_0000,
+            MemApValue::Bits128(u128::MAX - 1),
+            Endianness::Little,
+        );
+        // Lowest 64 bits
+        assert_eq!(decoded.unwrap().to_string(), "-2");
+    }
+}
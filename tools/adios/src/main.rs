@@ -1,12 +1,29 @@
-use std::io::{BufReader, Read};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufReader, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
-use adi::VmStateStep;
+use adi::{MemoryModel, VmStateStep};
 use clap::Parser;
 use cli::Args;
-use regdoctor::{Database, Register};
+use disasm::WrittenMemory;
+use elf::Firmware;
+use regdoctor::{Access, AccessKind, Database, NoResetValue, Register, RegisterInfo};
+use rtt::Rtt;
+use semihosting::Semihosting;
 
 mod adi;
 mod cli;
+mod coresight;
+mod cortex_m;
+mod disasm;
+mod elf;
+mod profile;
+mod rtt;
+mod semihosting;
 
 fn main() {
     env_logger::Builder::from_env(
@@ -19,16 +36,47 @@ fn main() {
 
     let mut args = Args::parse();
 
-    let mut mem_ap_db = Database::new();
-    for mut svd_file in args.svd {
-        let mut svd_as_string = String::new();
-        svd_file.read_to_string(&mut svd_as_string).unwrap();
-        let device = svd_parser::parse(&svd_as_string).unwrap();
-        mem_ap_db.extend_with_svd(device);
+    let patches: Vec<_> = args
+        .svd_patch
+        .iter_mut()
+        .map(|patch_file| {
+            let mut patch = String::new();
+            patch_file.read_to_string(&mut patch).unwrap();
+            regdoctor::Patch::from_yaml(&patch).unwrap()
+        })
+        .collect();
+    let svd_cache = args.svd_cache.take();
+    let load_svds = |svds: Vec<(PathBuf, String)>| {
+        let mut db = Database::new();
+        if let Some(svd_cache) = &svd_cache {
+            db = db.with_cache_dir(svd_cache.clone());
+        }
+        for (path, svd_as_string) in svds {
+            let patches: Vec<_> = patches
+                .iter()
+                .filter(|v| v.applies_to(&path))
+                .cloned()
+                .collect();
+            db.extend_with_svd_str(&svd_as_string, &patches).unwrap();
+        }
+        db
+    };
+    let svds: Vec<_> = args
+        .svd
+        .iter_mut()
+        .map(|svd_file| {
+            let mut svd_as_string = String::new();
+            svd_file.read_to_string(&mut svd_as_string).unwrap();
+            (svd_file.path().to_path_buf(), svd_as_string)
+        })
+        .collect();
+    let mut registry = profile::Registry::default();
+    for path in args.profiles.iter() {
+        let yaml = std::fs::read_to_string(path).unwrap();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        registry.extend_with_yaml(&yaml, dir).unwrap();
     }
-    let adi_db = regdoctor_adios_ext::Database::new();
-
-    let adi_commands = match args.mode {
+    let mut adi_commands = match args.mode {
         cli::Mode::CmsisDapWsPdml => {
             let pdml_file = BufReader::new(args.input);
             adios_from_cmsis_dap_ws_pdml::generate_vm_input(pdml_file)
@@ -36,22 +84,159 @@ fn main() {
         cli::Mode::SigrokSwd => {
             let mut swd_string = String::new();
             args.input.read_to_string(&mut swd_string).unwrap();
-            adios_from_sigrok_swd::generate_vm_commands(&swd_string)
+            adios_from_sigrok_swd::generate_wire_events(&swd_string)
                 .unwrap()
                 .into_iter()
-                .map(|v| v.into())
+                .map(adios_common::Input::from)
                 .collect()
         }
     };
+    if let Some(mut swo_file) = args.swo.take() {
+        if args.mode != cli::Mode::SigrokSwd {
+            log::warn!("No timestamps in the input, SWO trace is appended at the end");
+        }
+        let trace = match args.swo_mode {
+            cli::SwoMode::Uart => {
+                let mut swo_string = String::new();
+                swo_file.read_to_string(&mut swo_string).unwrap();
+                adios_from_sigrok_swo::generate_vm_input_from_uart(&swo_string).unwrap()
+            }
+            cli::SwoMode::Manchester => {
+                let mut samples = Vec::new();
+                swo_file.read_to_end(&mut samples).unwrap();
+                adios_from_sigrok_swo::generate_vm_input_from_manchester(&samples, args.swo_bit)
+            }
+        };
+        adi_commands = adios_common::interleave(adi_commands, trace);
+    }
+    let configured_vm = || {
+        let vm = adi::Vm::new().with_tar_wrap(args.tar_wrap);
+        match args.endianness {
+            Some(cli::Endianness::Little) => vm.with_endianness(adi::Endianness::Little),
+            Some(cli::Endianness::Big) => vm.with_endianness(adi::Endianness::Big),
+            None => vm,
+        }
+    };
+    // Target and its components have to be known before the decoding starts
+    let mut identity = profile::Identity::default();
+    let mut topology = None;
+    if args.coresight || !registry.profiles.is_empty() {
+        let mut vm = configured_vm();
+        while let Some(step) = vm.step_forward(&adi_commands) {
+            for operation in step.operations.iter() {
+                identity.record(operation);
+            }
+        }
+        topology = args
+            .coresight
+            .then(|| coresight::Topology::discover(vm.state()));
+    }
+    let profile = registry.best_match(&identity);
+    if profile.is_none() && !registry.profiles.is_empty() {
+        log::warn!("No target profile matches {identity}");
+    }
+    let mut mem_ap_db = match profile {
+        Some(profile) if svds.is_empty() => {
+            log::info!("Target matches the {:?} profile", profile.name);
+            profile.address_spaces(|paths| {
+                load_svds(
+                    paths
+                        .iter()
+                        .map(|path| (path.clone(), std::fs::read_to_string(path).unwrap()))
+                        .collect(),
+                )
+            })
+        }
+        _ => {
+            if let Some(profile) = profile.filter(|v| v.svds().next().is_some()) {
+                for (path, _) in svds.iter().filter(|(path, _)| !profile.uses(path)) {
+                    log::warn!(
+                        "{} does not match the traced chip, see the {:?} profile",
+                        path.display(),
+                        profile.name
+                    );
+                }
+            }
+            profile::AddressSpaces {
+                default: load_svds(svds),
+                aps: BTreeMap::new(),
+            }
+        }
+    };
+    if let Some(topology) = &topology {
+        topology.register_layouts(&mut mem_ap_db);
+    }
+    let mem_ap_db = Rc::new(mem_ap_db);
+    let only: Vec<Range<u64>> = args
+        .only
+        .iter()
+        .flat_map(|pattern| {
+            let ranges: Vec<_> = mem_ap_db
+                .all()
+                .flat_map(|db| db.glob(pattern))
+                .map(|v| v.range())
+                .collect();
+            if ranges.is_empty() {
+                log::warn!("No SVD register matches {pattern:?}");
+            }
+            ranges
+        })
+        .collect();
+    let shown = |address: u64, len: u32| {
+        let access = address..address + len as u64;
+        args.only.is_empty()
+            || only
+                .iter()
+                .any(|v| v.start < access.end && access.start < v.end)
+    };
+    let adi_db = regdoctor_adios_ext::Database::new();
+    let firmware = args.elf.take().map(|mut elf_file| {
+        let mut elf = Vec::new();
+        elf_file.read_to_end(&mut elf).unwrap();
+        Firmware::from_elf(&elf).unwrap()
+    });
+    let mut written_memory = WrittenMemory::default();
+    let mut rtt = args.rtt.then(|| {
+        let control_block = firmware
+            .as_ref()
+            .and_then(|v| v.symbols.by_name("_SEGGER_RTT"));
+        match control_block {
+            Some(symbol) => Rtt::with_control_block(symbol.address),
+            None => Rtt::default(),
+        }
+    });
 
-    let mut vm = adi::Vm::new();
+    let mut semihosting = args.semihosting.then(Semihosting::default);
+
+    // Last seen value of every DP and AP register, for field diffs
+    let mut dp_values = HashMap::new();
+    let mut ap_values = HashMap::new();
+    let mut failure_counts = BTreeMap::new();
+    let mut vm = configured_vm();
+    if args.model_writes {
+        vm = vm.with_memory_model(mem_ap_db.clone());
+    }
     while let Some(step) = vm.step_forward(&adi_commands) {
         let VmStateStep {
             operations,
             previous: (previous_state, _step_i),
             current: (current_state, _),
         } = step;
+        let rtt_events = match &mut rtt {
+            Some(rtt) => rtt.step(&operations, &previous_state, &current_state),
+            None => Vec::new(),
+        };
+        let semihosting_calls = match &mut semihosting {
+            Some(semihosting) => semihosting.step(&operations, &previous_state, &current_state),
+            None => Vec::new(),
+        };
         for operation in operations {
+            if args.disasm {
+                written_memory.record(&operation, &current_state);
+            }
+            if args.anomalies && shown_operation(&operation, shown) {
+                print_anomalies(&mem_ap_db, &operation, &previous_state, args.ts);
+            }
             match operation {
                 adi::Operation::MemAp {
                     ts,
@@ -59,7 +244,8 @@ fn main() {
                     rw,
                     address,
                     value,
-                } if args.raw_mem_ap => {
+                    suspect,
+                } if args.raw_mem_ap && shown(address, value.size()) => {
                     match ts {
                         Some(ts) if args.ts => {
                             print!("{}-{}:", ts.start, ts.end);
@@ -67,16 +253,63 @@ fn main() {
                         _ => {}
                     }
                     let rw_arrow = rw.arrow();
-                    let value = value.as_() as u64;
-                    print!("{rw}:AP[{apsel}]:{address:#010x} {rw_arrow} {value:#010x}");
-                    match mem_ap_db.get_register(address as _) {
-                        Some(register_info) => {
-                            println!(" ({})", register_info.identifier())
+                    print!("{rw}:AP[{apsel}]:{address:#010x} {rw_arrow} {value}");
+                    if suspect {
+                        print!(" [sticky error pending]");
+                    }
+                    let registers: Vec<_> = mem_ap_db
+                        .ap(apsel)
+                        .get_registers(address, value.size())
+                        .map(|v| v.identifier())
+                        .collect();
+                    if !registers.is_empty() {
+                        println!(" ({})", registers.join(", "));
+                    } else {
+                        let endianness = current_state.aps[apsel as usize].endianness;
+                        match firmware
+                            .as_ref()
+                            .and_then(|v| v.annotate(address, value, endianness))
+                        {
+                            Some(annotation) => println!(" ({annotation})"),
+                            None => println!(),
                         }
-                        None => {
-                            println!()
+                    }
+                }
+                adi::Operation::PushedCompare {
+                    ts,
+                    apsel,
+                    mode,
+                    address,
+                    value,
+                    mask,
+                    suspect,
+                } if args.raw_mem_ap && shown(address, 4) => {
+                    match ts {
+                        Some(ts) if args.ts => {
+                            print!("{}-{}:", ts.start, ts.end);
                         }
+                        _ => {}
+                    }
+                    let prefix = match mode {
+                        adi::dp::TrnMode::PushedVerify => "PV",
+                        adi::dp::TrnMode::PushedCompare => "PC",
+                        _ => "P?",
+                    };
+                    print!("{prefix}:AP[{apsel}]:{address:#010x} ≟ {value:#010x}");
+                    if mask != u32::MAX {
+                        print!(" & {mask:#010x}");
+                    }
+                    if let Some(memory) = previous_state.aps[apsel as usize].word(address) {
+                        let outcome = match memory & mask == value & mask {
+                            true => "equal",
+                            false => "different",
+                        };
+                        print!(" (memory {memory:#010x}, {outcome})");
                     }
+                    if suspect {
+                        print!(" [sticky error pending]");
+                    }
+                    println!();
                 }
                 adi::Operation::DpRegisterAccess {
                     ts,
@@ -92,6 +325,7 @@ fn main() {
                     }
                     let rw_arrow = rw.arrow();
                     println!("{rw}:DP.{name} {rw_arrow} {value:#010x}");
+                    print_dp_register(&adi_db, &mut dp_values, name, value);
                 }
                 adi::Operation::ApRegisterAccess {
                     ts,
@@ -108,23 +342,82 @@ fn main() {
                     }
                     let rw_arrow = rw.arrow();
                     println!("{rw}:AP[{apsel}].{name} {rw_arrow} {value:#010x}");
+                    let csw_type = current_state.aps[apsel as usize]
+                        .idr
+                        .map_or_else(|| regdoctor_adios_ext::CswType::Generic, |v| v.csw_type());
+                    if let Some(register_info) = adi_db.ap(name, csw_type) {
+                        let previous = ap_values.insert((apsel, name), value);
+                        print_register_fields(
+                            &register_info,
+                            previous,
+                            value,
+                            &format!(" / AP[{apsel}]"),
+                        );
+                    }
+                }
+                adi::Operation::Failure {
+                    ts,
+                    apsel,
+                    rw,
+                    name,
+                    ack,
+                    retries,
+                    address,
+                } if address.is_none_or(|address| shown(address, 4)) => {
+                    let register = match apsel {
+                        Some(apsel) => format!("AP[{apsel}].{name}"),
+                        None => format!("DP.{name}"),
+                    };
+                    if args.failures {
+                        match ts {
+                            Some(ts) if args.ts => {
+                                print!("{}-{}:", ts.start, ts.end);
+                            }
+                            _ => {}
+                        }
+                        print!("F:{rw}:{register}");
+                        if let Some(address) = address {
+                            print!(" @ {address:#010x}");
+                        }
+                        match retries {
+                            Some(retries) => println!(" → {ack} ({retries} retries)"),
+                            None => println!(" → {ack}"),
+                        }
+                    }
+                    *failure_counts
+                        .entry((register, rw.to_string(), ack.to_string()))
+                        .or_insert(0) += 1;
                 }
                 adi::Operation::Landmark { message: metadata } => {
                     println!("!:{metadata}");
                 }
+                adi::Operation::Trace { ts, packet } => {
+                    match ts {
+                        Some(ts) if args.ts => {
+                            print!("{}-{}:", ts.start, ts.end);
+                        }
+                        _ => {}
+                    }
+                    println!("ITM:{packet}");
+                }
                 _ => {}
             }
         }
+        for event in rtt_events {
+            println!("{event}");
+        }
+        for call in semihosting_calls {
+            println!("{call}");
+        }
 
         if !args.mem_diffs {
             continue;
         }
 
         for (apsel, ap) in current_state.aps.iter().enumerate() {
-            let csw_type = ap.idr.map_or_else(
-                || regdoctor_adios_ext::CswType::Generic,
-                |v| v.type_().csw_type(),
-            );
+            let csw_type = ap
+                .idr
+                .map_or_else(|| regdoctor_adios_ext::CswType::Generic, |v| v.csw_type());
             match (previous_state.aps[apsel].csw, ap.csw) {
                 (None, Some(new_csw)) => {
                     let new_value = u32::from(new_csw);
@@ -151,40 +444,321 @@ fn main() {
                 (None, None) => {}
             }
             for (&address, &new_value) in ap.memory.iter() {
+                if !shown(address, 4) {
+                    continue;
+                }
                 match previous_state.aps[apsel].memory.get(&address) {
                     Some(&old_value) => {
                         if old_value != new_value {
-                            println!("U:AP[{apsel}]:{address:#010x} : {old_value:#010x} → {new_value:#010x}");
-                            match mem_ap_db.get_register(address as _) {
-                                Some(register_info) => {
-                                    let old = register_info.decode_value(old_value as _);
-                                    let new = register_info.decode_value(new_value as _);
-                                    let Some(diff) = Register::diff(&old, &new)
-                                        .expect("Different registers on the same address?")
-                                    else {
-                                        continue;
-                                    };
-                                    println!("{}", register_info.identifier());
-                                    println!("{diff}");
-                                }
-                                None => {}
+                            print!("U:AP[{apsel}]:{address:#010x} : {old_value:#010x} → {new_value:#010x}");
+                            print_annotation(
+                                firmware.as_ref(),
+                                mem_ap_db.ap(apsel as u8),
+                                ap,
+                                address,
+                                new_value,
+                            );
+                            for register in mem_ap_db.ap(apsel as u8).get_registers(address, 4) {
+                                let old = register.decode_value(old_value as _);
+                                let new = register.decode_value(new_value as _);
+                                let Some(diff) = Register::diff(&old, &new)
+                                    .expect("Different registers on the same address?")
+                                else {
+                                    continue;
+                                };
+                                println!("{}", register.identifier());
+                                println!("{diff}");
                             }
                         }
                     }
                     None => {
-                        println!("N:AP[{apsel}]:{address:#010x} : 0x???????? → {new_value:#010x}");
-                        match mem_ap_db.get_register(address as _) {
-                            Some(register_info) => {
-                                let value = register_info.decode_value(new_value as _);
-                                let diff_from_nothing = value.diff_from_nothing();
-                                println!("{}", register_info.identifier());
-                                println!("{diff_from_nothing}");
+                        print!("N:AP[{apsel}]:{address:#010x} : 0x???????? → {new_value:#010x}");
+                        print_annotation(
+                            firmware.as_ref(),
+                            mem_ap_db.ap(apsel as u8),
+                            ap,
+                            address,
+                            new_value,
+                        );
+                        for register in mem_ap_db.ap(apsel as u8).get_registers(address, 4) {
+                            let value = register.decode_value(new_value as _);
+                            if args.diff_from_reset {
+                                match value.diff_from_reset() {
+                                    Ok(Some(diff)) => {
+                                        println!("{}", register.identifier());
+                                        println!("{diff}");
+                                        continue;
+                                    }
+                                    Ok(None) => {
+                                        println!("{} (reset value)", register.identifier());
+                                        continue;
+                                    }
+                                    Err(NoResetValue) => {}
+                                }
                             }
-                            None => {}
-                        };
+                            let diff_from_nothing = value.diff_from_nothing();
+                            println!("{}", register.identifier());
+                            println!("{diff_from_nothing}");
+                        }
                     }
                 }
             }
         }
     }
+
+    if args.disasm {
+        for region in written_memory.regions() {
+            if region.bytes.len() < args.disasm_min_size {
+                continue;
+            }
+            let overlaps_register = mem_ap_db
+                .ap(region.apsel)
+                .get_registers(region.address, region.bytes.len() as _)
+                .next()
+                .is_some();
+            if overlaps_register {
+                continue;
+            }
+            print!(
+                "{}",
+                region.disassemble(firmware.as_ref().map(|v| &v.symbols))
+            );
+        }
+    }
+    if let Some(semihosting) = &mut semihosting {
+        for call in semihosting.finish(vm.state()) {
+            println!("{call}");
+        }
+    }
+    if let Some(rtt) = rtt {
+        for (channel, data) in rtt.streams() {
+            println!("RTT:{channel} ({} bytes)", data.len());
+            println!("{}", rtt::Payload(data));
+        }
+    }
+    if let Some(topology) = topology {
+        print!("{topology}");
+    }
+    if args.failure_summary {
+        for ((register, rw, ack), count) in failure_counts {
+            println!("F:{rw}:{register} → {ack}: {count}x");
+        }
+    }
+}
+
+impl MemoryModel for profile::AddressSpaces {
+    fn write(&self, apsel: u8, address: u64, size: u32, old: u32, written: u32) -> u32 {
+        self.ap(apsel)
+            .get_registers(address, size)
+            .fold(written as u64, |stored, register| {
+                register.apply_write(old as _, stored)
+            }) as _
+    }
+}
+
+/// Field diff against the previous access of the same DP register
+///
+/// Write-only registers (e.g. ABORT) act on every write, so they are shown in full each time.
+fn print_dp_register(
+    adi_db: &regdoctor_adios_ext::Database,
+    dp_values: &mut HashMap<&'static str, u32>,
+    name: &'static str,
+    value: u32,
+) {
+    let Some(register_info) = adi_db.dp(name) else {
+        return;
+    };
+    let previous = dp_values.insert(name, value);
+    print_register_fields(&register_info, previous, value, "");
+}
+
+/// Fields of a DP/AP register access, diffed against the `previous` access if there was one
+fn print_register_fields(
+    register_info: &RegisterInfo,
+    previous: Option<u32>,
+    value: u32,
+    suffix: &str,
+) {
+    let new = register_info.decode_value(value as _);
+    if new.fields().is_empty() {
+        return;
+    }
+    match previous {
+        Some(old_value) if register_info.access() != Some(Access::WriteOnly) => {
+            let old = register_info.decode_value(old_value as _);
+            if let Some(diff) =
+                Register::diff(&old, &new).expect("Different registers on the same address?")
+            {
+                println!("{}{suffix}", register_info.identifier());
+                println!("{diff}");
+            }
+        }
+        _ => {
+            println!("{}{suffix}", register_info.identifier());
+            println!("{}", new.diff_from_nothing());
+        }
+    }
+}
+
+fn shown_operation(operation: &adi::Operation, shown: impl Fn(u64, u32) -> bool) -> bool {
+    match operation {
+        adi::Operation::MemAp { address, value, .. } => shown(*address, value.size()),
+        adi::Operation::PushedCompare { address, .. } => shown(*address, 4),
+        adi::Operation::Failure { address, .. } => address.is_none_or(|v| shown(v, 4)),
+        _ => true,
+    }
+}
+
+fn print_anomalies(
+    mem_ap_db: &profile::AddressSpaces,
+    operation: &adi::Operation,
+    previous_state: &adi::VmState,
+    print_ts: bool,
+) {
+    let &adi::Operation::MemAp {
+        ts,
+        apsel,
+        address,
+        suspect,
+        ..
+    } = operation
+    else {
+        return;
+    };
+    let print_ts = || match ts {
+        Some(ts) if print_ts => {
+            print!("{}-{}:", ts.start, ts.end);
+        }
+        _ => {}
+    };
+    if suspect {
+        print_ts();
+        println!("A:AP[{apsel}]:{address:#010x} (access while a sticky error is pending)");
+    }
+    for (register, anomaly) in mem_ap_anomalies(mem_ap_db, operation, previous_state) {
+        print_ts();
+        println!("A:AP[{apsel}]:{address:#010x} {register} ({anomaly})");
+    }
+}
+
+/// Register identifiers and their anomalies caused by a MEM-AP access
+///
+/// Accesses wider than 64 bits are not checked.
+fn mem_ap_anomalies(
+    mem_ap_db: &profile::AddressSpaces,
+    operation: &adi::Operation,
+    previous_state: &adi::VmState,
+) -> Vec<(String, String)> {
+    let &adi::Operation::MemAp {
+        apsel,
+        rw,
+        address,
+        value,
+        ..
+    } = operation
+    else {
+        return Vec::new();
+    };
+    let kind = match rw {
+        adi::RoW::R => AccessKind::Read,
+        adi::RoW::W => AccessKind::Write,
+    };
+    let size = value.size();
+    let Some(value) = value.as_u64() else {
+        return Vec::new();
+    };
+    let old = previous_state.aps[apsel as usize].value(address, size);
+    let mut found = Vec::new();
+    for register in mem_ap_db.ap(apsel).get_registers(address, size) {
+        let anomalies = register
+            .anomalies(kind, old, value)
+            .into_iter()
+            .map(|v| v.to_string());
+        let violations = register
+            .decode_value(value)
+            .violations(kind)
+            .into_iter()
+            .map(|v| v.to_string());
+        found.extend(
+            anomalies
+                .chain(violations)
+                .map(|v| (register.identifier(), v)),
+        );
+    }
+    found
+}
+
+/// ELF annotation of a memory word, unless an SVD register describes it
+fn print_annotation(
+    firmware: Option<&Firmware>,
+    registers: &Database,
+    ap: &adi::Ap,
+    address: u64,
+    value: u32,
+) {
+    if registers.get_registers(address, 4).next().is_some() {
+        println!();
+        return;
+    }
+    let value = adi::MemApValue::Word(value);
+    match firmware.and_then(|v| v.annotate(address, value, ap.endianness)) {
+        Some(annotation) => println!(" ({annotation})"),
+        None => println!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubleword_anomalies() {
+        let mut spaces = profile::AddressSpaces::default();
+        spaces
+            .default
+            .extend_with_svd_str(
+                r#"
+<device>
+  <name>DEV</name>
+  <peripherals>
+    <peripheral>
+      <name>TIMER</name>
+      <baseAddress>0x40000000</baseAddress>
+      <registers>
+        <register><name>LO</name><addressOffset>0x0</addressOffset></register>
+        <register>
+          <name>HI</name>
+          <addressOffset>0x4</addressOffset>
+          <fields>
+            <field>
+              <name>VERSION</name><bitOffset>0</bitOffset><bitWidth>8</bitWidth>
+              <access>read-only</access>
+            </field>
+          </fields>
+        </register>
+      </registers>
+    </peripheral>
+  </peripherals>
+</device>
+"#,
+                &[],
+            )
+            .unwrap();
+        let mut state = adi::VmState::default();
+        state.aps[0].memory.insert(0x40000000, 0x0);
+        state.aps[0].memory.insert(0x40000004, 0x2);
+        let write = |value| adi::Operation::MemAp {
+            ts: None,
+            apsel: 0,
+            rw: adi::RoW::W,
+            address: 0x40000000,
+            value: adi::MemApValue::Doubleword(value),
+            suspect: false,
+        };
+        // VERSION written back as it is
+        assert_eq!(mem_ap_anomalies(&spaces, &write(0x2_0000_0001), &state), []);
+        let anomalies = mem_ap_anomalies(&spaces, &write(0x3_0000_0001), &state);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].0, "DEV.TIMER.HI");
+    }
 }
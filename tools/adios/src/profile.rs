@@ -0,0 +1,325 @@
+//! Target profiles, picking the register databases by what the traced target identifies as
+//!
+//! A registry is a YAML list of profiles, all keys but `name` are optional:
+//! ```yaml
+//! - name: STM32H745 (Cortex-M7 side)
+//!   # DPIDR.DESIGNER and DPIDR.PARTNO
+//!   dpidr: { designer: 0x23b, part: 0xba }
+//!   # TARGETID.TDESIGNER and TARGETID.TPARTNO
+//!   targetid: { designer: 0x020, part: 0x4500 }
+//!   # SVD files of the MEM-APs without their own `svd`, relative to the registry file
+//!   svd: [STM32H745_CM7.svd]
+//!   aps:
+//!     0: { idr: 0x84770001 }
+//!     # Debug APB-AP, nothing of the system memory map is there
+//!     2: { idr: 0x54770002, svd: [] }
+//! ```
+//!
+//! Designers are 11-bit JEP106 codes, continuation code in the top 4 bits (Arm is `0x23b`).
+//! AP IDR values are compared without the REVISION field.
+//!
+//! The first DPIDR, TARGETID and IDR values read in the trace identify the target. A profile
+//! matches if none of them contradicts it, the one agreeing with most of them is picked.
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use regdoctor::Database;
+use serde::Deserialize;
+
+use crate::adi::{Operation, RoW};
+
+/// IDR.REVISION, changes with every AP implementation revision
+const IDR_REVISION: u32 = 0xF000_0000;
+
+/// JEP106 designer and part number, as found in DPIDR and TARGETID
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct PartId {
+    pub designer: u16,
+    pub part: u16,
+}
+
+impl PartId {
+    fn from_dpidr(dpidr: u32) -> Self {
+        Self {
+            designer: (dpidr >> 1) as u16 & 0x7FF,
+            part: (dpidr >> 20) as u16 & 0xFF,
+        }
+    }
+
+    fn from_targetid(targetid: u32) -> Self {
+        Self {
+            designer: (targetid >> 1) as u16 & 0x7FF,
+            part: (targetid >> 12) as u16,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ApProfile {
+    pub idr: Option<u32>,
+    /// SVD files of the AP's own address space, `None` if it shares the profile's one
+    pub svd: Option<Vec<PathBuf>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub dpidr: Option<PartId>,
+    pub targetid: Option<PartId>,
+    #[serde(default)]
+    pub svd: Vec<PathBuf>,
+    #[serde(default)]
+    pub aps: BTreeMap<u8, ApProfile>,
+}
+
+impl Profile {
+    /// Number of the `identity` values agreeing with the profile, `None` if any contradicts it
+    pub fn score(&self, identity: &Identity) -> Option<usize> {
+        let dpidr = self
+            .dpidr
+            .zip(identity.dpidr)
+            .map(|(id, value)| id == PartId::from_dpidr(value));
+        let targetid = self
+            .targetid
+            .zip(identity.targetid)
+            .map(|(id, value)| id == PartId::from_targetid(value));
+        let idrs = self.aps.iter().map(|(apsel, ap)| {
+            ap.idr
+                .zip(identity.idrs.get(apsel))
+                .map(|(idr, value)| idr & !IDR_REVISION == value & !IDR_REVISION)
+        });
+        let mut score = 0;
+        for agrees in [dpidr, targetid].into_iter().chain(idrs).flatten() {
+            if !agrees {
+                return None;
+            }
+            score += 1;
+        }
+        Some(score)
+    }
+
+    /// Every SVD file the profile uses, in any address space
+    pub fn svds(&self) -> impl Iterator<Item = &Path> {
+        self.svd
+            .iter()
+            .chain(self.aps.values().flat_map(|v| v.svd.iter().flatten()))
+            .map(|v| v.as_path())
+    }
+
+    /// Whether the SVD file at `path` is one of the profile's
+    ///
+    /// Profile paths are relative to the registry file, so only the file names are compared.
+    pub fn uses(&self, path: &Path) -> bool {
+        self.svds().any(|v| v.file_name() == path.file_name())
+    }
+
+    /// Address spaces of the profile, `load` builds the database of a list of SVD files
+    pub fn address_spaces(&self, mut load: impl FnMut(&[PathBuf]) -> Database) -> AddressSpaces {
+        AddressSpaces {
+            default: load(&self.svd),
+            aps: self
+                .aps
+                .iter()
+                .filter_map(|(&apsel, ap)| Some((apsel, load(ap.svd.as_ref()?))))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    pub profiles: Vec<Profile>,
+}
+
+impl Registry {
+    /// Add the profiles of a registry file, its SVD paths are relative to `dir`
+    pub fn extend_with_yaml(&mut self, yaml: &str, dir: &Path) -> Result<(), serde_yaml::Error> {
+        let profiles: Vec<Profile> = serde_yaml::from_str(yaml)?;
+        self.profiles
+            .extend(profiles.into_iter().map(|mut profile| {
+                for svd in profile.svd.iter_mut().chain(
+                    profile
+                        .aps
+                        .values_mut()
+                        .flat_map(|v| v.svd.iter_mut().flatten()),
+                ) {
+                    *svd = dir.join(&*svd);
+                }
+                profile
+            }));
+        Ok(())
+    }
+
+    /// Profile agreeing with most of the `identity`, the first one listed on a tie
+    pub fn best_match(&self, identity: &Identity) -> Option<&Profile> {
+        let mut best: Option<(usize, &Profile)> = None;
+        for profile in self.profiles.iter() {
+            match profile.score(identity) {
+                Some(score) if score > best.map_or(0, |(score, _)| score) => {
+                    best = Some((score, profile))
+                }
+                _ => {}
+            }
+        }
+        best.map(|(_, profile)| profile)
+    }
+}
+
+/// What the target told about itself, the first value of each identification register
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub dpidr: Option<u32>,
+    pub targetid: Option<u32>,
+    pub idrs: BTreeMap<u8, u32>,
+}
+
+impl Identity {
+    pub fn record(&mut self, operation: &Operation) {
+        match *operation {
+            Operation::DpRegisterAccess {
+                rw: RoW::R,
+                name: "DPIDR",
+                value,
+                ..
+            } => {
+                self.dpidr.get_or_insert(value);
+            }
+            Operation::DpRegisterAccess {
+                rw: RoW::R,
+                name: "TARGETID",
+                value,
+                ..
+            } => {
+                self.targetid.get_or_insert(value);
+            }
+            Operation::ApRegisterAccess {
+                apsel,
+                rw: RoW::R,
+                name: "IDR",
+                value,
+                ..
+            } => {
+                self.idrs.entry(apsel).or_insert(value);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values: Vec<_> = self
+            .dpidr
+            .map(|v| format!("DPIDR {v:#010x}"))
+            .into_iter()
+            .chain(self.targetid.map(|v| format!("TARGETID {v:#010x}")))
+            .chain(
+                self.idrs
+                    .iter()
+                    .map(|(apsel, v)| format!("AP[{apsel}].IDR {v:#010x}")),
+            )
+            .collect();
+        match values.is_empty() {
+            true => f.write_str("no identification reads"),
+            false => f.write_str(&values.join(", ")),
+        }
+    }
+}
+
+/// Register databases of the MEM-AP address spaces
+pub struct AddressSpaces {
+    /// Shared by the APs without their own
+    pub default: Database,
+    pub aps: BTreeMap<u8, Database>,
+}
+
+impl Default for AddressSpaces {
+    fn default() -> Self {
+        Self {
+            default: Database::new(),
+            aps: BTreeMap::new(),
+        }
+    }
+}
+
+impl AddressSpaces {
+    pub fn ap(&self, apsel: u8) -> &Database {
+        self.aps.get(&apsel).unwrap_or(&self.default)
+    }
+
+    pub fn ap_mut(&mut self, apsel: u8) -> &mut Database {
+        self.aps.get_mut(&apsel).unwrap_or(&mut self.default)
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Database> {
+        std::iter::once(&self.default).chain(self.aps.values())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGISTRY: &str = "
+- name: nRF52840
+  dpidr: { designer: 0x23b, part: 0xba }
+  svd: [nrf52840.svd]
+  aps:
+    0: { idr: 0x24770011 }
+    1: { idr: 0x02880000, svd: [] }
+- name: RP2040 core 0
+  targetid: { designer: 0x493, part: 0x1002 }
+  svd: [rp2040.svd]
+";
+
+    fn registry() -> Registry {
+        let mut registry = Registry::default();
+        registry
+            .extend_with_yaml(REGISTRY, Path::new("profiles"))
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn registry_paths() {
+        let registry = registry();
+        let nrf = &registry.profiles[0];
+        assert_eq!(nrf.svd, [PathBuf::from("profiles/nrf52840.svd")]);
+        assert_eq!(nrf.aps[&1].svd, Some(Vec::new()));
+        assert_eq!(nrf.aps[&0].svd, None);
+        assert_eq!(
+            registry.profiles[1].targetid,
+            Some(PartId {
+                designer: 0x493,
+                part: 0x1002
+            })
+        );
+    }
+
+    #[test]
+    fn best_match() {
+        let registry = registry();
+        let nrf = Identity {
+            dpidr: Some(0x2BA0_1477),
+            targetid: None,
+            // Different IDR.REVISION
+            idrs: BTreeMap::from([(0, 0x1477_0011)]),
+        };
+        assert_eq!(registry.profiles[0].score(&nrf), Some(2));
+        assert_eq!(registry.profiles[1].score(&nrf), Some(0));
+        assert_eq!(registry.best_match(&nrf).unwrap().name, "nRF52840");
+
+        let rp2040 = Identity {
+            dpidr: Some(0x0BC1_2477),
+            targetid: Some(0x0100_2927),
+            idrs: BTreeMap::new(),
+        };
+        assert_eq!(registry.profiles[0].score(&rp2040), None);
+        assert_eq!(registry.best_match(&rp2040).unwrap().name, "RP2040 core 0");
+
+        assert!(registry.best_match(&Identity::default()).is_none());
+    }
+}
@@ -0,0 +1,366 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::adi::{Ap, Operation, RoW, VmState};
+
+const ID: &[u8; 12] = b"SEGGER RTT\0\0";
+/// `acID[16]`, `MaxNumUpBuffers`, `MaxNumDownBuffers`
+const HEADER_SIZE: u64 = 24;
+/// `sName`, `pBuffer`, `SizeOfBuffer`, `WrOff`, `RdOff`, `Flags`
+const BUFFER_DESC_SIZE: u64 = 24;
+const BUFFER_DESC_P_BUFFER: u64 = 4;
+const BUFFER_DESC_SIZE_OF_BUFFER: u64 = 8;
+const BUFFER_DESC_WR_OFF: u64 = 12;
+const BUFFER_DESC_RD_OFF: u64 = 16;
+
+/// SEGGER RTT analyzer
+///
+/// RTT is nothing more than MEM-AP accesses to ring buffers described by a control block
+/// living in the target RAM. Up (target → host) buffers are consumed by the host when it
+/// moves `RdOff`, down (host → target) buffers are filled when it moves `WrOff`.
+#[derive(Default)]
+pub struct Rtt {
+    control_block: Option<ControlBlock>,
+    streams: BTreeMap<Channel, Vec<u8>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct ControlBlock {
+    /// Learnt on the first access if the address came from outside of the trace
+    apsel: Option<u8>,
+    address: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Channel {
+    pub direction: Direction,
+    pub index: u32,
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.direction {
+            Direction::Up => write!(f, "up[{}]", self.index),
+            Direction::Down => write!(f, "down[{}]", self.index),
+        }
+    }
+}
+
+pub enum Event {
+    ControlBlockFound {
+        apsel: u8,
+        address: u64,
+    },
+    OffsetUpdate {
+        channel: Channel,
+        name: &'static str,
+        old: Option<u32>,
+        new: u32,
+    },
+    Transfer {
+        channel: Channel,
+        data: Vec<u8>,
+    },
+    /// Offsets moved but the buffer content was never seen on the wire
+    MissingData {
+        channel: Channel,
+        len: u32,
+    },
+}
+
+impl Rtt {
+    /// Control block address known upfront (e.g. `_SEGGER_RTT` symbol from the ELF)
+    pub fn with_control_block(address: u64) -> Self {
+        Self {
+            control_block: Some(ControlBlock {
+                apsel: None,
+                address,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Everything transferred so far, per channel
+    pub fn streams(&self) -> impl Iterator<Item = (&Channel, &Vec<u8>)> {
+        self.streams.iter()
+    }
+
+    pub fn step(
+        &mut self,
+        operations: &[Operation],
+        previous: &VmState,
+        current: &VmState,
+    ) -> Vec<Event> {
+        let mut events = Vec::new();
+        for operation in operations {
+            let &Operation::MemAp {
+                apsel, rw, address, ..
+            } = operation
+            else {
+                continue;
+            };
+            let ap = &current.aps[apsel as usize];
+            let control_block = match &mut self.control_block {
+                Some(control_block) => control_block,
+                None => {
+                    let Some(address) = Self::find_id(ap, address) else {
+                        continue;
+                    };
+                    events.push(Event::ControlBlockFound { apsel, address });
+                    self.control_block.insert(ControlBlock {
+                        apsel: Some(apsel),
+                        address,
+                    })
+                }
+            };
+            match control_block.apsel {
+                Some(cb_apsel) if cb_apsel != apsel => continue,
+                Some(_) => {}
+                None => {
+                    let end = control_block.address + HEADER_SIZE;
+                    if !(control_block.address..end).contains(&address) {
+                        continue;
+                    }
+                    control_block.apsel = Some(apsel);
+                    events.push(Event::ControlBlockFound {
+                        apsel,
+                        address: control_block.address,
+                    });
+                }
+            }
+            let control_block = *control_block;
+            let previous_ap = &previous.aps[apsel as usize];
+            self.buffer_desc_access(&mut events, control_block, previous_ap, ap, rw, address);
+        }
+        events
+    }
+
+    fn find_id(ap: &Ap, address: u64) -> Option<u64> {
+        let address = address & !0b11;
+        (0..ID.len() as u64)
+            .step_by(4)
+            .filter_map(|offset| address.checked_sub(offset))
+            .find(|&start| ap.bytes(start, ID.len()).as_deref() == Some(ID))
+    }
+
+    fn buffer_desc_access(
+        &mut self,
+        events: &mut Vec<Event>,
+        control_block: ControlBlock,
+        previous: &Ap,
+        current: &Ap,
+        rw: RoW,
+        address: u64,
+    ) {
+        let (Some(max_up), Some(max_down)) = (
+            current.word(control_block.address + 16),
+            current.word(control_block.address + 20),
+        ) else {
+            return;
+        };
+        let descs = control_block.address + HEADER_SIZE;
+        let Some(offset) = address.checked_sub(descs) else {
+            return;
+        };
+        let index = (offset / BUFFER_DESC_SIZE) as u32;
+        let field = offset % BUFFER_DESC_SIZE;
+        let channel = match index {
+            index if index < max_up => Channel {
+                direction: Direction::Up,
+                index,
+            },
+            index if index < max_up.saturating_add(max_down) => Channel {
+                direction: Direction::Down,
+                index: index - max_up,
+            },
+            _ => return,
+        };
+        let name = match field {
+            BUFFER_DESC_WR_OFF => "WrOff",
+            BUFFER_DESC_RD_OFF => "RdOff",
+            _ => return,
+        };
+        let old = previous.word(address);
+        let Some(new) = current.word(address) else {
+            return;
+        };
+        if old == Some(new) {
+            return;
+        }
+        events.push(Event::OffsetUpdate {
+            channel,
+            name,
+            old,
+            new,
+        });
+        // Only the host side moving its offset means the data has been transferred
+        let host_moved_offset = matches!(
+            (channel.direction, field, rw),
+            (Direction::Up, BUFFER_DESC_RD_OFF, RoW::W)
+                | (Direction::Down, BUFFER_DESC_WR_OFF, RoW::W)
+        );
+        let (true, Some(old)) = (host_moved_offset, old) else {
+            return;
+        };
+        let desc = address - field;
+        let (Some(buffer), Some(size)) = (
+            current.word(desc + BUFFER_DESC_P_BUFFER),
+            current.word(desc + BUFFER_DESC_SIZE_OF_BUFFER),
+        ) else {
+            return;
+        };
+        if old >= size || new >= size {
+            log::warn!(
+                "RTT {channel}: offsets {old:#x} → {new:#x} outside of the buffer ({size:#x})"
+            );
+            return;
+        }
+        let ranges = if new >= old {
+            [(old, new), (0, 0)]
+        } else {
+            [(old, size), (0, new)]
+        };
+        let data: Option<Vec<u8>> = ranges
+            .iter()
+            .map(|&(start, end)| {
+                current.bytes(buffer as u64 + start as u64, (end - start) as usize)
+            })
+            .try_fold(Vec::new(), |mut data, chunk| {
+                data.extend(chunk?);
+                Some(data)
+            });
+        match data {
+            Some(data) => {
+                self.streams
+                    .entry(channel)
+                    .or_default()
+                    .extend_from_slice(&data);
+                events.push(Event::Transfer { channel, data });
+            }
+            None => events.push(Event::MissingData {
+                channel,
+                len: ranges.iter().map(|(start, end)| end - start).sum(),
+            }),
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::ControlBlockFound { apsel, address } => {
+                write!(f, "RTT:AP[{apsel}]:{address:#010x} control block")
+            }
+            Event::OffsetUpdate {
+                channel,
+                name,
+                old: Some(old),
+                new,
+            } => write!(f, "RTT:{channel}.{name} : {old:#x} → {new:#x}"),
+            Event::OffsetUpdate {
+                channel,
+                name,
+                old: None,
+                new,
+            } => write!(f, "RTT:{channel}.{name} : 0x? → {new:#x}"),
+            Event::Transfer { channel, data } => {
+                let arrow = match channel.direction {
+                    Direction::Up => "→",
+                    Direction::Down => "←",
+                };
+                write!(f, "RTT:{channel} {arrow} {}", Payload(data))
+            }
+            Event::MissingData { channel, len } => {
+                write!(f, "RTT:{channel} {len} bytes transferred, content unknown")
+            }
+        }
+    }
+}
+
+/// Text if it looks like text, hex dump otherwise
+pub struct Payload<'a>(pub &'a [u8]);
+
+impl Display for Payload<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let is_text = self
+            .0
+            .iter()
+            .all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+        if is_text {
+            write!(f, "{:?}", String::from_utf8_lossy(self.0))
+        } else {
+            write!(f, "{:02x?}", self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adi::MemApValue;
+
+    const CB: u64 = 0x2000_0400;
+    const UP0: u64 = CB + HEADER_SIZE;
+    const BUFFER: u32 = 0x2000_0800;
+
+    fn write_bytes(state: &mut VmState, address: u64, bytes: &[u8]) {
+        for (i, chunk) in bytes.chunks(4).enumerate() {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            state.aps[0]
+                .memory
+                .insert(address + 4 * i as u64, u32::from_le_bytes(word));
+        }
+    }
+
+    fn access(state: &mut VmState, rw: RoW, address: u64, value: u32) -> Operation {
+        state.aps[0].memory.insert(address, value);
+        Operation::MemAp {
+            ts: None,
+            apsel: 0,
+            rw,
+            address,
+            value: MemApValue::Word(value),
+            suspect: false,
+        }
+    }
+
+    #[test]
+    fn up_channel_is_reassembled() {
+        let mut rtt = Rtt::default();
+        let mut state = VmState::default();
+        write_bytes(&mut state, CB, b"SEGGER RTT\0\0\0\0\0\0");
+        write_bytes(&mut state, CB + 16, &[1, 0, 0, 0, 1, 0, 0, 0]);
+        // sName, pBuffer, SizeOfBuffer, WrOff, RdOff, Flags
+        for (i, v) in [0u32, BUFFER, 8, 6, 6, 0].iter().enumerate() {
+            write_bytes(&mut state, UP0 + 4 * i as u64, &v.to_le_bytes());
+        }
+
+        let previous = state.clone();
+        let read = access(&mut state, RoW::R, CB, u32::from_le_bytes(*b"SEGG"));
+        let events = rtt.step(&[read], &previous, &state);
+        assert!(matches!(
+            events[..],
+            [Event::ControlBlockFound { address: CB, .. }]
+        ));
+
+        // Target wrote "Hi!\n" wrapping around the end of the buffer
+        let previous = state.clone();
+        let read = access(&mut state, RoW::R, UP0 + BUFFER_DESC_WR_OFF, 2);
+        let events = rtt.step(&[read], &previous, &state);
+        assert_eq!(events[0].to_string(), "RTT:up[0].WrOff : 0x6 → 0x2");
+        write_bytes(&mut state, BUFFER as u64, b"!\n\0\0\0\0Hi");
+
+        let previous = state.clone();
+        let write = access(&mut state, RoW::W, UP0 + BUFFER_DESC_RD_OFF, 2);
+        let events = rtt.step(&[write], &previous, &state);
+        assert_eq!(events[1].to_string(), r#"RTT:up[0] → "Hi!\n""#);
+        let streams: Vec<_> = rtt.streams().collect();
+        assert_eq!(streams[0].1, b"Hi!\n");
+    }
+}
@@ -0,0 +1,448 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    adi::{Ap, Operation, VmState},
+    cortex_m::{CoreRegisters, RegisterAccess, REGSEL_PC, REGSEL_R0, REGSEL_R1},
+    rtt::Payload,
+};
+
+/// `BKPT 0xAB`
+const BKPT_SEMIHOSTING: [u8; 2] = [0xAB, 0xBE];
+/// Strings longer than this are truncated
+const MAX_STRING_LEN: usize = 1024;
+
+/// Semihosting analyzer
+///
+/// A semihosting call is a halt on `BKPT 0xAB` with the operation number in R0 and
+/// a parameter (usually a pointer to a parameter block) in R1. The debugger reads
+/// both, fetches whatever the parameters point to, writes the result into R0 and
+/// resumes the core. The call is decoded on resume, once all of that is in the
+/// reconstructed memory.
+#[derive(Default)]
+pub struct Semihosting {
+    cores: BTreeMap<u8, Core>,
+}
+
+#[derive(Default)]
+struct Core {
+    registers: CoreRegisters,
+    pending: Option<PendingCall>,
+}
+
+#[derive(Default)]
+struct PendingCall {
+    pc: u32,
+    op: Option<u32>,
+    param: Option<u32>,
+    result: Option<u32>,
+}
+
+pub struct Call {
+    pub apsel: u8,
+    pub pc: u32,
+    pub op: Option<u32>,
+    pub param: Option<u32>,
+    pub result: Option<u32>,
+    pub decoded: Option<Decoded>,
+}
+
+pub enum Decoded {
+    Open { path: Option<String>, mode: u32 },
+    Handle { handle: u32 },
+    WriteC { c: Option<u8> },
+    Write0 { text: Option<Vec<u8>> },
+    Write { handle: u32, data: Option<Vec<u8>> },
+    Read { handle: u32, buffer: u32, len: u32 },
+    Seek { handle: u32, position: u32 },
+    Path { path: Option<String> },
+    Exit { reason: u32, subcode: Option<u32> },
+    NoParameters,
+}
+
+impl Semihosting {
+    pub fn step(
+        &mut self,
+        operations: &[Operation],
+        _previous: &VmState,
+        current: &VmState,
+    ) -> Vec<Call> {
+        let mut calls = Vec::new();
+        for operation in operations {
+            let &Operation::MemAp {
+                apsel,
+                rw,
+                address,
+                value,
+                ..
+            } = operation
+            else {
+                continue;
+            };
+            let ap = &current.aps[apsel as usize];
+            let core = self.cores.entry(apsel).or_default();
+            match core.registers.access(ap, rw, address, value.as_()) {
+                Some(RegisterAccess::Read { regsel, value }) => {
+                    if let Some(pending) = &mut core.pending {
+                        match regsel {
+                            REGSEL_R0 if pending.result.is_none() => pending.op = Some(value),
+                            REGSEL_R1 => pending.param = Some(value),
+                            _ => {}
+                        }
+                    }
+                }
+                Some(RegisterAccess::Write {
+                    regsel: REGSEL_R0,
+                    value,
+                }) => {
+                    if let Some(pending) = &mut core.pending {
+                        pending.result = Some(value);
+                    }
+                }
+                Some(RegisterAccess::Resume) => {
+                    if let Some(pending) = core.pending.take() {
+                        calls.push(Self::decode(apsel, ap, pending));
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if core.pending.is_some() {
+                continue;
+            }
+            // Either PC or the instruction it points to may be the last piece of the puzzle
+            let Some(pc) = core.registers.get(REGSEL_PC) else {
+                continue;
+            };
+            if ap.bytes(pc.into(), 2).as_deref() == Some(&BKPT_SEMIHOSTING) {
+                core.pending = Some(PendingCall {
+                    pc,
+                    op: core.registers.get(REGSEL_R0),
+                    param: core.registers.get(REGSEL_R1),
+                    result: None,
+                });
+            }
+        }
+        calls
+    }
+
+    /// Calls the trace ended in the middle of
+    pub fn finish(&mut self, current: &VmState) -> Vec<Call> {
+        let mut calls = Vec::new();
+        for (&apsel, core) in self.cores.iter_mut() {
+            if let Some(pending) = core.pending.take() {
+                calls.push(Self::decode(apsel, &current.aps[apsel as usize], pending));
+            }
+        }
+        calls
+    }
+
+    fn decode(apsel: u8, ap: &Ap, pending: PendingCall) -> Call {
+        let PendingCall {
+            pc,
+            op,
+            param,
+            result,
+        } = pending;
+        let decoded = match (op, param) {
+            (Some(op), Some(param)) => decode_op(ap, op, param),
+            _ => None,
+        };
+        Call {
+            apsel,
+            pc,
+            op,
+            param,
+            result,
+            decoded,
+        }
+    }
+}
+
+fn decode_op(ap: &Ap, op: u32, param: u32) -> Option<Decoded> {
+    let arg = |i: u32| ap.word(param.wrapping_add(4 * i).into());
+    let decoded = match op {
+        op::SYS_OPEN => Decoded::Open {
+            path: string(ap, arg(0)?, arg(2)),
+            mode: arg(1)?,
+        },
+        op::SYS_CLOSE | op::SYS_ISTTY | op::SYS_FLEN => Decoded::Handle { handle: arg(0)? },
+        op::SYS_WRITEC => Decoded::WriteC {
+            c: ap.bytes(param.into(), 1).map(|v| v[0]),
+        },
+        op::SYS_WRITE0 => Decoded::Write0 {
+            text: c_string(ap, param),
+        },
+        op::SYS_WRITE => Decoded::Write {
+            handle: arg(0)?,
+            data: ap.bytes(arg(1)?.into(), arg(2)? as usize),
+        },
+        op::SYS_READ => Decoded::Read {
+            handle: arg(0)?,
+            buffer: arg(1)?,
+            len: arg(2)?,
+        },
+        op::SYS_SEEK => Decoded::Seek {
+            handle: arg(0)?,
+            position: arg(1)?,
+        },
+        op::SYS_REMOVE | op::SYS_SYSTEM => Decoded::Path {
+            path: string(ap, arg(0)?, arg(1)),
+        },
+        // On 32-bit targets the reason is passed directly in R1
+        op::SYS_EXIT => Decoded::Exit {
+            reason: param,
+            subcode: None,
+        },
+        op::SYS_EXIT_EXTENDED => Decoded::Exit {
+            reason: arg(0)?,
+            subcode: arg(1),
+        },
+        op::SYS_READC
+        | op::SYS_CLOCK
+        | op::SYS_TIME
+        | op::SYS_ERRNO
+        | op::SYS_ELAPSED
+        | op::SYS_TICKFREQ => Decoded::NoParameters,
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+fn string(ap: &Ap, address: u32, len: Option<u32>) -> Option<String> {
+    let bytes = match len {
+        Some(len) => ap.bytes(address.into(), (len as usize).min(MAX_STRING_LEN))?,
+        None => c_string(ap, address)?,
+    };
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn c_string(ap: &Ap, address: u32) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for i in 0..MAX_STRING_LEN as u32 {
+        match ap.bytes(address.checked_add(i)?.into(), 1)?[0] {
+            0 => break,
+            byte => bytes.push(byte),
+        }
+    }
+    Some(bytes)
+}
+
+pub mod op {
+    pub const SYS_OPEN: u32 = 0x01;
+    pub const SYS_CLOSE: u32 = 0x02;
+    pub const SYS_WRITEC: u32 = 0x03;
+    pub const SYS_WRITE0: u32 = 0x04;
+    pub const SYS_WRITE: u32 = 0x05;
+    pub const SYS_READ: u32 = 0x06;
+    pub const SYS_READC: u32 = 0x07;
+    pub const SYS_ISERROR: u32 = 0x08;
+    pub const SYS_ISTTY: u32 = 0x09;
+    pub const SYS_SEEK: u32 = 0x0A;
+    pub const SYS_FLEN: u32 = 0x0C;
+    pub const SYS_TMPNAM: u32 = 0x0D;
+    pub const SYS_REMOVE: u32 = 0x0E;
+    pub const SYS_RENAME: u32 = 0x0F;
+    pub const SYS_CLOCK: u32 = 0x10;
+    pub const SYS_TIME: u32 = 0x11;
+    pub const SYS_SYSTEM: u32 = 0x12;
+    pub const SYS_ERRNO: u32 = 0x13;
+    pub const SYS_GET_CMDLINE: u32 = 0x15;
+    pub const SYS_HEAPINFO: u32 = 0x16;
+    pub const SYS_EXIT: u32 = 0x18;
+    pub const SYS_EXIT_EXTENDED: u32 = 0x20;
+    pub const SYS_ELAPSED: u32 = 0x30;
+    pub const SYS_TICKFREQ: u32 = 0x31;
+
+    pub fn name(op: u32) -> Option<&'static str> {
+        let name = match op {
+            SYS_OPEN => "SYS_OPEN",
+            SYS_CLOSE => "SYS_CLOSE",
+            SYS_WRITEC => "SYS_WRITEC",
+            SYS_WRITE0 => "SYS_WRITE0",
+            SYS_WRITE => "SYS_WRITE",
+            SYS_READ => "SYS_READ",
+            SYS_READC => "SYS_READC",
+            SYS_ISERROR => "SYS_ISERROR",
+            SYS_ISTTY => "SYS_ISTTY",
+            SYS_SEEK => "SYS_SEEK",
+            SYS_FLEN => "SYS_FLEN",
+            SYS_TMPNAM => "SYS_TMPNAM",
+            SYS_REMOVE => "SYS_REMOVE",
+            SYS_RENAME => "SYS_RENAME",
+            SYS_CLOCK => "SYS_CLOCK",
+            SYS_TIME => "SYS_TIME",
+            SYS_SYSTEM => "SYS_SYSTEM",
+            SYS_ERRNO => "SYS_ERRNO",
+            SYS_GET_CMDLINE => "SYS_GET_CMDLINE",
+            SYS_HEAPINFO => "SYS_HEAPINFO",
+            SYS_EXIT => "SYS_EXIT",
+            SYS_EXIT_EXTENDED => "SYS_EXIT_EXTENDED",
+            SYS_ELAPSED => "SYS_ELAPSED",
+            SYS_TICKFREQ => "SYS_TICKFREQ",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+fn exit_reason(reason: u32) -> Option<&'static str> {
+    let name = match reason {
+        0x20000 => "ADP_Stopped_BranchThroughZero",
+        0x20001 => "ADP_Stopped_UndefinedInstr",
+        0x20002 => "ADP_Stopped_SoftwareInterrupt",
+        0x20003 => "ADP_Stopped_PrefetchAbort",
+        0x20004 => "ADP_Stopped_DataAbort",
+        0x20005 => "ADP_Stopped_AddressException",
+        0x20006 => "ADP_Stopped_IRQ",
+        0x20007 => "ADP_Stopped_FIQ",
+        0x20020 => "ADP_Stopped_BreakPoint",
+        0x20021 => "ADP_Stopped_WatchPoint",
+        0x20022 => "ADP_Stopped_StepComplete",
+        0x20023 => "ADP_Stopped_RunTimeErrorUnknown",
+        0x20024 => "ADP_Stopped_InternalError",
+        0x20025 => "ADP_Stopped_UserInterruption",
+        0x20026 => "ADP_Stopped_ApplicationExit",
+        0x20027 => "ADP_Stopped_StackOverflow",
+        0x20028 => "ADP_Stopped_DivisionByZero",
+        0x20029 => "ADP_Stopped_OSSpecific",
+        _ => return None,
+    };
+    Some(name)
+}
+
+impl Display for Call {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SH:AP[{}]:{:#010x} ", self.apsel, self.pc)?;
+        let Some(op) = self.op else {
+            return write!(f, "BKPT 0xAB, operation unknown (R0 not read)");
+        };
+        match op::name(op) {
+            Some(name) => write!(f, "{name}")?,
+            None => write!(f, "SYS_{op:#04x}")?,
+        }
+        match &self.decoded {
+            Some(Decoded::Open { path, mode }) => {
+                write!(f, "({:?}, mode: {mode})", path.as_deref().unwrap_or("?"))?
+            }
+            Some(Decoded::Handle { handle }) => write!(f, "({handle})")?,
+            Some(Decoded::WriteC { c: Some(c) }) => write!(f, "({})", Payload(&[*c]))?,
+            Some(Decoded::Write0 { text: Some(text) }) => write!(f, "({})", Payload(text))?,
+            Some(Decoded::Write {
+                handle,
+                data: Some(data),
+            }) => write!(f, "({handle}, {})", Payload(data))?,
+            Some(Decoded::WriteC { c: None })
+            | Some(Decoded::Write0 { text: None })
+            | Some(Decoded::Write { data: None, .. }) => write!(f, "(<unknown payload>)")?,
+            Some(Decoded::Read {
+                handle,
+                buffer,
+                len,
+            }) => write!(f, "({handle}, {buffer:#010x}, {len})")?,
+            Some(Decoded::Seek { handle, position }) => write!(f, "({handle}, {position})")?,
+            Some(Decoded::Path { path }) => write!(f, "({:?})", path.as_deref().unwrap_or("?"))?,
+            Some(Decoded::Exit { reason, subcode }) => {
+                match exit_reason(*reason) {
+                    Some(name) => write!(f, "({name}")?,
+                    None => write!(f, "({reason:#x}")?,
+                }
+                match subcode {
+                    Some(subcode) => write!(f, ", {subcode})")?,
+                    None => write!(f, ")")?,
+                }
+            }
+            Some(Decoded::NoParameters) => write!(f, "()")?,
+            None => match self.param {
+                Some(param) => write!(f, "(R1: {param:#010x})")?,
+                None => write!(f, "(R1 unknown)")?,
+            },
+        }
+        if let Some(result) = self.result {
+            write!(f, " = {}", result as i32)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        adi::{MemApValue, RoW},
+        cortex_m::{DCRDR, DCRSR, DHCSR},
+    };
+
+    struct Trace {
+        semihosting: Semihosting,
+        state: VmState,
+        calls: Vec<Call>,
+    }
+
+    impl Trace {
+        fn access(&mut self, rw: RoW, address: u64, value: u32) {
+            let previous = self.state.clone();
+            self.state.aps[0].memory.insert(address, value);
+            let operation = Operation::MemAp {
+                ts: None,
+                apsel: 0,
+                rw,
+                address,
+                value: MemApValue::Word(value),
+                suspect: false,
+            };
+            let calls = self.semihosting.step(&[operation], &previous, &self.state);
+            self.calls.extend(calls);
+        }
+
+        fn read_register(&mut self, regsel: u32, value: u32) {
+            self.access(RoW::W, DCRSR, regsel);
+            self.access(RoW::R, DCRDR, value);
+        }
+    }
+
+    #[test]
+    fn sys_write0() {
+        let mut trace = Trace {
+            semihosting: Semihosting::default(),
+            state: VmState::default(),
+            calls: Vec::new(),
+        };
+        trace.read_register(15, 0x0800_0102);
+        trace.access(RoW::R, 0x0800_0100, 0xBEAB_2004);
+        trace.read_register(0, op::SYS_WRITE0);
+        trace.read_register(1, 0x2000_0010);
+        trace.access(RoW::R, 0x2000_0010, u32::from_le_bytes(*b"Hell"));
+        trace.access(RoW::R, 0x2000_0014, u32::from_le_bytes(*b"o!\n\0"));
+        assert!(trace.calls.is_empty());
+        trace.access(RoW::W, DHCSR, 0xA05F_0001);
+        assert_eq!(trace.calls.len(), 1);
+        assert_eq!(
+            trace.calls[0].to_string(),
+            r#"SH:AP[0]:0x08000102 SYS_WRITE0("Hello!\n")"#
+        );
+    }
+
+    #[test]
+    fn sys_write_with_result() {
+        let mut trace = Trace {
+            semihosting: Semihosting::default(),
+            state: VmState::default(),
+            calls: Vec::new(),
+        };
+        trace.access(RoW::R, 0x0800_0200, 0x0000_BEAB);
+        trace.read_register(0, op::SYS_WRITE);
+        trace.read_register(1, 0x2000_0100);
+        trace.read_register(15, 0x0800_0200);
+        trace.access(RoW::R, 0x2000_0100, 1);
+        trace.access(RoW::R, 0x2000_0104, 0x2000_0200);
+        trace.access(RoW::R, 0x2000_0108, 2);
+        trace.access(RoW::R, 0x2000_0200, u32::from_le_bytes(*b"ok\0\0"));
+        trace.access(RoW::W, DCRDR, 0);
+        trace.access(RoW::W, DCRSR, 0x0001_0000);
+        trace.access(RoW::W, DHCSR, 0xA05F_0001);
+        assert_eq!(
+            trace.calls[0].to_string(),
+            r#"SH:AP[0]:0x08000200 SYS_WRITE(1, "ok") = 0"#
+        );
+    }
+}